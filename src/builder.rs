@@ -0,0 +1,284 @@
+//! The VttBuilder creates a VTT from scratch instead of from a vtt file, e.g. for maps generated by
+//! a dungeon generator. The result can be written to a file with `save_vtt`.
+#[cfg(feature = "image")]
+use base64::{prelude::BASE64_STANDARD, Engine as _};
+
+use crate::{
+    errors::RustVttError,
+    vtt::{Coordinate, Environment, Portal, Resolution, VTTPartial, VTT},
+};
+#[cfg(feature = "image")]
+use crate::{pixbuf::Pixbuf, png::encode_png};
+
+/// The version of the vtt format written by the builder
+const FORMAT: f32 = 0.3;
+
+/// A light that is added to the vtt when it is built
+struct LightParameters {
+    position: Coordinate,
+    range: f64,
+    intensity: f64,
+    color: String,
+    shadows: bool,
+}
+
+/// Builds a VTT from scratch.
+///
+/// # Example
+/// ```
+/// use vtt_rust::{Coordinate, VttBuilder};
+///
+/// let vtt = VttBuilder::new(Coordinate { x: 0.0, y: 0.0 }, Coordinate { x: 4.0, y: 3.0 }, 16)
+///     .add_wall(vec![Coordinate { x: 1.0, y: 1.0 }, Coordinate { x: 3.0, y: 1.0 }])
+///     .build()
+///     .unwrap();
+/// assert_eq!(vtt.pixels_per_grid(), 16);
+/// ```
+pub struct VttBuilder {
+    resolution: Resolution,
+    line_of_sight: Vec<Vec<Coordinate>>,
+    objects_line_of_sight: Vec<Vec<Coordinate>>,
+    portals: Vec<Portal>,
+    lights: Vec<LightParameters>,
+    #[cfg(feature = "image")]
+    image: Option<Pixbuf>,
+}
+
+impl VttBuilder {
+    /// Start building a map with the given origin and size in squares, where every square is
+    /// `pixels_per_grid` pixels wide and high.
+    pub fn new(origin: Coordinate, size: Coordinate, pixels_per_grid: i32) -> Self {
+        return Self {
+            resolution: Resolution::new(origin, size, pixels_per_grid),
+            line_of_sight: Vec::new(),
+            objects_line_of_sight: Vec::new(),
+            portals: Vec::new(),
+            lights: Vec::new(),
+            #[cfg(feature = "image")]
+            image: None,
+        };
+    }
+
+    /// Change the origin and size in squares and the pixels per square of the map
+    pub fn resolution(
+        mut self,
+        origin: Coordinate,
+        size: Coordinate,
+        pixels_per_grid: i32,
+    ) -> Self {
+        self.resolution = Resolution::new(origin, size, pixels_per_grid);
+        return self;
+    }
+
+    /// Add a wall that runs through the given points in grid coordinates
+    pub fn add_wall(mut self, wall: Vec<Coordinate>) -> Self {
+        self.line_of_sight.push(wall);
+        return self;
+    }
+
+    /// Add an object that blocks line of sight, with an outline through the given points in grid
+    /// coordinates
+    pub fn add_object(mut self, object: Vec<Coordinate>) -> Self {
+        self.objects_line_of_sight.push(object);
+        return self;
+    }
+
+    /// Add a door between two points in grid coordinates. A freestanding portal is not part of a
+    /// wall, such as a window.
+    pub fn add_portal(
+        mut self,
+        start: Coordinate,
+        end: Coordinate,
+        closed: bool,
+        freestanding: bool,
+    ) -> Self {
+        self.portals
+            .push(Portal::new(start, end, closed, freestanding));
+        return self;
+    }
+
+    /// Add a light source, see [`VTT::add_light`][crate::vtt::VTT::add_light()]. The color is
+    /// validated when the vtt is built.
+    pub fn add_light(
+        mut self,
+        position: Coordinate,
+        range: f64,
+        intensity: f64,
+        color: String,
+        shadows: bool,
+    ) -> Self {
+        self.lights.push(LightParameters {
+            position,
+            range,
+            intensity,
+            color,
+            shadows,
+        });
+        return self;
+    }
+
+    /// Set the image of the map, which is stored as a PNG. When no image is set, a black image of
+    /// the size of the map is used. The image must be the size of the map in pixels, see
+    /// [`build`][VttBuilder::build()].
+    #[cfg(feature = "image")]
+    pub fn image_from_pixbuf(mut self, image: Pixbuf) -> Self {
+        self.image = Some(image);
+        return self;
+    }
+
+    /// Build the vtt. Returns an error if the origin is negative, the size is not a positive number
+    /// of squares, the pixels per grid is not positive, the image is not the size of the map in
+    /// pixels or a light has an invalid color. Without the `image` feature the map has no image.
+    pub fn build(self) -> Result<VTT, RustVttError> {
+        // validate before allocating the default image of the size of the map
+        self.resolution.validate()?;
+        #[cfg(feature = "image")]
+        let image = {
+            if let Some(image) = &self.image {
+                self.resolution
+                    .check_image_size(image.width(), image.height())?;
+            }
+            let image = self.image.unwrap_or_else(|| {
+                let size = self.resolution.pixel_size();
+                Pixbuf::new(size.x as u32, size.y as u32)
+            });
+            BASE64_STANDARD.encode(encode_png(&image))
+        };
+        #[cfg(not(feature = "image"))]
+        let image = String::new();
+        let mut vtt = VTTPartial {
+            format: FORMAT,
+            resolution: self.resolution,
+            line_of_sight: self.line_of_sight,
+            objects_line_of_sight: self.objects_line_of_sight,
+            portals: self.portals,
+            environment: Environment::default(),
+            lights: Vec::new(),
+            image,
+            fog_of_war: None,
+        }
+        .try_to_vtt()?;
+        for light in self.lights {
+            vtt.add_light(
+                light.position,
+                light.range,
+                light.intensity,
+                light.color,
+                light.shadows,
+            )?;
+        }
+        return Ok(vtt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::VttBuilder;
+    use crate::errors::RustVttError;
+    #[cfg(feature = "image")]
+    use crate::open_vtt;
+    #[cfg(feature = "image")]
+    use crate::pixbuf::{Pixbuf, Rgb};
+    #[cfg(feature = "image")]
+    use crate::tests::temp_path;
+    use crate::vtt::Coordinate;
+    #[cfg(feature = "image")]
+    use geo::Area;
+
+    fn coordinate(x: f64, y: f64) -> Coordinate {
+        return Coordinate { x, y };
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_build() {
+        let mut image = Pixbuf::new(64, 48);
+        image.put_pixel(1, 2, Rgb([10, 20, 30]));
+        let vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(4.0, 3.0), 16)
+            .add_wall(vec![
+                coordinate(1.0, 1.0),
+                coordinate(3.0, 1.0),
+                coordinate(3.0, 2.0),
+                coordinate(1.0, 2.0),
+                coordinate(1.0, 1.5),
+            ])
+            .add_portal(coordinate(1.0, 1.5), coordinate(1.0, 1.0), true, false)
+            .add_light(coordinate(2.0, 1.5), 2.0, 1.0, "ffffffff".to_string(), true)
+            .image_from_pixbuf(image.clone())
+            .build()
+            .expect("Could not build the vtt");
+        let rooms = vtt.rooms();
+        assert_eq!(rooms.len(), 1, "Expected the wall and door to form a room");
+        assert!((rooms[0].unsigned_area() - 2.0).abs() < 1e-9);
+
+        let path = temp_path("builder.dd2vtt");
+        vtt.save_vtt(&path).expect("Could not save the vtt");
+        let reopened = open_vtt(&path).expect("Could not open the saved vtt");
+        assert_eq!(reopened.lights().len(), 1);
+        assert_eq!(reopened.portal_segments().len(), 1);
+        assert_eq!(
+            reopened
+                .get_pixbuf_without_fow()
+                .expect("Could not decode the image")
+                .get_pixel(1, 2),
+            Rgb([10, 20, 30]),
+            "Expected the image to be stored in the vtt"
+        );
+
+        let wrong_size = VttBuilder::new(coordinate(0.0, 0.0), coordinate(4.0, 3.0), 16)
+            .image_from_pixbuf(Pixbuf::new(10, 10))
+            .build();
+        assert!(matches!(
+            wrong_size,
+            Err(RustVttError::ImageSizeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_build_invalid() {
+        let invalid_size = VttBuilder::new(coordinate(0.0, 0.0), coordinate(-4.0, 3.0), 16).build();
+        assert!(matches!(
+            invalid_size,
+            Err(RustVttError::InvalidMapSize { .. })
+        ));
+        let invalid_origin =
+            VttBuilder::new(coordinate(-1.0, 0.0), coordinate(4.0, 3.0), 16).build();
+        assert!(matches!(
+            invalid_origin,
+            Err(RustVttError::InvalidOrigin { .. })
+        ));
+        let invalid_ppg = VttBuilder::new(coordinate(0.0, 0.0), coordinate(4.0, 3.0), 0).build();
+        assert!(matches!(
+            invalid_ppg,
+            Err(RustVttError::InvalidPixelsPerGrid { .. })
+        ));
+        let invalid_light = VttBuilder::new(coordinate(0.0, 0.0), coordinate(4.0, 3.0), 16)
+            .add_light(coordinate(1.0, 1.0), 1.0, 1.0, "torch".to_string(), false)
+            .build();
+        assert!(matches!(
+            invalid_light,
+            Err(RustVttError::InvalidColor { .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(not(feature = "image"))]
+    fn test_build_without_image() {
+        let mut vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(4.0, 3.0), 16)
+            .add_wall(vec![coordinate(2.0, 0.0), coordinate(2.0, 3.0)])
+            .build()
+            .expect("Could not build the vtt");
+        assert!(
+            vtt.image_bytes().expect("Expected valid base64").is_empty(),
+            "Expected no image without the image feature"
+        );
+        vtt.fow_hide_all();
+        vtt.fow_show(coordinate(1.0, 1.5), false)
+            .expect("Could not reveal the fog of war");
+        assert!(vtt.is_visible(coordinate(1.0, 1.0)));
+        assert!(
+            !vtt.is_visible(coordinate(3.0, 1.0)),
+            "Expected the wall to block the line of sight"
+        );
+    }
+}
@@ -1,9 +1,72 @@
+use std::str::Utf8Error;
+
 use thiserror::{self, Error};
 
-use crate::vtt::Coordinate;
+use crate::vtt::{Coordinate, PixelCoordinate};
 
 #[derive(Error, Debug)]
 pub enum RustVttError {
     #[error("Coordinate (x,y): ({}, {}) does not lie inside the vtt image", coordinate.x, coordinate.y)]
     OutOfBounds { coordinate: Coordinate },
+    #[error("The image could not be decoded: {reason}")]
+    ImageDecode { reason: String },
+    #[error("{color:?} is not a valid hex color")]
+    InvalidColor { color: String },
+    #[error("Light {index} has color {color:?}, which is not a valid hex color")]
+    InvalidLightColor { index: usize, color: String },
+    #[error("The fog of war data is invalid")]
+    InvalidFogOfWar,
+    #[error("The fog of war was made for a {}x{} image, but the image is {}x{}", found.x, found.y, expected.x, expected.y)]
+    FogOfWarMismatch {
+        expected: PixelCoordinate,
+        found: PixelCoordinate,
+    },
+    #[error("The image is {}x{} pixels, but the map is {}x{} pixels", found.x, found.y, expected.x, expected.y)]
+    ImageSizeMismatch {
+        expected: PixelCoordinate,
+        found: PixelCoordinate,
+    },
+    #[error("The appended map has {found} pixels per grid, but this map has {expected}")]
+    PixelsPerGridMismatch { expected: i32, found: i32 },
+    #[error("The edge of the appended map is {found} squares long, but the edge it is appended to is {expected} squares long")]
+    EdgeMismatch { expected: f64, found: f64 },
+    #[error("The line of sight resolution must be a positive number, found {step_size}")]
+    InvalidLosResolution { step_size: f64 },
+    #[error("The field of view must be a positive number of degrees facing a finite direction, found {fov_deg} degrees facing {facing_deg} degrees")]
+    InvalidFieldOfView { facing_deg: f64, fov_deg: f64 },
+    #[error("The sight range must be a positive number of squares, found {max_range}")]
+    InvalidSightRange { max_range: f64 },
+    #[error("The map origin ({}, {}) must not be negative", origin.x, origin.y)]
+    InvalidOrigin { origin: Coordinate },
+    #[error("The map size ({}, {}) must be a positive number of squares", size.x, size.y)]
+    InvalidMapSize { size: Coordinate },
+    #[error("The pixels per grid must be positive, found {pixels_per_grid}")]
+    InvalidPixelsPerGrid { pixels_per_grid: i32 },
+    #[error("The pixel dimensions {}x{} must be positive", dimensions.x, dimensions.y)]
+    InvalidPixelDimensions { dimensions: PixelCoordinate },
+    #[error("The vtt format version {format} is newer than the supported version {supported}")]
+    UnsupportedFormat { format: f32, supported: f32 },
+    #[error(
+        "The minimum fog of war rectangle size must be at least 1 pixel, found {min_square_size}"
+    )]
+    InvalidMinSquareSize { min_square_size: i32 },
+    #[error("The gzip data could not be decompressed: {reason}")]
+    InvalidGzip { reason: String },
+    #[error("The vtt file could not be read: {source}")]
+    Io {
+        #[from]
+        source: std::io::Error,
+    },
+    #[error("The vtt data is not valid json: {source}")]
+    InvalidJson {
+        #[from]
+        source: serde_json::Error,
+    },
+    #[error("The vtt data is not valid UTF-8")]
+    InvalidUtf8 {
+        #[source]
+        source: Utf8Error,
+    },
+    #[error("There is no fog of war layer named {name:?}")]
+    UnknownFowLayer { name: String },
 }
@@ -1,49 +1,1811 @@
 //! The FogOfWar is quadtree that efficiently stores information on which pixels in the image are
 //! covered by fog of war. This struct is used in the VTT struct and should generally only be accessed
 //! via the VTT struct.
+use base64::{prelude::BASE64_STANDARD, Engine as _};
+use geo::{BooleanOps, Contains, Coord, Intersects, Line, MultiPolygon, Point, Polygon, Rect};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    errors::RustVttError,
+    vtt::{PixelCoordinate, Resolution},
+};
+
+/// The default smallest width or height in pixels of a rectangle in the quadtree. Rectangles of
+/// this size are not split any further.
+const MIN_SQUARE_SIZE: i32 = 3;
+/// The lowest allowed minimum rectangle size, rectangles of a single pixel can not be split
+const MIN_SQUARE_SIZE_FLOOR: i32 = 1;
+/// Identifies serialized fog of war data
+const MAGIC: &[u8; 4] = b"VTTF";
+const VERSION: u8 = 2;
+/// The first version, which did not store remembered leaves
+const VERSION_1: u8 = 1;
+
+/// Whether an update of the fog of war should hide or reveal an area
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Hide,
+    Show,
+}
+
+/// The state of a leaf of the fog of war quadtree. The states are ordered from most to least
+/// visible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum FowState {
+    /// Revealed and seen right now
+    #[default]
+    Shown,
+    /// Revealed before, but not seen right now, see [`FogOfWar::remember_shown`]
+    Remembered,
+    /// Hidden by fog of war
+    Hidden,
+}
+
+impl Operation {
+    /// Returns the state that this operation gives a leaf
+    fn state(self) -> FowState {
+        if self == Operation::Hide {
+            return FowState::Hidden;
+        }
+        return FowState::Shown;
+    }
+}
+
+/// A rectangle of pixels on the image. The top left pixel is part of the rectangle, the bottom
+/// right pixel is the first pixel outside of the rectangle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct FoWRectangle {
+    pub topleft: PixelCoordinate,
+    pub bottomright: PixelCoordinate,
+}
+
+impl FoWRectangle {
+    /// Returns the width of the rectangle in pixels
+    pub fn width(&self) -> i32 {
+        return self.bottomright.x - self.topleft.x;
+    }
+
+    /// Returns the height of the rectangle in pixels
+    pub fn height(&self) -> i32 {
+        return self.bottomright.y - self.topleft.y;
+    }
+
+    /// Returns the pixel of this rectangle that lies closest to the given pixel
+    pub fn closest_pixel(&self, pixel: PixelCoordinate) -> PixelCoordinate {
+        return PixelCoordinate {
+            x: pixel.x.clamp(self.topleft.x, self.bottomright.x - 1),
+            y: pixel.y.clamp(self.topleft.y, self.bottomright.y - 1),
+        };
+    }
+
+    /// Helper function: returns the squared distance in pixels between the pixel and the closest
+    /// pixel of this rectangle, which is 0 if the rectangle contains the pixel
+    fn squared_distance_to(&self, pixel: PixelCoordinate) -> i64 {
+        let closest = self.closest_pixel(pixel);
+        let (dx, dy) = ((closest.x - pixel.x) as i64, (closest.y - pixel.y) as i64);
+        return dx * dx + dy * dy;
+    }
+
+    /// Returns this rectangle as a geo rectangle
+    pub fn as_rect(&self) -> Rect {
+        return Rect::new(
+            Coord {
+                x: self.topleft.x as f64,
+                y: self.topleft.y as f64,
+            },
+            Coord {
+                x: self.bottomright.x as f64,
+                y: self.bottomright.y as f64,
+            },
+        );
+    }
+
+    /// Returns whether the given pixel lies inside of this rectangle. The top left edges are part
+    /// of the rectangle, the bottom right edges are not.
+    pub fn contains(&self, point: PixelCoordinate) -> bool {
+        return self.topleft.x <= point.x
+            && point.x < self.bottomright.x
+            && self.topleft.y <= point.y
+            && point.y < self.bottomright.y;
+    }
+
+    /// Returns the part of this rectangle that also lies inside the other rectangle, or None if they
+    /// do not overlap
+    fn intersection(&self, other: &FoWRectangle) -> Option<FoWRectangle> {
+        let intersection = FoWRectangle {
+            topleft: PixelCoordinate {
+                x: self.topleft.x.max(other.topleft.x),
+                y: self.topleft.y.max(other.topleft.y),
+            },
+            bottomright: PixelCoordinate {
+                x: self.bottomright.x.min(other.bottomright.x),
+                y: self.bottomright.y.min(other.bottomright.y),
+            },
+        };
+        if intersection.width() <= 0 || intersection.height() <= 0 {
+            return None;
+        }
+        return Some(intersection);
+    }
+
+    /// Split this rectangle in four: top left, top right, bottom left and bottom right
+    fn split(&self) -> [FoWRectangle; 4] {
+        let middle = PixelCoordinate {
+            x: self.topleft.x + self.width() / 2,
+            y: self.topleft.y + self.height() / 2,
+        };
+        let rectangle = |topleft: (i32, i32), bottomright: (i32, i32)| FoWRectangle {
+            topleft: PixelCoordinate {
+                x: topleft.0,
+                y: topleft.1,
+            },
+            bottomright: PixelCoordinate {
+                x: bottomright.0,
+                y: bottomright.1,
+            },
+        };
+        let (left, top) = (self.topleft.x, self.topleft.y);
+        let (right, bottom) = (self.bottomright.x, self.bottomright.y);
+        return [
+            rectangle((left, top), (middle.x, middle.y)),
+            rectangle((middle.x, top), (right, middle.y)),
+            rectangle((left, middle.y), (middle.x, bottom)),
+            rectangle((middle.x, middle.y), (right, bottom)),
+        ];
+    }
+}
+
+/// The rectangles of the image that changed between two states of a fog of war, see
+/// [`FogOfWar::take_changes`]
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct FowDelta {
+    /// The rectangles that are now hidden
+    pub hidden: Vec<FoWRectangle>,
+    /// The rectangles that are now revealed and seen
+    pub shown: Vec<FoWRectangle>,
+    /// The rectangles that are now revealed but not seen right now
+    #[serde(default)]
+    pub remembered: Vec<FoWRectangle>,
+}
+
+impl FowDelta {
+    /// Returns true if nothing changed
+    pub fn is_empty(&self) -> bool {
+        return self.hidden.is_empty() && self.shown.is_empty() && self.remembered.is_empty();
+    }
+}
+
 /// A quadtree representing fog of war.
+#[derive(Default)]
 pub struct FogOfWar {
-    hidden: bool,
+    bounds: FoWRectangle,
+    state: FowState,
     child1: Option<Box<FogOfWar>>,
     child2: Option<Box<FogOfWar>>,
     child3: Option<Box<FogOfWar>>,
     child4: Option<Box<FogOfWar>>,
+    /// The state of the quadtree at the last call to `take_changes`, only used on the root
+    last_taken: Option<Box<FogOfWar>>,
+    /// The smallest width or height of a rectangle that is split by updates, only used on the root
+    min_square_size: i32,
+}
+
+/// Two fogs of war are equal when they cover an image of the same size and hide and remember
+/// exactly the same pixels, even if their quadtrees are split differently. The minimum rectangle
+/// size and the changes since [`take_changes`][FogOfWar::take_changes()] are not compared.
+impl PartialEq for FogOfWar {
+    fn eq(&self, other: &Self) -> bool {
+        if self.bounds != other.bounds {
+            return false;
+        }
+        // the hidden and remembered areas of this fog of war lie inside the same areas of the
+        // other and are just as large
+        let area = |rectangle: &FoWRectangle| rectangle.width() as i64 * rectangle.height() as i64;
+        return [FowState::Hidden, FowState::Remembered]
+            .into_iter()
+            .all(|state| {
+                let rectangles = self.collect_leaves(state);
+                return rectangles
+                    .iter()
+                    .all(|rectangle| other.area_in(rectangle, state) == area(rectangle))
+                    && rectangles.iter().map(area).sum::<i64>()
+                        == other.area_in(&other.bounds, state);
+            });
+    }
 }
 
 impl FogOfWar {
+    /// Create a fog of war covering the image of a vtt with the given resolution, where everything
+    /// is revealed
+    pub fn new(resolution: &Resolution) -> Self {
+        let size = resolution.pixel_size();
+        return Self::with_bounds(FoWRectangle {
+            topleft: PixelCoordinate { x: 0, y: 0 },
+            bottomright: size,
+        });
+    }
+
+    /// Rebuild the quadtree to cover the image of a vtt with the given resolution, e.g. after the
+    /// resolution of the vtt changed. The fog of war state is reset, so everything is revealed.
+    pub fn resize(&mut self, resolution: &Resolution) -> &mut Self {
+        let min_square_size = self.min_square_size;
+        *self = FogOfWar::new(resolution);
+        self.min_square_size = min_square_size;
+        return self;
+    }
+
+    fn with_bounds(bounds: FoWRectangle) -> Self {
+        return Self {
+            bounds,
+            min_square_size: MIN_SQUARE_SIZE,
+            ..Default::default()
+        };
+    }
+
+    /// Use the given smallest width or height in pixels of a rectangle that updates split the
+    /// quadtree into, see [`set_min_square_size`][FogOfWar::set_min_square_size()].
+    pub fn with_min_square_size(mut self, min_square_size: i32) -> Result<Self, RustVttError> {
+        self.set_min_square_size(min_square_size)?;
+        return Ok(self);
+    }
+
+    /// Returns the smallest width or height in pixels of a rectangle that updates split the
+    /// quadtree into
+    pub fn min_square_size(&self) -> i32 {
+        return self.min_square_size;
+    }
+
+    /// Set the smallest width or height in pixels of a rectangle that updates split the quadtree
+    /// into. Lower values give crisper edges at the cost of more rectangles, higher values are
+    /// faster. The default is 3. Only future updates are affected. Returns an error if the value is
+    /// smaller than 1.
+    pub fn set_min_square_size(&mut self, min_square_size: i32) -> Result<&mut Self, RustVttError> {
+        if min_square_size < MIN_SQUARE_SIZE_FLOOR {
+            return Err(RustVttError::InvalidMinSquareSize { min_square_size });
+        }
+        self.min_square_size = min_square_size;
+        return Ok(self);
+    }
+
+    /// Returns all rectangles of the image that are hidden by fog of war
+    pub fn get_rectangles(&self) -> Vec<FoWRectangle> {
+        return self.collect_leaves(FowState::Hidden);
+    }
+
+    /// Returns all rectangles of the image that are not hidden by fog of war, including the
+    /// remembered ones. Together with [`get_rectangles`][FogOfWar::get_rectangles()] these cover
+    /// the image without overlapping.
+    pub fn get_visible_rectangles(&self) -> Vec<FoWRectangle> {
+        let mut rectangles = Vec::new();
+        self.for_each_leaf(|bounds, hidden| {
+            if !hidden {
+                rectangles.push(*bounds);
+            }
+        });
+        return rectangles;
+    }
+
+    /// Returns all rectangles of the image that are revealed but not seen right now, see
+    /// [`remember_shown`][FogOfWar::remember_shown()]
+    pub fn get_remembered_rectangles(&self) -> Vec<FoWRectangle> {
+        return self.collect_leaves(FowState::Remembered);
+    }
+
+    /// Returns whether the given pixel is hidden by fog of war. Pixels outside of the image are
+    /// never hidden.
+    pub fn is_hidden(&self, point: PixelCoordinate) -> bool {
+        return self.state(point) == FowState::Hidden;
+    }
+
+    /// Returns whether the given pixel is revealed but not seen right now, see
+    /// [`remember_shown`][FogOfWar::remember_shown()]
+    pub fn is_remembered(&self, point: PixelCoordinate) -> bool {
+        return self.state(point) == FowState::Remembered;
+    }
+
+    /// Returns the state of the leaf containing the given pixel. Pixels outside of the image are
+    /// shown.
+    pub fn state(&self, point: PixelCoordinate) -> FowState {
+        if !self.bounds.contains(point) {
+            return FowState::Shown;
+        }
+        let mut node = self;
+        while let Some(child) = node.children().find(|child| child.bounds.contains(point)) {
+            node = child;
+        }
+        return node.state;
+    }
+
+    /// Returns the hidden area of the image as polygons in pixel coordinates, where adjacent
+    /// hidden rectangles are merged and revealed areas inside a hidden area are holes. This is
+    /// a much smaller description of the fog of war than [`get_rectangles`][FogOfWar::get_rectangles()]
+    /// for drawing it as vector graphics.
+    pub fn to_polygons(&self) -> MultiPolygon {
+        if self.is_leaf() {
+            if self.is_hidden_leaf() && self.bounds.width() > 0 && self.bounds.height() > 0 {
+                return MultiPolygon::new(vec![self.bounds.as_rect().to_polygon()]);
+            }
+            return MultiPolygon::new(vec![]);
+        }
+        // merging the children first keeps every union small
+        return self.children().map(|child| child.to_polygons()).fold(
+            MultiPolygon::new(vec![]),
+            |hidden, child| {
+                if child.0.is_empty() {
+                    return hidden;
+                }
+                return hidden.union(&child);
+            },
+        );
+    }
+
+    /// Returns the fraction of the given area of the image that is not hidden by fog of war, from
+    /// 0.0 when everything is hidden to 1.0 when everything is revealed, e.g. to fade in a
+    /// partially revealed square. Parts of the area outside of the image are left out, an area
+    /// entirely outside of the image returns 0.0.
+    pub fn visible_fraction(&self, area: FoWRectangle) -> f64 {
+        let Some(area) = self.bounds.intersection(&area) else {
+            return 0.0;
+        };
+        let total = area.width() as f64 * area.height() as f64;
+        return 1.0 - self.area_in(&area, FowState::Hidden) as f64 / total;
+    }
+
+    /// Returns the fraction of the image that is not hidden, between 0 and 1
+    pub fn explored_fraction(&self) -> f64 {
+        return self.visible_fraction(self.bounds);
+    }
+
+    /// Returns whether nothing is hidden. This is much cheaper than
+    /// [`explored_fraction`][FogOfWar::explored_fraction()], because updates merge a fully
+    /// revealed area into a single leaf, so the search stops at the first hidden leaf.
+    pub fn is_fully_revealed(&self) -> bool {
+        return !self.any_hidden();
+    }
+
+    /// Helper function: returns whether any leaf with a size is hidden
+    fn any_hidden(&self) -> bool {
+        if self.is_leaf() {
+            return self.is_hidden_leaf() && self.bounds.width() > 0 && self.bounds.height() > 0;
+        }
+        return self.children().any(FogOfWar::any_hidden);
+    }
+
+    /// Returns the revealed leaf of the quadtree that lies closest to the pixel, or the leaf
+    /// containing it if it is revealed. Returns None if nothing is revealed.
+    pub fn nearest_visible(&self, from: PixelCoordinate) -> Option<FoWRectangle> {
+        let mut nearest = None;
+        self.find_nearest_visible(from, &mut nearest);
+        return nearest.map(|(_, rectangle)| rectangle);
+    }
+
+    /// Helper function: searches the children closest to the pixel first, skipping every node
+    /// that lies further away than the nearest revealed leaf found so far
+    fn find_nearest_visible(
+        &self,
+        from: PixelCoordinate,
+        nearest: &mut Option<(i64, FoWRectangle)>,
+    ) {
+        if self.bounds.width() <= 0 || self.bounds.height() <= 0 {
+            return;
+        }
+        let distance = self.bounds.squared_distance_to(from);
+        if matches!(nearest, Some((best, _)) if *best <= distance) {
+            return;
+        }
+        if self.is_leaf() {
+            if !self.is_hidden_leaf() {
+                *nearest = Some((distance, self.bounds));
+            }
+            return;
+        }
+        let mut children: Vec<&FogOfWar> = self.children().collect();
+        children.sort_by_key(|child| child.bounds.squared_distance_to(from));
+        for child in children {
+            child.find_nearest_visible(from, nearest);
+        }
+    }
+
+    /// Helper function: returns the number of pixels inside the area that are in the given state
+    fn area_in(&self, area: &FoWRectangle, state: FowState) -> i64 {
+        let Some(overlap) = self.bounds.intersection(area) else {
+            return 0;
+        };
+        if self.is_leaf() {
+            if self.state != state {
+                return 0;
+            }
+            return overlap.width() as i64 * overlap.height() as i64;
+        }
+        return self
+            .children()
+            .map(|child| child.area_in(area, state))
+            .sum();
+    }
+
+    /// Call the function with the bounds of every leaf of the quadtree and whether it is hidden,
+    /// e.g. to draw the fog of war yourself. Together the leaves cover the image without
+    /// overlapping.
+    pub fn for_each_leaf<F: FnMut(&FoWRectangle, bool)>(&self, mut f: F) {
+        self.visit_leaves(&mut |bounds, state| f(bounds, state == FowState::Hidden));
+    }
+
+    fn visit_leaves<F: FnMut(&FoWRectangle, FowState)>(&self, f: &mut F) {
+        if self.is_leaf() {
+            if self.bounds.width() > 0 && self.bounds.height() > 0 {
+                f(&self.bounds, self.state);
+            }
+            return;
+        }
+        for child in self.children() {
+            child.visit_leaves(f);
+        }
+    }
+
+    /// Helper function: returns the bounds of every leaf in the given state
+    fn collect_leaves(&self, state: FowState) -> Vec<FoWRectangle> {
+        let mut rectangles = Vec::new();
+        self.push_leaves_in(state, &mut rectangles);
+        return rectangles;
+    }
+
+    fn push_leaves_in(&self, state: FowState, rectangles: &mut Vec<FoWRectangle>) {
+        if self.is_leaf() {
+            if self.state == state && self.bounds.width() > 0 && self.bounds.height() > 0 {
+                rectangles.push(self.bounds);
+            }
+            return;
+        }
+        for child in self.children() {
+            child.push_leaves_in(state, rectangles);
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        return self.child1.is_none();
+    }
+
+    fn is_hidden_leaf(&self) -> bool {
+        return self.state == FowState::Hidden;
+    }
+
+    fn children(&self) -> impl Iterator<Item = &FogOfWar> {
+        return [&self.child1, &self.child2, &self.child3, &self.child4]
+            .into_iter()
+            .flatten()
+            .map(|child| child.as_ref());
+    }
+
+    fn children_mut(&mut self) -> impl Iterator<Item = &mut FogOfWar> {
+        return [
+            &mut self.child1,
+            &mut self.child2,
+            &mut self.child3,
+            &mut self.child4,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|child| child.as_mut());
+    }
+
     /// Set the entire fog of war hidden area to true
     pub fn hide_all(&mut self) -> &mut Self {
-        self.hidden = true;
-        self.child1 = None;
-        self.child2 = None;
-        self.child3 = None;
-        self.child4 = None;
+        self.set_leaf(FowState::Hidden);
         return self;
     }
 
     /// Set the entire fog of war hidden area to false (reveal everything)
     pub fn show_all(&mut self) -> &mut Self {
-        self.hidden = false;
+        self.set_leaf(FowState::Shown);
+        return self;
+    }
+
+    /// Mark everything that is shown as remembered: it stays revealed, but is no longer seen
+    /// right now. Show what is seen afterwards with [`update`][FogOfWar::update()], so everything
+    /// that left the view stays remembered.
+    pub fn remember_shown(&mut self) -> &mut Self {
+        self.replace_state(FowState::Shown, FowState::Remembered);
+        return self;
+    }
+
+    /// Show everything that is remembered again, so only hidden and shown areas are left
+    pub fn show_remembered(&mut self) -> &mut Self {
+        self.replace_state(FowState::Remembered, FowState::Shown);
+        return self;
+    }
+
+    /// Helper function: changes every leaf in the state `from` to the state `to`
+    fn replace_state(&mut self, from: FowState, to: FowState) {
+        if self.is_leaf() {
+            if self.state == from {
+                self.state = to;
+            }
+            return;
+        }
+        for child in self.children_mut() {
+            child.replace_state(from, to);
+        }
+        self.merge();
+    }
+
+    fn set_leaf(&mut self, state: FowState) {
+        self.state = state;
         self.child1 = None;
         self.child2 = None;
         self.child3 = None;
         self.child4 = None;
-        return self;
     }
 
-    pub fn update(&mut self) {
-        todo!("Given pixel data of what is visible or not, this function should convert this into a quad tree");
+    /// Hide or reveal the area inside the given polygon. The polygon should be in pixel
+    /// coordinates. Rectangles at the edge of the polygon that can not be split any further are
+    /// updated if their center lies inside the polygon.
+    pub fn update(&mut self, polygon: &Polygon, operation: Operation) {
+        self.update_root(polygon, &polygon_edges(polygon), operation.state());
+    }
+
+    /// Hide or reveal the area inside any of the given polygons like
+    /// [`update`][FogOfWar::update()], in a single pass over the quadtree.
+    pub fn update_multi(&mut self, multi_polygon: &MultiPolygon, operation: Operation) {
+        let edges: Vec<Line> = multi_polygon.iter().flat_map(polygon_edges).collect();
+        self.update_root(multi_polygon, &edges, operation.state());
+    }
+
+    /// Hide or reveal the area inside the given rectangle like [`update`][FogOfWar::update()].
+    /// Corners given out of order are normalized.
+    pub fn update_rect(&mut self, rect: FoWRectangle, operation: Operation) {
+        self.set_rect(rect, operation.state());
+    }
+
+    /// Helper function: sets the area inside the rectangle to the given state
+    fn set_rect(&mut self, rect: FoWRectangle, state: FowState) {
+        let polygon = rect.as_rect().to_polygon();
+        self.update_root(&polygon, &polygon_edges(&polygon), state);
+    }
+
+    /// Copy the state of the other fog of war into this one, moved by the offset in pixels, e.g.
+    /// to place the fog of war of a map that was joined onto this map. Hidden, shown and
+    /// remembered areas are copied, parts that fall outside of this fog of war are left out.
+    pub fn paste(&mut self, other: &FogOfWar, offset: PixelCoordinate) {
+        let shift = |pixel: PixelCoordinate| PixelCoordinate {
+            x: pixel.x + offset.x,
+            y: pixel.y + offset.y,
+        };
+        other.visit_leaves(&mut |bounds, state| {
+            let rectangle = FoWRectangle {
+                topleft: shift(bounds.topleft),
+                bottomright: shift(bounds.bottomright),
+            };
+            self.set_rect(rectangle, state);
+        });
+    }
+
+    /// Returns this fog of war turned a quarter turn clockwise, e.g. for an image that was rotated.
+    /// The width and height swap, and hidden, shown and remembered areas move along.
+    pub fn rotate_clockwise(&self) -> FogOfWar {
+        let height = self.bounds.bottomright.y;
+        let mut rotated = FogOfWar::with_bounds(FoWRectangle {
+            topleft: PixelCoordinate { x: 0, y: 0 },
+            bottomright: PixelCoordinate {
+                x: height,
+                y: self.bounds.bottomright.x,
+            },
+        });
+        rotated.min_square_size = self.min_square_size;
+        // the pixel (x, y) moves to (height - 1 - y, x)
+        self.visit_leaves(&mut |bounds, state| {
+            let rectangle = FoWRectangle {
+                topleft: PixelCoordinate {
+                    x: height - bounds.bottomright.y,
+                    y: bounds.topleft.x,
+                },
+                bottomright: PixelCoordinate {
+                    x: height - bounds.topleft.y,
+                    y: bounds.bottomright.x,
+                },
+            };
+            rotated.set_rect(rectangle, state);
+        });
+        return rotated;
+    }
+
+    /// Helper function: updates the whole quadtree, tracing the change in the number of rectangles
+    /// when the `tracing` feature is enabled
+    fn update_root<G: Contains<Point>>(&mut self, polygon: &G, edges: &[Line], state: FowState) {
+        #[cfg(feature = "tracing")]
+        let before = tracing::enabled!(tracing::Level::DEBUG).then(|| self.leaf_count());
+        let min_square_size = self.min_square_size;
+        self.update_node(polygon, edges, state, min_square_size);
+        #[cfg(feature = "tracing")]
+        if let Some(before) = before {
+            tracing::debug!(
+                edges = edges.len(),
+                rectangles_before = before,
+                rectangles_after = self.leaf_count(),
+                "fog of war update"
+            );
+        }
+    }
+
+    /// Helper function: returns the number of leaves of the quadtree
+    #[cfg(feature = "tracing")]
+    fn leaf_count(&self) -> usize {
+        let mut count = 0;
+        self.for_each_leaf(|_, _| count += 1);
+        return count;
+    }
+
+    /// Helper function: `edges` are the edges of the polygon that may intersect this node
+    fn update_node<G: Contains<Point>>(
+        &mut self,
+        polygon: &G,
+        edges: &[Line],
+        state: FowState,
+        min_square_size: i32,
+    ) {
+        if self.bounds.width() <= 0 || self.bounds.height() <= 0 {
+            return;
+        }
+        if self.is_leaf() && self.state == state {
+            return;
+        }
+        let rect = self.bounds.as_rect();
+        let edges: Vec<Line> = edges
+            .iter()
+            .filter(|edge| edge.intersects(&rect))
+            .copied()
+            .collect();
+        if edges.is_empty() {
+            // the rectangle lies either entirely inside or entirely outside the polygon
+            if polygon.contains(&Point::from(rect.center())) {
+                self.set_leaf(state);
+            }
+            return;
+        }
+        if self.bounds.width() <= min_square_size || self.bounds.height() <= min_square_size {
+            if polygon.contains(&Point::from(rect.center())) {
+                self.set_leaf(state);
+            }
+            return;
+        }
+        if self.is_leaf() {
+            self.split_leaf();
+        }
+        for child in self.children_mut() {
+            child.update_node(polygon, &edges, state, min_square_size);
+        }
+        self.merge();
+    }
+
+    /// Reveal everything that is revealed in the other fog of war, so the result shows what is
+    /// revealed in either of them. Areas that are shown in one and remembered in the other are
+    /// shown. Returns an error if the other fog of war was made for an image
+    /// of a different size.
+    pub fn merge_visible(&mut self, other: &FogOfWar) -> Result<&mut Self, RustVttError> {
+        if self.bounds != other.bounds {
+            return Err(RustVttError::FogOfWarMismatch {
+                expected: self.bounds.bottomright,
+                found: other.bounds.bottomright,
+            });
+        }
+        self.merge_visible_node(other);
+        return Ok(self);
+    }
+
+    /// Helper function: `other` is the node with the same bounds in the other fog of war
+    fn merge_visible_node(&mut self, other: &FogOfWar) {
+        if self.is_leaf() && self.state == FowState::Shown {
+            return;
+        }
+        if other.is_leaf() {
+            self.limit_state(other.state);
+            return;
+        }
+        if self.is_leaf() {
+            // this whole node has one state, so the other node decides where it is more visible
+            let state = self.state;
+            let copy = other.clone_tree();
+            (self.child1, self.child2, self.child3, self.child4) =
+                (copy.child1, copy.child2, copy.child3, copy.child4);
+            for child in self.children_mut() {
+                child.limit_state(state);
+            }
+            self.merge();
+            return;
+        }
+        for (child, other) in self.children_mut().zip(other.children()) {
+            child.merge_visible_node(other);
+        }
+        self.merge();
+    }
+
+    /// Helper function: makes every leaf at least as visible as the given state
+    fn limit_state(&mut self, state: FowState) {
+        if self.is_leaf() {
+            self.state = self.state.min(state);
+            return;
+        }
+        for child in self.children_mut() {
+            child.limit_state(state);
+        }
+        self.merge();
+    }
+
+    /// Helper function: turns this leaf into a node with four children of the same state
+    fn split_leaf(&mut self) {
+        let [child1, child2, child3, child4] = self.bounds.split().map(|bounds| {
+            Some(Box::new(FogOfWar {
+                bounds,
+                state: self.state,
+                ..Default::default()
+            }))
+        });
+        (self.child1, self.child2, self.child3, self.child4) = (child1, child2, child3, child4);
+    }
+
+    /// Helper function: turns this node into a leaf if all children are leaves with the same state
+    fn merge(&mut self) {
+        let Some(first) = &self.child1 else {
+            return;
+        };
+        let state = first.state;
+        if self
+            .children()
+            .all(|child| child.is_leaf() && child.state == state)
+        {
+            self.set_leaf(state);
+        }
+    }
+
+    /// Returns the rectangles that were hidden or revealed since the last call, so a copy of the
+    /// fog of war elsewhere can be updated with [`apply_delta`][FogOfWar::apply_delta()] without
+    /// sending all rectangles. The first call returns the changes since the fog of war was created,
+    /// when everything was revealed.
+    pub fn take_changes(&mut self) -> FowDelta {
+        let mut delta = FowDelta::default();
+        let previous = self
+            .last_taken
+            .take()
+            .unwrap_or_else(|| Box::new(FogOfWar::with_bounds(self.bounds)));
+        previous.diff(self, &mut delta);
+        self.last_taken = Some(Box::new(self.clone_tree()));
+        return delta;
+    }
+
+    /// Apply the changes returned by [`take_changes`][FogOfWar::take_changes()] on another fog of
+    /// war of the same size. Rectangles that do not belong to the quadtree of this fog of war are
+    /// ignored.
+    pub fn apply_delta(&mut self, delta: &FowDelta) {
+        for rectangle in &delta.hidden {
+            self.set_node(rectangle, FowState::Hidden);
+        }
+        for rectangle in &delta.shown {
+            self.set_node(rectangle, FowState::Shown);
+        }
+        for rectangle in &delta.remembered {
+            self.set_node(rectangle, FowState::Remembered);
+        }
+    }
+
+    /// Helper function: adds every rectangle that changed from this (older) node to the newer node
+    /// with the same bounds to the delta
+    fn diff(&self, newer: &FogOfWar, delta: &mut FowDelta) {
+        match (self.is_leaf(), newer.is_leaf()) {
+            (true, true) => {
+                if self.state != newer.state {
+                    push_delta(delta, self.bounds, newer.state);
+                }
+            }
+            (true, false) => newer.push_changed_leaves(self.state, None, delta),
+            (false, true) => {
+                for child in self.children() {
+                    child.push_changed_leaves(newer.state, Some(newer.state), delta);
+                }
+            }
+            (false, false) => {
+                for (old, new) in self.children().zip(newer.children()) {
+                    old.diff(new, delta);
+                }
+            }
+        }
+    }
+
+    /// Helper function: adds the bounds of every leaf that is not in the state `unchanged` to the
+    /// delta, as changed to the state `to` or else to the state of the leaf
+    fn push_changed_leaves(&self, unchanged: FowState, to: Option<FowState>, delta: &mut FowDelta) {
+        if self.is_leaf() {
+            if self.state != unchanged && self.bounds.width() > 0 && self.bounds.height() > 0 {
+                push_delta(delta, self.bounds, to.unwrap_or(self.state));
+            }
+            return;
+        }
+        for child in self.children() {
+            child.push_changed_leaves(unchanged, to, delta);
+        }
+    }
+
+    /// Helper function: sets the node with exactly the given bounds to a leaf with the given state
+    fn set_node(&mut self, bounds: &FoWRectangle, state: FowState) {
+        if self.bounds == *bounds {
+            self.set_leaf(state);
+            return;
+        }
+        let inside = |outer: &FoWRectangle| {
+            outer.topleft.x <= bounds.topleft.x
+                && outer.topleft.y <= bounds.topleft.y
+                && bounds.bottomright.x <= outer.bottomright.x
+                && bounds.bottomright.y <= outer.bottomright.y
+        };
+        if !inside(&self.bounds) || (self.is_leaf() && self.state == state) {
+            return;
+        }
+        if self.is_leaf() {
+            if self.bounds.width() <= 1 || self.bounds.height() <= 1 {
+                return;
+            }
+            self.split_leaf();
+        }
+        if let Some(child) = self.children_mut().find(|child| inside(&child.bounds)) {
+            child.set_node(bounds, state);
+        }
+        self.merge();
+    }
+
+    /// Helper function: copies the quadtree without the state of `take_changes`
+    fn clone_tree(&self) -> FogOfWar {
+        let clone = |child: &Option<Box<FogOfWar>>| {
+            child.as_ref().map(|child| Box::new(child.clone_tree()))
+        };
+        return FogOfWar {
+            bounds: self.bounds,
+            state: self.state,
+            child1: clone(&self.child1),
+            child2: clone(&self.child2),
+            child3: clone(&self.child3),
+            child4: clone(&self.child4),
+            last_taken: None,
+            min_square_size: self.min_square_size,
+        };
+    }
+
+    /// Serialize the fog of war state into a compact binary form. The bounds of the image are
+    /// stored together with one to three bits per node of the quadtree.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bits = BitWriter::default();
+        self.write_node(&mut bits);
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.bounds.bottomright.x.to_le_bytes());
+        bytes.extend_from_slice(&self.bounds.bottomright.y.to_le_bytes());
+        bytes.extend_from_slice(&bits.bytes);
+        return bytes;
+    }
+
+    fn write_node(&self, bits: &mut BitWriter) {
+        if self.is_leaf() {
+            bits.push(false);
+            bits.push(self.state == FowState::Hidden);
+            if self.state != FowState::Hidden {
+                bits.push(self.state == FowState::Remembered);
+            }
+            return;
+        }
+        bits.push(true);
+        for child in self.children() {
+            child.write_node(bits);
+        }
+    }
+
+    /// Restore a fog of war that was serialized with [`serialize`][FogOfWar::serialize()]. Returns
+    /// an error if the fog of war was created for a map with a different resolution or if the
+    /// data is invalid.
+    pub fn deserialize(bytes: &[u8], resolution: &Resolution) -> Result<FogOfWar, RustVttError> {
+        let fog_of_war = FogOfWar::from_bytes(bytes)?;
+        fog_of_war.check_resolution(resolution)?;
+        return Ok(fog_of_war);
+    }
+
+    /// Returns an error if this fog of war does not cover the image of a vtt with the given
+    /// resolution
+    pub(crate) fn check_resolution(&self, resolution: &Resolution) -> Result<(), RustVttError> {
+        let expected = resolution.pixel_size();
+        if self.bounds.bottomright != expected {
+            return Err(RustVttError::FogOfWarMismatch {
+                expected,
+                found: self.bounds.bottomright,
+            });
+        }
+        return Ok(());
+    }
+
+    /// Helper function: restores a serialized fog of war with the bounds that are stored in it
+    fn from_bytes(bytes: &[u8]) -> Result<FogOfWar, RustVttError> {
+        let header_length = MAGIC.len() + 1 + 8;
+        let version = bytes.get(4).copied();
+        if bytes.len() < header_length
+            || &bytes[..4] != MAGIC
+            || !matches!(version, Some(VERSION | VERSION_1))
+        {
+            return Err(RustVttError::InvalidFogOfWar);
+        }
+        let width = i32::from_le_bytes(bytes[5..9].try_into().unwrap());
+        let height = i32::from_le_bytes(bytes[9..13].try_into().unwrap());
+        if width <= 0 || height <= 0 {
+            return Err(RustVttError::InvalidFogOfWar);
+        }
+        let mut fog_of_war = FogOfWar::with_bounds(FoWRectangle {
+            topleft: PixelCoordinate { x: 0, y: 0 },
+            bottomright: PixelCoordinate {
+                x: width,
+                y: height,
+            },
+        });
+        let mut bits = BitReader {
+            bytes: &bytes[header_length..],
+            position: 0,
+        };
+        fog_of_war.read_node(&mut bits, bytes[4])?;
+        return Ok(fog_of_war);
+    }
+
+    fn read_node(&mut self, bits: &mut BitReader, version: u8) -> Result<(), RustVttError> {
+        if !bits.next()? {
+            self.state = if bits.next()? {
+                FowState::Hidden
+            } else if version != VERSION_1 && bits.next()? {
+                FowState::Remembered
+            } else {
+                FowState::Shown
+            };
+            return Ok(());
+        }
+        if self.bounds.width() <= 1 || self.bounds.height() <= 1 {
+            return Err(RustVttError::InvalidFogOfWar);
+        }
+        let [child1, child2, child3, child4] = self
+            .bounds
+            .split()
+            .map(|bounds| Some(Box::new(FogOfWar::with_bounds(bounds))));
+        (self.child1, self.child2, self.child3, self.child4) = (child1, child2, child3, child4);
+        for child in self.children_mut() {
+            child.read_node(bits, version)?;
+        }
+        return Ok(());
+    }
+}
+
+/// The serde representation of a fog of war: the compact binary form of
+/// [`serialize`][FogOfWar::serialize()] encoded as base64, instead of every node of the quadtree
+#[derive(Serialize, Deserialize)]
+struct FogOfWarData {
+    min_square_size: i32,
+    quadtree: String,
+}
+
+impl Serialize for FogOfWar {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        return FogOfWarData {
+            min_square_size: self.min_square_size,
+            quadtree: BASE64_STANDARD.encode(FogOfWar::serialize(self)),
+        }
+        .serialize(serializer);
+    }
+}
+
+impl<'de> Deserialize<'de> for FogOfWar {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = FogOfWarData::deserialize(deserializer)?;
+        let bytes = BASE64_STANDARD
+            .decode(data.quadtree)
+            .map_err(serde::de::Error::custom)?;
+        return FogOfWar::from_bytes(&bytes)
+            .and_then(|fog_of_war| fog_of_war.with_min_square_size(data.min_square_size))
+            .map_err(serde::de::Error::custom);
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    length: usize,
+}
+
+impl BitWriter {
+    fn push(&mut self, bit: bool) {
+        if self.length.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 1 << (self.length % 8);
+        }
+        self.length += 1;
     }
 }
 
-impl Default for FogOfWar {
-    fn default() -> Self {
-        Self {
-            hidden: false,
-            child1: None,
-            child2: None,
-            child3: None,
-            child4: None,
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn next(&mut self) -> Result<bool, RustVttError> {
+        let byte = self
+            .bytes
+            .get(self.position / 8)
+            .ok_or(RustVttError::InvalidFogOfWar)?;
+        let bit = byte & (1 << (self.position % 8)) != 0;
+        self.position += 1;
+        return Ok(bit);
+    }
+}
+
+/// Helper function: adds the rectangle to the list of the delta for the given state
+fn push_delta(delta: &mut FowDelta, rectangle: FoWRectangle, state: FowState) {
+    match state {
+        FowState::Shown => delta.shown.push(rectangle),
+        FowState::Remembered => delta.remembered.push(rectangle),
+        FowState::Hidden => delta.hidden.push(rectangle),
+    }
+}
+
+/// Helper function: returns the edges of the exterior and interiors of a polygon
+fn polygon_edges(polygon: &Polygon) -> Vec<Line> {
+    return polygon
+        .exterior()
+        .lines()
+        .chain(polygon.interiors().iter().flat_map(|ring| ring.lines()))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::RustVttError;
+    use crate::fog_of_war::{FoWRectangle, FogOfWar, FowDelta, FowState, Operation};
+    use crate::open_vtt;
+    use crate::vtt::{Coordinate, PixelCoordinate, Resolution};
+    use geo::{polygon, Area, LineString, MultiPolygon, Polygon};
+
+    fn hidden_area(fog_of_war: &FogOfWar) -> i64 {
+        return fog_of_war
+            .get_rectangles()
+            .iter()
+            .map(|r| r.width() as i64 * r.height() as i64)
+            .sum();
+    }
+
+    /// Returns true if no node of the quadtree has four leaves with the same state as children
+    fn is_merged(fog_of_war: &FogOfWar) -> bool {
+        if fog_of_war.is_leaf() {
+            return true;
+        }
+        let state = fog_of_war.child1.as_ref().unwrap().state;
+        let mergeable = fog_of_war
+            .children()
+            .all(|child| child.is_leaf() && child.state == state);
+        return !mergeable && fog_of_war.children().all(is_merged);
+    }
+
+    #[test]
+    fn test_random_updates() {
+        let size = 64;
+        let resolution = Resolution::new(
+            Coordinate { x: 0.0, y: 0.0 },
+            Coordinate { x: 4.0, y: 4.0 },
+            size / 4,
+        );
+        let mut fog_of_war = FogOfWar::new(&resolution).with_min_square_size(1).unwrap();
+        let mut expected = vec![false; (size * size) as usize];
+        // a linear congruential generator, so the test is reproducible
+        let mut seed: u64 = 0x2545f4914f6cdd1d;
+        let mut random = |max: i32| -> i32 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            return ((seed >> 33) % max as u64) as i32;
+        };
+        for i in 0..1000 {
+            let (x, y) = (random(size), random(size));
+            let rectangle = FoWRectangle {
+                topleft: PixelCoordinate { x, y },
+                bottomright: PixelCoordinate {
+                    x: x + 1 + random(size - x),
+                    y: y + 1 + random(size - y),
+                },
+            };
+            let operation = if random(2) == 0 {
+                Operation::Hide
+            } else {
+                Operation::Show
+            };
+            fog_of_war.update_rect(rectangle, operation);
+            let rectangles = fog_of_war.get_rectangles();
+            fog_of_war.update_rect(rectangle, operation);
+            assert_eq!(
+                fog_of_war.get_rectangles(),
+                rectangles,
+                "Expected repeating update {} to not change anything",
+                i
+            );
+            for py in rectangle.topleft.y..rectangle.bottomright.y {
+                for px in rectangle.topleft.x..rectangle.bottomright.x {
+                    expected[(py * size + px) as usize] = operation == Operation::Hide;
+                }
+            }
+            if i % 50 != 0 {
+                continue;
+            }
+            for py in 0..size {
+                for px in 0..size {
+                    assert_eq!(
+                        fog_of_war.is_hidden(PixelCoordinate { x: px, y: py }),
+                        expected[(py * size + px) as usize],
+                        "Pixel ({}, {}) did not match after update {}",
+                        px,
+                        py,
+                        i
+                    );
+                }
+            }
+            let visible: i64 = fog_of_war
+                .get_visible_rectangles()
+                .iter()
+                .map(|r| r.width() as i64 * r.height() as i64)
+                .sum();
+            assert_eq!(hidden_area(&fog_of_war) + visible, (size * size) as i64);
+            assert!(
+                is_merged(&fog_of_war),
+                "Expected equal leaves to be merged after update {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_rectangles() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        assert!(
+            fog_of_war.get_rectangles().is_empty(),
+            "Expected a new fog of war to reveal everything"
+        );
+        fog_of_war.hide_all();
+        assert_eq!(
+            fog_of_war.get_rectangles(),
+            vec![FoWRectangle {
+                topleft: PixelCoordinate { x: 0, y: 0 },
+                bottomright: PixelCoordinate {
+                    x: 27 * 256,
+                    y: 15 * 256
+                },
+            }],
+            "Expected a single rectangle covering the image"
+        );
+        fog_of_war.show_all();
+        assert!(fog_of_war.get_rectangles().is_empty());
+    }
+
+    #[test]
+    fn test_for_each_leaf() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        fog_of_war.update(
+            &polygon![(x: 100.0, y: 100.0), (x: 2000.0, y: 100.0), (x: 100.0, y: 1500.0)],
+            Operation::Hide,
+        );
+        let (mut hidden, mut visible) = (Vec::new(), Vec::new());
+        fog_of_war.for_each_leaf(|bounds, is_hidden| {
+            if is_hidden {
+                hidden.push(*bounds);
+            } else {
+                visible.push(*bounds);
+            }
+        });
+        assert_eq!(hidden, fog_of_war.get_rectangles());
+        assert_eq!(visible, fog_of_war.get_visible_rectangles());
+    }
+
+    #[test]
+    fn test_get_visible_rectangles() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        assert_eq!(
+            fog_of_war.get_visible_rectangles(),
+            vec![fog_of_war.bounds],
+            "Expected a new fog of war to reveal the whole image"
+        );
+        fog_of_war.hide_all();
+        assert!(fog_of_war.get_visible_rectangles().is_empty());
+        fog_of_war.update(
+            &polygon![(x: 100.0, y: 100.0), (x: 2000.0, y: 100.0), (x: 100.0, y: 1500.0)],
+            Operation::Show,
+        );
+        let hidden = fog_of_war.get_rectangles();
+        let visible = fog_of_war.get_visible_rectangles();
+        let area = |rectangles: &[FoWRectangle]| -> i64 {
+            rectangles
+                .iter()
+                .map(|r| r.width() as i64 * r.height() as i64)
+                .sum()
+        };
+        assert_eq!(
+            area(&hidden) + area(&visible),
+            27 * 256 * 15 * 256,
+            "Expected the hidden and visible rectangles to cover the image"
+        );
+        assert!(
+            hidden
+                .iter()
+                .all(|h| visible.iter().all(|v| h.intersection(v).is_none())),
+            "Expected the hidden and visible rectangles to not overlap"
+        );
+    }
+
+    #[test]
+    fn test_update() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        fog_of_war.hide_all();
+        let total = hidden_area(&fog_of_war);
+        let triangle = polygon![(x: 100.0, y: 100.0), (x: 2000.0, y: 100.0), (x: 100.0, y: 1500.0)];
+        fog_of_war.update(&triangle, Operation::Show);
+        let revealed = (total - hidden_area(&fog_of_war)) as f64;
+        let error = (revealed - triangle.unsigned_area()).abs() / triangle.unsigned_area();
+        assert!(
+            error < 0.01,
+            "Expected about {} pixels to be revealed, found {}",
+            triangle.unsigned_area(),
+            revealed
+        );
+        fog_of_war.update(&triangle, Operation::Hide);
+        assert_eq!(
+            fog_of_war.get_rectangles().len(),
+            1,
+            "Expected hiding the triangle again to merge the quadtree back into one rectangle"
+        );
+    }
+
+    #[test]
+    fn test_is_hidden() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        fog_of_war.hide_all();
+        let square = polygon![(x: 300.0, y: 300.0), (x: 900.0, y: 300.0), (x: 900.0, y: 1000.0), (x: 300.0, y: 1000.0)];
+        fog_of_war.update(&square, Operation::Show);
+        let point = |x, y| PixelCoordinate { x, y };
+        assert!(!fog_of_war.is_hidden(point(500, 500)));
+        assert!(fog_of_war.is_hidden(point(100, 500)));
+        // pixels on the edge of the square belong to exactly one rectangle
+        let rectangles = fog_of_war.get_rectangles();
+        for y in 295..305 {
+            for x in 895..905 {
+                assert_eq!(
+                    fog_of_war.is_hidden(point(x, y)),
+                    rectangles.iter().any(|r| r.contains(point(x, y))),
+                    "pixel ({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+        assert!(
+            !fog_of_war.is_hidden(point(-1, 0)),
+            "Expected a pixel outside of the image to not be hidden"
+        );
+        for rectangle in rectangles {
+            assert!(fog_of_war.is_hidden(rectangle.topleft));
+        }
+    }
+
+    #[test]
+    fn test_update_rect() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        fog_of_war.hide_all();
+        let total = hidden_area(&fog_of_war);
+        // bottom right above the top left
+        let rect = FoWRectangle {
+            topleft: PixelCoordinate { x: 1000, y: 700 },
+            bottomright: PixelCoordinate { x: 100, y: 50 },
+        };
+        fog_of_war.update_rect(rect, Operation::Show);
+        let revealed = (total - hidden_area(&fog_of_war)) as f64;
+        let error = (revealed - 900.0 * 650.0).abs() / (900.0 * 650.0);
+        assert!(
+            error < 0.01,
+            "Expected about {} pixels to be revealed, found {}",
+            900 * 650,
+            revealed
+        );
+        assert!(!fog_of_war.is_hidden(PixelCoordinate { x: 500, y: 300 }));
+        assert!(fog_of_war.is_hidden(PixelCoordinate { x: 1100, y: 300 }));
+    }
+
+    #[test]
+    fn test_update_multi() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let first = polygon![(x: 100.0, y: 100.0), (x: 900.0, y: 150.0), (x: 400.0, y: 700.0)];
+        let second = polygon![(x: 300.0, y: 200.0), (x: 1200.0, y: 300.0), (x: 800.0, y: 900.0)];
+        let mut sequential = FogOfWar::new(vtt.resolution());
+        sequential.hide_all();
+        sequential.update(&first, Operation::Show);
+        sequential.update(&second, Operation::Show);
+        let mut combined = FogOfWar::new(vtt.resolution());
+        combined.hide_all();
+        combined.update_multi(&MultiPolygon::new(vec![first, second]), Operation::Show);
+        assert_eq!(
+            hidden_area(&combined),
+            hidden_area(&sequential),
+            "Expected a single pass to reveal the same area as two updates"
+        );
+        assert!(!combined.is_hidden(PixelCoordinate { x: 500, y: 300 }));
+        assert!(combined.is_hidden(PixelCoordinate { x: 1500, y: 1500 }));
+    }
+
+    #[test]
+    fn test_min_square_size() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let triangle = polygon![(x: 100.0, y: 100.0), (x: 900.0, y: 150.0), (x: 400.0, y: 700.0)];
+        let mut coarse = FogOfWar::new(vtt.resolution())
+            .with_min_square_size(12)
+            .expect("Could not set the minimum square size");
+        coarse.update(&triangle, Operation::Hide);
+        let mut fine = FogOfWar::new(vtt.resolution());
+        assert_eq!(fine.min_square_size(), 3, "Expected the default of 3");
+        fine.update(&triangle, Operation::Hide);
+        assert!(
+            fine.get_rectangles().len() > coarse.get_rectangles().len(),
+            "Expected a smaller minimum to give more rectangles, found {} and {}",
+            fine.get_rectangles().len(),
+            coarse.get_rectangles().len()
+        );
+        assert!(
+            matches!(
+                fine.set_min_square_size(0),
+                Err(RustVttError::InvalidMinSquareSize { min_square_size: 0 })
+            ),
+            "Expected a minimum below one pixel to be rejected"
+        );
+        assert_eq!(fine.min_square_size(), 3);
+        fine.resize(vtt.resolution());
+        coarse.resize(vtt.resolution());
+        assert_eq!(
+            coarse.min_square_size(),
+            12,
+            "Expected the minimum to be kept on resize"
+        );
+    }
+
+    #[test]
+    fn test_merge_visible() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut first = FogOfWar::new(vtt.resolution());
+        first.hide_all().update(
+            &polygon![(x: 100.0, y: 100.0), (x: 900.0, y: 150.0), (x: 400.0, y: 700.0)],
+            Operation::Show,
+        );
+        let mut second = FogOfWar::new(vtt.resolution());
+        second.hide_all().update(
+            &polygon![(x: 2000.0, y: 1000.0), (x: 3000.0, y: 1200.0), (x: 2500.0, y: 2000.0)],
+            Operation::Show,
+        );
+        let total = FogOfWar::new(vtt.resolution()).hide_all().get_rectangles()[0];
+        let total = total.width() as i64 * total.height() as i64;
+        let revealed = |fog_of_war: &FogOfWar| total - hidden_area(fog_of_war);
+        let expected = revealed(&first) + revealed(&second);
+        let mut merged = first.clone_tree();
+        merged
+            .merge_visible(&second)
+            .expect("Could not merge the fog of war");
+        assert_eq!(
+            revealed(&merged),
+            expected,
+            "Expected the revealed areas of both to be revealed"
+        );
+        let mut leaves = FowDelta::default();
+        merged.push_changed_leaves(FowState::Shown, None, &mut leaves);
+        assert_eq!(
+            leaves.hidden,
+            merged.get_rectangles(),
+            "Expected the merged leaves to match the hidden rectangles"
+        );
+        for point in [
+            PixelCoordinate { x: 450, y: 300 },
+            PixelCoordinate { x: 2500, y: 1400 },
+        ] {
+            assert!(!merged.is_hidden(point));
         }
+        assert!(merged.is_hidden(PixelCoordinate { x: 5000, y: 3000 }));
+
+        let other = open_vtt("tests/resources/example2.dd2vtt")
+            .expect("Could not open file example2.dd2vtt");
+        assert!(matches!(
+            merged.merge_visible(&FogOfWar::new(other.resolution())),
+            Err(RustVttError::FogOfWarMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_polygons() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        assert!(fog_of_war.to_polygons().0.is_empty());
+        let circle = Polygon::new(
+            LineString::from(
+                (0..64)
+                    .map(|i| {
+                        let angle = i as f64 / 64.0 * std::f64::consts::TAU;
+                        (2000.0 + 500.0 * angle.cos(), 1500.0 + 500.0 * angle.sin())
+                    })
+                    .collect::<Vec<(f64, f64)>>(),
+            ),
+            vec![],
+        );
+        fog_of_war.hide_all().update(&circle, Operation::Show);
+        let polygons = fog_of_war.to_polygons();
+        assert_eq!(
+            polygons.0.len(),
+            1,
+            "Expected the hidden area to be a single polygon"
+        );
+        assert_eq!(
+            polygons.0[0].interiors().len(),
+            1,
+            "Expected the revealed circle to be a hole"
+        );
+        assert!(
+            (polygons.unsigned_area() - hidden_area(&fog_of_war) as f64).abs() < 1e-6,
+            "Expected the polygons to cover the hidden rectangles"
+        );
+    }
+
+    #[test]
+    fn test_visible_fraction() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        let square = FoWRectangle {
+            topleft: PixelCoordinate { x: 256, y: 256 },
+            bottomright: PixelCoordinate { x: 512, y: 512 },
+        };
+        assert_eq!(fog_of_war.visible_fraction(square), 1.0);
+        fog_of_war.hide_all();
+        assert_eq!(fog_of_war.visible_fraction(square), 0.0);
+        // reveal the left half of the square
+        fog_of_war.update_rect(
+            FoWRectangle {
+                topleft: PixelCoordinate { x: 0, y: 0 },
+                bottomright: PixelCoordinate { x: 384, y: 1024 },
+            },
+            Operation::Show,
+        );
+        let fraction = fog_of_war.visible_fraction(square);
+        assert!(
+            (fraction - 0.5).abs() < 0.02,
+            "Expected half of the square to be visible, found {}",
+            fraction
+        );
+        let outside = FoWRectangle {
+            topleft: PixelCoordinate { x: -100, y: -100 },
+            bottomright: PixelCoordinate { x: -10, y: -10 },
+        };
+        assert_eq!(fog_of_war.visible_fraction(outside), 0.0);
+    }
+
+    #[test]
+    fn test_nearest_visible() {
+        let resolution = Resolution::new(
+            Coordinate { x: 0.0, y: 0.0 },
+            Coordinate { x: 4.0, y: 4.0 },
+            16,
+        );
+        let mut fog_of_war = FogOfWar::new(&resolution);
+        fog_of_war.hide_all();
+        assert_eq!(
+            fog_of_war.nearest_visible(PixelCoordinate { x: 5, y: 5 }),
+            None
+        );
+
+        let near = FoWRectangle {
+            topleft: PixelCoordinate { x: 40, y: 8 },
+            bottomright: PixelCoordinate { x: 48, y: 16 },
+        };
+        let far = FoWRectangle {
+            topleft: PixelCoordinate { x: 0, y: 56 },
+            bottomright: PixelCoordinate { x: 8, y: 64 },
+        };
+        fog_of_war.update_rect(near, Operation::Show);
+        fog_of_war.update_rect(far, Operation::Show);
+        let from = PixelCoordinate { x: 30, y: 12 };
+        let nearest = fog_of_war
+            .nearest_visible(from)
+            .expect("Expected a revealed rectangle");
+        assert_eq!(
+            nearest.closest_pixel(from),
+            PixelCoordinate { x: 40, y: 12 },
+            "Expected the closest revealed pixel to lie on the left edge of the near rectangle"
+        );
+        let inside = PixelCoordinate { x: 3, y: 60 };
+        let nearest = fog_of_war.nearest_visible(inside).unwrap();
+        assert!(
+            nearest.contains(inside),
+            "Expected the revealed rectangle containing the pixel"
+        );
+        // brute force over every revealed leaf
+        for from in [
+            PixelCoordinate { x: 63, y: 0 },
+            PixelCoordinate { x: 20, y: 40 },
+        ] {
+            let expected = fog_of_war
+                .get_visible_rectangles()
+                .iter()
+                .map(|rectangle| rectangle.squared_distance_to(from))
+                .min();
+            assert_eq!(
+                fog_of_war
+                    .nearest_visible(from)
+                    .map(|rectangle| rectangle.squared_distance_to(from)),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_eq() {
+        let resolution = Resolution::new(
+            Coordinate { x: 0.0, y: 0.0 },
+            Coordinate { x: 4.0, y: 4.0 },
+            16,
+        );
+        let left = FoWRectangle {
+            topleft: PixelCoordinate { x: 0, y: 0 },
+            bottomright: PixelCoordinate { x: 32, y: 64 },
+        };
+        let mut split = FogOfWar::new(&resolution);
+        split.update_rect(left, Operation::Hide);
+        // the same area hidden by a polygon that ends in the middle of leaves of the first
+        let mut other = FogOfWar::new(&resolution);
+        other.update_rect(
+            FoWRectangle {
+                topleft: PixelCoordinate { x: 0, y: 0 },
+                bottomright: PixelCoordinate { x: 32, y: 40 },
+            },
+            Operation::Hide,
+        );
+        other.update_rect(
+            FoWRectangle {
+                topleft: PixelCoordinate { x: 0, y: 40 },
+                bottomright: PixelCoordinate { x: 32, y: 64 },
+            },
+            Operation::Hide,
+        );
+        assert!(split == other, "Expected the same hidden area to be equal");
+        other.update_rect(
+            FoWRectangle {
+                topleft: PixelCoordinate { x: 48, y: 48 },
+                bottomright: PixelCoordinate { x: 64, y: 64 },
+            },
+            Operation::Hide,
+        );
+        assert!(split != other, "Expected an extra hidden square to differ");
+        assert!(other != split);
+        assert!(FogOfWar::new(&resolution) != FogOfWar::with_bounds(left));
+    }
+
+    #[test]
+    fn test_is_fully_revealed() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        assert!(fog_of_war.is_fully_revealed());
+        fog_of_war.hide_all();
+        assert!(!fog_of_war.is_fully_revealed());
+        // a rectangle that does not line up with the quadtree leaves, so squares are split
+        let partial = FoWRectangle {
+            topleft: PixelCoordinate { x: 5, y: 7 },
+            bottomright: PixelCoordinate { x: 1000, y: 333 },
+        };
+        fog_of_war.update_rect(partial, Operation::Show);
+        assert!(!fog_of_war.is_fully_revealed());
+        let whole = FoWRectangle {
+            topleft: PixelCoordinate { x: 0, y: 0 },
+            bottomright: vtt.resolution().pixel_size(),
+        };
+        fog_of_war.update_rect(whole, Operation::Show);
+        assert!(
+            fog_of_war.is_fully_revealed(),
+            "Expected showing the whole image to reveal everything"
+        );
+        assert!(fog_of_war.is_leaf(), "Expected the quadtree to be merged");
+        fog_of_war.update_rect(partial, Operation::Hide);
+        let restored = FogOfWar::deserialize(&fog_of_war.serialize(), vtt.resolution())
+            .expect("Could not deserialize the fog of war");
+        assert!(!restored.is_fully_revealed());
+    }
+
+    #[test]
+    fn test_explored_fraction() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        assert_eq!(fog_of_war.explored_fraction(), 1.0);
+        fog_of_war.hide_all();
+        assert_eq!(fog_of_war.explored_fraction(), 0.0);
+        // the left third of the image, which is not a whole number of quadtree leaves
+        fog_of_war.update_rect(
+            FoWRectangle {
+                topleft: PixelCoordinate { x: 0, y: 0 },
+                bottomright: PixelCoordinate {
+                    x: 9 * 256,
+                    y: 15 * 256,
+                },
+            },
+            Operation::Show,
+        );
+        let fraction = fog_of_war.explored_fraction();
+        assert!(
+            (fraction - 1.0 / 3.0).abs() < 0.01,
+            "Expected a third of the image to be explored, found {}",
+            fraction
+        );
+    }
+
+    #[test]
+    fn test_take_changes() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut server = FogOfWar::new(vtt.resolution());
+        let mut client = FogOfWar::new(vtt.resolution());
+        assert!(server.take_changes().is_empty());
+
+        server.hide_all();
+        let delta = server.take_changes();
+        assert_eq!(delta.hidden, server.get_rectangles());
+        assert!(delta.shown.is_empty());
+        client.apply_delta(&delta);
+
+        let triangle = polygon![(x: 100.0, y: 100.0), (x: 2000.0, y: 100.0), (x: 100.0, y: 1500.0)];
+        server.update(&triangle, Operation::Show);
+        let delta = server.take_changes();
+        assert!(delta.hidden.is_empty());
+        let shown: i64 = delta
+            .shown
+            .iter()
+            .map(|r| r.width() as i64 * r.height() as i64)
+            .sum();
+        assert_eq!(shown, 27 * 15 * 256 * 256 - hidden_area(&server));
+        // the delta can be sent over the network
+        let json = serde_json::to_string(&delta).expect("Could not serialize the delta");
+        let delta: FowDelta = serde_json::from_str(&json).expect("Could not deserialize");
+        client.apply_delta(&delta);
+        assert_eq!(client.get_rectangles(), server.get_rectangles());
+
+        let square = polygon![(x: 1000.0, y: 200.0), (x: 3000.0, y: 200.0), (x: 3000.0, y: 900.0), (x: 1000.0, y: 900.0)];
+        server.update(&square, Operation::Hide);
+        client.apply_delta(&server.take_changes());
+        assert_eq!(client.get_rectangles(), server.get_rectangles());
+        assert!(server.take_changes().is_empty());
+    }
+
+    #[test]
+    fn test_serde() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution())
+            .with_min_square_size(5)
+            .unwrap();
+        fog_of_war.hide_all();
+        fog_of_war.update(
+            &polygon![(x: 10.0, y: 10.0), (x: 200.0, y: 30.0), (x: 90.0, y: 250.0)],
+            Operation::Show,
+        );
+        let json = serde_json::to_string(&fog_of_war).expect("Could not serialize the fog of war");
+        let restored: FogOfWar =
+            serde_json::from_str(&json).expect("Could not deserialize the fog of war");
+        assert_eq!(
+            restored.get_rectangles(),
+            fog_of_war.get_rectangles(),
+            "Expected the same hidden rectangles after a roundtrip"
+        );
+        assert_eq!(restored.min_square_size(), 5);
+        assert!(
+            serde_json::from_str::<FogOfWar>(r#"{"min_square_size":3,"quadtree":"AAAA"}"#).is_err(),
+            "Expected invalid data to be rejected"
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        fog_of_war.hide_all();
+        let square = polygon![(x: 300.0, y: 300.0), (x: 900.5, y: 300.0), (x: 900.5, y: 1000.0), (x: 300.0, y: 1000.0)];
+        fog_of_war.update(&square, Operation::Show);
+        let bytes = fog_of_war.serialize();
+        let restored =
+            FogOfWar::deserialize(&bytes, vtt.resolution()).expect("Could not deserialize");
+        assert_eq!(
+            restored.get_rectangles(),
+            fog_of_war.get_rectangles(),
+            "Deserialized fog of war did not match"
+        );
+
+        let other = open_vtt("tests/resources/The Pig and Whistle tavern.uvtt")
+            .expect("Could not open file the pig and whistle tavern.uvtt");
+        assert!(
+            FogOfWar::deserialize(&bytes, other.resolution()).is_err(),
+            "Expected a fog of war of a different resolution to be rejected"
+        );
+        assert!(
+            FogOfWar::deserialize(&bytes[..bytes.len() - 1], vtt.resolution()).is_err(),
+            "Expected truncated data to be rejected"
+        );
+    }
+
+    #[test]
+    fn test_remembered() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut fog_of_war = FogOfWar::new(vtt.resolution());
+        let mut client = FogOfWar::new(vtt.resolution());
+        fog_of_war.hide_all();
+        let left = FoWRectangle {
+            topleft: PixelCoordinate { x: 0, y: 0 },
+            bottomright: PixelCoordinate { x: 512, y: 512 },
+        };
+        let right = FoWRectangle {
+            topleft: PixelCoordinate { x: 512, y: 0 },
+            bottomright: PixelCoordinate { x: 1024, y: 512 },
+        };
+        fog_of_war.update_rect(left, Operation::Show);
+        client.apply_delta(&fog_of_war.take_changes());
+        fog_of_war
+            .remember_shown()
+            .update_rect(right, Operation::Show);
+        assert!(fog_of_war.is_remembered(PixelCoordinate { x: 10, y: 10 }));
+        assert_eq!(
+            fog_of_war.state(PixelCoordinate { x: 600, y: 10 }),
+            FowState::Shown
+        );
+        assert!(
+            !fog_of_war.is_hidden(PixelCoordinate { x: 10, y: 10 }),
+            "Expected a remembered pixel to stay revealed"
+        );
+
+        let delta = fog_of_war.take_changes();
+        assert_eq!(delta.remembered, fog_of_war.get_remembered_rectangles());
+        client.apply_delta(&delta);
+        assert!(
+            client == fog_of_war,
+            "Expected the delta to carry the remembered area"
+        );
+
+        let restored = FogOfWar::deserialize(&fog_of_war.serialize(), vtt.resolution())
+            .expect("Could not deserialize");
+        assert!(
+            restored == fog_of_war,
+            "Expected the remembered area to be serialized"
+        );
+
+        let mut merged = FogOfWar::new(vtt.resolution());
+        merged.hide_all().update_rect(left, Operation::Show);
+        merged.merge_visible(&fog_of_war).unwrap();
+        assert!(
+            merged.get_remembered_rectangles().is_empty(),
+            "Expected areas shown in either fog of war to be shown"
+        );
+
+        fog_of_war.show_remembered();
+        assert!(fog_of_war.get_remembered_rectangles().is_empty());
+        assert!(!fog_of_war.is_hidden(PixelCoordinate { x: 10, y: 10 }));
     }
 }
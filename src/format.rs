@@ -0,0 +1,126 @@
+//! Recognizing the format of the images embedded in vtt files. This does not need the `image`
+//! feature, so the size of an embedded PNG image can be read without the image codecs.
+use crate::errors::RustVttError;
+#[cfg(feature = "image")]
+use crate::jpeg;
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// The format of an encoded image, as recognized by [`guess_format`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Gif,
+    Bmp,
+}
+
+impl ImageFormat {
+    /// Returns the usual file extension of the format, without the dot
+    pub fn extension(&self) -> &'static str {
+        return match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Gif => "gif",
+            ImageFormat::Bmp => "bmp",
+        };
+    }
+}
+
+/// Returns true if the given bytes start with the PNG signature
+pub fn is_png(bytes: &[u8]) -> bool {
+    return bytes.starts_with(&PNG_SIGNATURE);
+}
+
+/// Returns true if the given bytes start with a JPEG start of image marker
+pub fn is_jpeg(bytes: &[u8]) -> bool {
+    return bytes.starts_with(&[0xff, 0xd8, 0xff]);
+}
+
+/// Returns the format of an encoded image based on its first bytes, or None if the format is not
+/// recognized. Only PNG and JPEG images can be decoded by this crate.
+pub fn guess_format(bytes: &[u8]) -> Option<ImageFormat> {
+    if is_png(bytes) {
+        return Some(ImageFormat::Png);
+    }
+    if is_jpeg(bytes) {
+        return Some(ImageFormat::Jpeg);
+    }
+    if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        return Some(ImageFormat::Webp);
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some(ImageFormat::Gif);
+    }
+    if bytes.starts_with(b"BM") {
+        return Some(ImageFormat::Bmp);
+    }
+    return None;
+}
+
+/// Read the width and height of a PNG or JPEG image from its header, based on its format. Returns
+/// an error for images in other formats. Reading the size of a JPEG image needs the `image`
+/// feature.
+pub fn dimensions(bytes: &[u8]) -> Result<(u32, u32), RustVttError> {
+    return match guess_format(bytes) {
+        Some(ImageFormat::Png) => png_dimensions(bytes),
+        #[cfg(feature = "image")]
+        Some(ImageFormat::Jpeg) => jpeg::dimensions(bytes),
+        Some(format) => Err(RustVttError::ImageDecode {
+            reason: format!("{:?} images are not supported", format),
+        }),
+        None => Err(RustVttError::ImageDecode {
+            reason: "the image format is not recognized".to_string(),
+        }),
+    };
+}
+
+/// Read the width and height of a PNG image from its header, without decoding the image. Only the
+/// start of the image up to the end of the IHDR chunk is needed.
+pub fn png_dimensions(bytes: &[u8]) -> Result<(u32, u32), RustVttError> {
+    if !is_png(bytes) {
+        return Err(decode_error("the image is not a PNG image"));
+    }
+    let position = PNG_SIGNATURE.len();
+    match bytes.get(position + 4..position + 16) {
+        Some([b'I', b'H', b'D', b'R', size @ ..]) => {
+            let width = u32::from_be_bytes(size[0..4].try_into().unwrap());
+            let height = u32::from_be_bytes(size[4..8].try_into().unwrap());
+            return Ok((width, height));
+        }
+        Some(_) => return Err(decode_error("missing IHDR chunk")),
+        None => return Err(decode_error("unexpected end of file")),
+    }
+}
+
+fn decode_error(reason: &str) -> RustVttError {
+    return RustVttError::ImageDecode {
+        reason: reason.to_string(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{guess_format, ImageFormat, PNG_SIGNATURE};
+
+    #[test]
+    fn test_guess_format() {
+        assert_eq!(guess_format(&PNG_SIGNATURE), Some(ImageFormat::Png));
+        assert_eq!(
+            guess_format(&[0xff, 0xd8, 0xff, 0xe0, 0, 0x10]),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(
+            guess_format(b"RIFF\x10\0\0\0WEBPVP8 "),
+            Some(ImageFormat::Webp)
+        );
+        assert_eq!(guess_format(b"GIF89a"), Some(ImageFormat::Gif));
+        assert_eq!(
+            guess_format(b"not an image"),
+            None,
+            "Expected unknown data to not be recognized"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! Gzip support for compressed vtt files such as `.dd2vtt.gz`, using the flate2 crate.
+use std::io::Read;
+
+use flate2::read::MultiGzDecoder;
+
+use crate::errors::RustVttError;
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Returns true if the given bytes start with the gzip magic bytes
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    return bytes.starts_with(&MAGIC);
+}
+
+/// Decompress a gzip stream. Files made of multiple gzip members are decompressed as one.
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>, RustVttError> {
+    let mut data = Vec::new();
+    MultiGzDecoder::new(bytes)
+        .read_to_end(&mut data)
+        .map_err(|error| RustVttError::InvalidGzip {
+            reason: error.to_string(),
+        })?;
+    return Ok(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression, GzBuilder};
+
+    use crate::errors::RustVttError;
+    use crate::gzip::{decompress, is_gzip};
+    use crate::open_vtt_from_bytes;
+
+    /// Compress data into a gzip stream with a file name
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzBuilder::new()
+            .filename("example1.dd2vtt")
+            .write(Vec::new(), Compression::default());
+        encoder.write_all(data).expect("Could not compress");
+        return encoder.finish().expect("Could not compress");
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 241) as u8).collect();
+        let compressed = gzip(&data);
+        assert!(is_gzip(&compressed), "Expected gzip magic bytes");
+        let decompressed = decompress(&compressed).expect("Could not decompress");
+        assert_eq!(decompressed, data, "Decompressed data did not match");
+    }
+
+    #[test]
+    fn test_gzip_multiple_members() {
+        let mut compressed = gzip(b"some vtt ");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(b"data").expect("Could not compress");
+        compressed.extend(encoder.finish().expect("Could not compress"));
+        let decompressed = decompress(&compressed).expect("Could not decompress");
+        assert_eq!(decompressed, b"some vtt data");
+    }
+
+    #[test]
+    fn test_gzip_truncated() {
+        let compressed = gzip(b"some vtt data");
+        let result = decompress(&compressed[..compressed.len() - 3]);
+        assert!(
+            matches!(result, Err(RustVttError::InvalidGzip { .. })),
+            "Expected a truncated stream to be rejected"
+        );
+    }
+
+    #[test]
+    fn test_open_gzip_vtt() {
+        let bytes = std::fs::read("tests/resources/example1.dd2vtt")
+            .expect("Could not read file example1.dd2vtt");
+        let vtt = open_vtt_from_bytes(&gzip(&bytes)).expect("Could not parse gzip vtt");
+        assert_eq!(vtt.pixels_per_grid(), 256, "pixels per grid did not match");
+        let Err(error) = open_vtt_from_bytes(&[0x1f, 0x8b, 8, 0, 0]) else {
+            panic!("Expected an invalid gzip stream to be rejected");
+        };
+        assert!(
+            matches!(error, RustVttError::InvalidGzip { .. }),
+            "Expected an InvalidGzip error, found {}",
+            error
+        );
+    }
+}
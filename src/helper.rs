@@ -1,9 +1,37 @@
-use geo::Line;
+#[cfg(feature = "image")]
+use std::collections::BTreeSet;
+use std::collections::{HashMap, HashSet};
 
+use geo::{
+    line_intersection::line_intersection, orient::Direction, Area, Contains, Coord, Line,
+    LineIntersection, LineString, Orient, Polygon, Rect,
+};
+#[cfg(feature = "image")]
+use image::{imageops, GrayImage};
+#[cfg(feature = "image")]
+use imageproc::filter::box_filter;
+use rayon::prelude::*;
+
+#[cfg(feature = "image")]
+use crate::fog_of_war::FoWRectangle;
 use crate::vtt::Coordinate;
 
+/// Precision used to snap coordinates to the same vertex when building a planar graph
+const VERTEX_PRECISION: f64 = 1e6;
+/// The default distance in squares between the points on the map border that line of sight rays
+/// are cast to
+pub const STEP_SIZE: f64 = 0.2;
+/// The number of times the progress of the line of sight is reported, see
+/// [`calculate_direct_los_with_progress`]
+const PROGRESS_STEPS: usize = 20;
+/// The size in pixels of the tiles that the edges of the fog of war are blurred in, see
+/// [`blur_fow_edges`]
+#[cfg(feature = "image")]
+const BLUR_TILE: i32 = 64;
+
 /// Helper function: In essence this calculates the distance between a point and the max or minimum
 /// boundary.
+#[allow(dead_code)]
 pub fn checked_div(numerator: f64, denominator: f64) -> Option<f64> {
     if denominator == 0.0 {
         return None;
@@ -15,7 +43,39 @@ pub fn checked_div(numerator: f64, denominator: f64) -> Option<f64> {
     Some(fraction)
 }
 
-/// Given a line_of_sight parameter this will return a Vec of all line segments
+/// Parse a hex color string as used in vtt files to its red, green, blue and alpha components.
+/// Both `rrggbb` and `aarrggbb` are accepted, optionally prefixed with a `#`. Colors without alpha
+/// are fully opaque. Returns None if the string is not a valid color.
+pub fn parse_hex_rgba(color: &str) -> Option<[u8; 4]> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    return match hex.len() {
+        6 => Some([component(0)?, component(2)?, component(4)?, u8::MAX]),
+        8 => Some([component(2)?, component(4)?, component(6)?, component(0)?]),
+        _ => None,
+    };
+}
+
+/// Parse a hex color string like [`parse_hex_rgba`] to its red, green and blue components. The
+/// alpha component is ignored.
+pub fn parse_hex_color(color: &str) -> Option<[u8; 3]> {
+    let [r, g, b, _] = parse_hex_rgba(color)?;
+    Some([r, g, b])
+}
+
+/// Normalize a hex color string to the lowercase `aarrggbb` form written by current versions of
+/// Dungeondraft. Colors without alpha get full opacity. Returns None if the string is not a valid
+/// color.
+pub fn normalize_hex_color(color: &str) -> Option<String> {
+    let [r, g, b, a] = parse_hex_rgba(color)?;
+    return Some(format!("{:02x}{:02x}{:02x}{:02x}", a, r, g, b));
+}
+
+/// Given a line_of_sight parameter this will return a Vec of all line segments. Segments that
+/// coincide with an earlier segment are left out, see [`dedup_segments`].
 pub fn get_line_segments(line_of_sight_elements: Vec<Vec<Coordinate>>) -> Vec<Line> {
     let mut all_lines: Vec<Line> = Vec::new();
     for lines in line_of_sight_elements {
@@ -27,15 +87,561 @@ pub fn get_line_segments(line_of_sight_elements: Vec<Vec<Coordinate>>) -> Vec<Li
             prev_coord = Some(coordinate);
         }
     }
+    dedup_segments(&mut all_lines);
     all_lines
 }
 
+/// Removes every segment that has the same endpoints as an earlier segment, in either direction,
+/// within the precision used for the vertices of the planar graph. Exports often repeat an edge,
+/// e.g. where a wall and an object share it, which only slows down the line of sight.
+pub fn dedup_segments(segments: &mut Vec<Line>) {
+    let mut seen: HashSet<[(i64, i64); 2]> = HashSet::with_capacity(segments.len());
+    segments.retain(|segment| {
+        let mut key = [snap(segment.start), snap(segment.end)];
+        key.sort_unstable();
+        return seen.insert(key);
+    });
+}
+
+/// Helper function: rounds a coordinate to the precision used for the vertices of the planar
+/// graph
+fn snap(coord: Coord) -> (i64, i64) {
+    return (
+        (coord.x * VERTEX_PRECISION).round() as i64,
+        (coord.y * VERTEX_PRECISION).round() as i64,
+    );
+}
+
+/// Given a set of line segments, this will return all enclosed faces of the planar graph formed by
+/// these segments. Segments are split at every point where they cross or touch another segment, so
+/// the lines do not need to share endpoints. The unbounded outer face of every connected part of
+/// the graph is not returned, but it is used as a hole for the face it lies in.
+pub fn get_planar_faces(lines: &[Line]) -> Vec<Polygon> {
+    let mut vertices: Vec<Coord> = Vec::new();
+    let mut vertex_ids: HashMap<(i64, i64), usize> = HashMap::new();
+    let mut vertex_id = |coord: Coord| -> usize {
+        let key = snap(coord);
+        *vertex_ids.entry(key).or_insert_with(|| {
+            vertices.push(coord);
+            vertices.len() - 1
+        })
+    };
+
+    // Split every line at the points where it intersects other lines
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let mut splits: Vec<(f64, Coord)> = vec![(0.0, line.start), (1.0, line.end)];
+        for (j, other) in lines.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            match line_intersection(*line, *other) {
+                Some(LineIntersection::SinglePoint { intersection, .. }) => {
+                    splits.push((line_fraction(line, intersection), intersection));
+                }
+                Some(LineIntersection::Collinear { intersection }) => {
+                    splits.push((line_fraction(line, intersection.start), intersection.start));
+                    splits.push((line_fraction(line, intersection.end), intersection.end));
+                }
+                None => {}
+            }
+        }
+        splits.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let ids: Vec<usize> = splits.into_iter().map(|(_, c)| vertex_id(c)).collect();
+        for pair in ids.windows(2) {
+            if pair[0] != pair[1] {
+                edges.push((pair[0].min(pair[1]), pair[0].max(pair[1])));
+            }
+        }
+    }
+    edges.sort_unstable();
+    edges.dedup();
+
+    // Sort the neighbours of every vertex counterclockwise by angle
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+    for &(a, b) in &edges {
+        neighbours[a].push(b);
+        neighbours[b].push(a);
+    }
+    let angle = |from: usize, to: usize| -> f64 {
+        let d = vertices[to] - vertices[from];
+        d.y.atan2(d.x)
+    };
+    for (v, adjacent) in neighbours.iter_mut().enumerate() {
+        adjacent.sort_by(|&a, &b| angle(v, a).total_cmp(&angle(v, b)));
+    }
+
+    // Walk every half edge, always taking the next edge clockwise from the one we came from. This
+    // traces enclosed faces counterclockwise and the outer faces clockwise.
+    let mut visited: HashSet<(usize, usize)> = HashSet::new();
+    let mut faces: Vec<LineString> = Vec::new();
+    let mut outer_faces: Vec<LineString> = Vec::new();
+    for &(a, b) in &edges {
+        for start in [(a, b), (b, a)] {
+            if visited.contains(&start) {
+                continue;
+            }
+            let mut ring: Vec<usize> = Vec::new();
+            let (mut from, mut to) = start;
+            while visited.insert((from, to)) {
+                ring.push(from);
+                let adjacent = &neighbours[to];
+                let index = adjacent
+                    .iter()
+                    .position(|&n| n == from)
+                    .expect("expected the graph to be undirected");
+                let next = adjacent[(index + adjacent.len() - 1) % adjacent.len()];
+                (from, to) = (to, next);
+            }
+            let ring = remove_spikes(ring);
+            if ring.len() < 3 {
+                continue;
+            }
+            let exterior = LineString::from(ring.iter().map(|&v| vertices[v]).collect::<Vec<_>>());
+            let signed_area = Polygon::new(exterior.clone(), vec![]).signed_area();
+            if signed_area > 0.0 {
+                faces.push(exterior);
+            } else if signed_area < 0.0 {
+                outer_faces.push(exterior);
+            }
+        }
+    }
+
+    // The outer face of a part of the graph that lies inside an enclosed face is a hole of the
+    // smallest enclosed face that contains it
+    let mut holes: Vec<Vec<LineString>> = vec![Vec::new(); faces.len()];
+    for outer in outer_faces {
+        let outer_polygon = Polygon::new(outer.clone(), vec![]);
+        let parent = faces
+            .iter()
+            .enumerate()
+            .filter(|(_, face)| {
+                let face = Polygon::new((*face).clone(), vec![]);
+                face.unsigned_area() > outer_polygon.unsigned_area()
+                    && face.contains(&outer_polygon)
+            })
+            .min_by(|(_, a), (_, b)| {
+                let a = Polygon::new((*a).clone(), vec![]).unsigned_area();
+                let b = Polygon::new((*b).clone(), vec![]).unsigned_area();
+                a.total_cmp(&b)
+            })
+            .map(|(i, _)| i);
+        if let Some(parent) = parent {
+            holes[parent].push(outer);
+        }
+    }
+
+    faces
+        .into_iter()
+        .zip(holes)
+        .map(|(exterior, interiors)| Polygon::new(exterior, interiors))
+        .collect()
+}
+
+/// Calculate the area that a person standing at `pov` can see when they can look around walls
+/// perfectly: the smallest enclosed area of the planar graph formed by the wall segments that
+/// contains the pov. Returns None if the pov is not enclosed by the walls. The exterior of the
+/// area is oriented like [`Direction::Default`].
+pub fn calculate_indirect_los(pov: Coord, wall_segments: &[Line]) -> Option<Polygon> {
+    let room = get_planar_faces(wall_segments)
+        .into_iter()
+        .filter(|face| face.contains(&pov))
+        .min_by(|a, b| a.unsigned_area().total_cmp(&b.unsigned_area()))
+        .map(|room| room.orient(Direction::Default));
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        ?pov,
+        wall_segments = wall_segments.len(),
+        vertices = ?room.as_ref().map(|room| room.exterior().0.len()),
+        "indirect line of sight"
+    );
+    return room;
+}
+
+/// Calculate the area that a person standing at `pov` can see in a straight line within `bounds`.
+/// Rays are cast from the pov to points every `step_size` along the border of the bounds, so a
+/// smaller step size gives smoother edges at the cost of more rays. The rays are cast in parallel,
+/// the result is the same as collecting the rays of
+/// [`for_each_interesection`][crate::helper::for_each_interesection]. The exterior of the area is
+/// oriented like [`Direction::Default`], so every line of sight has the same winding.
+pub fn calculate_direct_los(
+    pov: Coord,
+    wall_segments: &[Line],
+    bounds: Rect,
+    step_size: f64,
+) -> Polygon {
+    return direct_los(pov, wall_segments, bounds, step_size, None);
+}
+
+/// Calculate the area that a person standing at `pov` can see like [`calculate_direct_los`],
+/// calling `progress` with the fraction of the rays that have been cast so far, from just above
+/// 0 up to 1. The rays are cast in parallel in batches, so progress is reported about 20 times.
+pub fn calculate_direct_los_with_progress(
+    pov: Coord,
+    wall_segments: &[Line],
+    bounds: Rect,
+    step_size: f64,
+    progress: &mut dyn FnMut(f32),
+) -> Polygon {
+    return direct_los(pov, wall_segments, bounds, step_size, Some(progress));
+}
+
+/// Helper function: calculates the direct line of sight, where the rays are only cast in batches
+/// when there is a progress callback to report to.
+fn direct_los(
+    pov: Coord,
+    wall_segments: &[Line],
+    bounds: Rect,
+    step_size: f64,
+    progress: Option<&mut dyn FnMut(f32)>,
+) -> Polygon {
+    let targets = ray_targets(pov, wall_segments, bounds, step_size);
+    let cast = |&target: &Coord| find_intersection(Line::new(pov, target), wall_segments);
+    // collecting or extending from an indexed iterator preserves the clockwise order of the targets
+    let points: Vec<Coord> = match progress {
+        None => targets.par_iter().map(cast).collect(),
+        Some(progress) => {
+            let batch_size = targets.len().div_ceil(PROGRESS_STEPS).max(1);
+            let mut points = Vec::with_capacity(targets.len());
+            for batch in targets.chunks(batch_size) {
+                points.par_extend(batch.par_iter().map(cast));
+                progress(points.len() as f32 / targets.len() as f32);
+            }
+            points
+        }
+    };
+    // the rays follow the border, but a pov close to a corner can still give the opposite winding
+    let los = Polygon::new(LineString::from(points), vec![]).orient(Direction::Default);
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        ?pov,
+        vertices = los.exterior().0.len(),
+        "direct line of sight"
+    );
+    return los;
+}
+
+/// Casts a ray from `pov` to points every `step_size` along the border of `bounds`, clockwise
+/// starting at the top left corner, and calls `f` with every ray cut off at the first wall it
+/// hits.
+pub fn for_each_interesection<F: FnMut(Line)>(
+    pov: Coord,
+    wall_segments: &[Line],
+    bounds: Rect,
+    step_size: f64,
+    mut f: F,
+) {
+    for target in ray_targets(pov, wall_segments, bounds, step_size) {
+        let ray = Line::new(pov, target);
+        f(Line::new(pov, find_intersection(ray, wall_segments)));
+    }
+}
+
+/// Helper function: returns the targets of the rays cast from `pov`, see [`border_points`]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn ray_targets(pov: Coord, wall_segments: &[Line], bounds: Rect, step_size: f64) -> Vec<Coord> {
+    let targets = border_points(bounds, step_size);
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        ?pov,
+        rays = targets.len(),
+        wall_segments = wall_segments.len(),
+        "casting rays"
+    );
+    return targets;
+}
+
+/// Helper function: returns points every `step_size` along the border of `bounds`, clockwise
+/// starting at the top left corner.
+fn border_points(bounds: Rect, step_size: f64) -> Vec<Coord> {
+    let (min, max) = (bounds.min(), bounds.max());
+    let corners = [
+        min,
+        Coord { x: max.x, y: min.y },
+        max,
+        Coord { x: min.x, y: max.y },
+    ];
+    let mut points: Vec<Coord> = Vec::new();
+    for i in 0..corners.len() {
+        let edge = Line::new(corners[i], corners[(i + 1) % corners.len()]);
+        let length = edge.dx().hypot(edge.dy());
+        let steps = (length / step_size).ceil().max(1.0) as usize;
+        points.extend(
+            (0..steps).map(|step| edge.start + edge.delta() * (step as f64 / steps as f64)),
+        );
+    }
+    return points;
+}
+
+/// Blur the edges of the hidden rectangles of the fog of war over the given radius in pixels, on an
+/// image of `width` by `height` pixels. The image is split into tiles and only the tiles within the
+/// radius of the edge of a rectangle are blurred, where tiles whose surroundings are completely
+/// hidden or completely shown are skipped. Returns the top left corner of every blurred tile with
+/// its mask, where 255 is fully hidden and 0 is fully shown.
+#[cfg(feature = "image")]
+pub fn blur_fow_edges(
+    hidden: &[FoWRectangle],
+    width: u32,
+    height: u32,
+    radius: u32,
+) -> Vec<(u32, u32, GrayImage)> {
+    let (width, height, radius) = (width as i32, height as i32, radius as i32);
+    // the rectangles as (left, top, right, bottom), limited to the image
+    let hidden: Vec<(i32, i32, i32, i32)> = hidden
+        .iter()
+        .map(|rectangle| {
+            (
+                rectangle.topleft.x.clamp(0, width),
+                rectangle.topleft.y.clamp(0, height),
+                rectangle.bottomright.x.clamp(0, width),
+                rectangle.bottomright.y.clamp(0, height),
+            )
+        })
+        .filter(|&(left, top, right, bottom)| left < right && top < bottom)
+        .collect();
+    let mut tiles = BTreeSet::new();
+    for &(left, top, right, bottom) in &hidden {
+        // edges on the border of the image are never blurred
+        let edges = [
+            (left > 0).then_some((left, top, left, bottom)),
+            (right < width).then_some((right, top, right, bottom)),
+            (top > 0).then_some((left, top, right, top)),
+            (bottom < height).then_some((left, bottom, right, bottom)),
+        ];
+        for (min_x, min_y, max_x, max_y) in edges.into_iter().flatten() {
+            let columns =
+                (min_x - radius).max(0) / BLUR_TILE..=(max_x + radius).min(width - 1) / BLUR_TILE;
+            let rows =
+                (min_y - radius).max(0) / BLUR_TILE..=(max_y + radius).min(height - 1) / BLUR_TILE;
+            for row in rows {
+                tiles.extend(columns.clone().map(|column| (column, row)));
+            }
+        }
+    }
+    let tiles: Vec<(i32, i32)> = tiles.into_iter().collect();
+    return tiles
+        .par_iter()
+        .filter_map(|&(column, row)| {
+            let (x, y) = (column * BLUR_TILE, row * BLUR_TILE);
+            let tile_width = BLUR_TILE.min(width - x);
+            let tile_height = BLUR_TILE.min(height - y);
+            // the tile with every pixel within the radius around it
+            let left = (x - radius).max(0);
+            let top = (y - radius).max(0);
+            let right = (x + tile_width + radius).min(width);
+            let bottom = (y + tile_height + radius).min(height);
+            let window_width = (right - left) as usize;
+            let mut mask = vec![0u8; window_width * (bottom - top) as usize];
+            for &(min_x, min_y, max_x, max_y) in &hidden {
+                let (min_x, max_x) = (min_x.clamp(left, right), max_x.clamp(left, right));
+                let (min_y, max_y) = (min_y.clamp(top, bottom), max_y.clamp(top, bottom));
+                for mask_row in (min_y - top) as usize..(max_y - top) as usize {
+                    let start = mask_row * window_width;
+                    mask[start + (min_x - left) as usize..start + (max_x - left) as usize]
+                        .fill(u8::MAX);
+                }
+            }
+            if mask.iter().all(|&value| value == mask[0]) {
+                return None;
+            }
+            let mask = GrayImage::from_raw(window_width as u32, (bottom - top) as u32, mask)
+                .expect("the mask always matches the size of the window");
+            let blurred = box_filter(&mask, radius as u32, radius as u32);
+            let tile = imageops::crop_imm(
+                &blurred,
+                (x - left) as u32,
+                (y - top) as u32,
+                tile_width as u32,
+                tile_height as u32,
+            )
+            .to_image();
+            return Some((x as u32, y as u32, tile));
+        })
+        .collect();
+}
+
+/// Returns the point where the ray first hits one of the walls, or the end of the ray if it does
+/// not hit any wall. A ray that runs along a wall is stopped where it first touches the wall,
+/// which is the end of the overlap closest to the start of the ray regardless of the direction of
+/// the wall. If the start of the ray lies on a wall, the ray is stopped at its start.
+pub fn find_intersection(ray: Line, wall_segments: &[Line]) -> Coord {
+    let mut closest = ray.end;
+    let mut closest_fraction = 1.0;
+    for wall in wall_segments {
+        let point = match line_intersection(ray, *wall) {
+            Some(LineIntersection::SinglePoint { intersection, .. }) => intersection,
+            // the ray runs along the wall, it is stopped by the end of the wall closest to the pov
+            Some(LineIntersection::Collinear { intersection }) => {
+                if line_fraction(&ray, intersection.start) <= line_fraction(&ray, intersection.end)
+                {
+                    intersection.start
+                } else {
+                    intersection.end
+                }
+            }
+            None => continue,
+        };
+        let fraction = line_fraction(&ray, point);
+        if fraction < closest_fraction {
+            closest = point;
+            closest_fraction = fraction;
+        }
+    }
+    return closest;
+}
+
+/// Returns the part of the line that lies inside the rectangle, or None if the line lies entirely
+/// outside of it.
+#[cfg(feature = "image")]
+pub fn clip_line(line: Line, bounds: Rect) -> Option<Line> {
+    let (min, max) = (bounds.min(), bounds.max());
+    let delta = line.delta();
+    let (mut start, mut end): (f64, f64) = (0.0, 1.0);
+    // Liang-Barsky: every edge of the rectangle limits the fraction of the line that lies inside
+    for (direction, distance) in [
+        (-delta.x, line.start.x - min.x),
+        (delta.x, max.x - line.start.x),
+        (-delta.y, line.start.y - min.y),
+        (delta.y, max.y - line.start.y),
+    ] {
+        if direction == 0.0 {
+            if distance < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let fraction = distance / direction;
+        if direction < 0.0 {
+            start = start.max(fraction);
+        } else {
+            end = end.min(fraction);
+        }
+    }
+    if start > end {
+        return None;
+    }
+    return Some(Line::new(
+        line.start + delta * start,
+        line.start + delta * end,
+    ));
+}
+
+/// Returns the parts of a wall through the given points that lie inside the rectangle. A wall
+/// that leaves the rectangle and enters it again is split into multiple walls.
+#[cfg(feature = "image")]
+pub fn clip_polyline(points: &[Coordinate], bounds: Rect) -> Vec<Vec<Coordinate>> {
+    let mut walls: Vec<Vec<Coordinate>> = Vec::new();
+    let mut current: Vec<Coord> = Vec::new();
+    let mut finish = |current: &mut Vec<Coord>| {
+        if current.len() >= 2 {
+            walls.push(current.drain(..).map(Coordinate::from).collect());
+        }
+        current.clear();
+    };
+    for pair in points.windows(2) {
+        let line = Line::new(Coord::from(pair[0].clone()), Coord::from(pair[1].clone()));
+        let clipped = clip_line(line, bounds).filter(|clipped| clipped.start != clipped.end);
+        let Some(clipped) = clipped else {
+            finish(&mut current);
+            continue;
+        };
+        if current.last() != Some(&clipped.start) {
+            finish(&mut current);
+            current.push(clipped.start);
+        }
+        current.push(clipped.end);
+        if clipped.end != line.end {
+            finish(&mut current);
+        }
+    }
+    finish(&mut current);
+    return walls;
+}
+
+/// Returns the parts of the horizontal line at height `y` that lie inside the polygon, as pairs of
+/// the start and end x coordinate ordered from left to right.
+#[cfg(feature = "image")]
+pub fn scanline_spans(polygon: &Polygon, y: f64) -> Vec<(f64, f64)> {
+    let mut crossings: Vec<f64> = polygon
+        .exterior()
+        .lines()
+        .chain(polygon.interiors().iter().flat_map(|ring| ring.lines()))
+        // an edge includes its lower end but not its upper end, so a vertex is counted once
+        .filter(|edge| (edge.start.y <= y) != (edge.end.y <= y))
+        .map(|edge| edge.start.x + (y - edge.start.y) / edge.dy() * edge.dx())
+        .collect();
+    crossings.sort_by(f64::total_cmp);
+    return crossings
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect();
+}
+
+/// Helper function: returns how far along the line the given point lies, where 0.0 is the start
+/// and 1.0 is the end of the line.
+fn line_fraction(line: &Line, point: Coord) -> f64 {
+    let delta = line.delta();
+    let length_squared = delta.x * delta.x + delta.y * delta.y;
+    if length_squared == 0.0 {
+        return 0.0;
+    }
+    let offset = point - line.start;
+    (offset.x * delta.x + offset.y * delta.y) / length_squared
+}
+
+/// Helper function: removes dead ends (a -> b -> a) from a closed ring of vertex ids. Dead ends
+/// are created by walls that do not connect to anything on one side.
+fn remove_spikes(mut ring: Vec<usize>) -> Vec<usize> {
+    let mut changed = true;
+    while changed && ring.len() >= 3 {
+        changed = false;
+        let len = ring.len();
+        for i in 0..len {
+            if ring[i] == ring[(i + 2) % len] {
+                // remove the tip of the spike and one of the duplicate vertices
+                let tip = (i + 1) % len;
+                let duplicate = (i + 2) % len;
+                let (first, second) = (tip.max(duplicate), tip.min(duplicate));
+                ring.remove(first);
+                ring.remove(second);
+                changed = true;
+                break;
+            }
+        }
+    }
+    ring
+}
+
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "image")]
+    use crate::fog_of_war::FoWRectangle;
+    #[cfg(feature = "image")]
+    use crate::helper::blur_fow_edges;
+    use crate::helper::calculate_direct_los;
+    use crate::helper::calculate_direct_los_with_progress;
+    use crate::helper::calculate_indirect_los;
     use crate::helper::checked_div;
+    #[cfg(feature = "image")]
+    use crate::helper::clip_line;
+    #[cfg(feature = "image")]
+    use crate::helper::clip_polyline;
+    use crate::helper::dedup_segments;
+    use crate::helper::find_intersection;
+    use crate::helper::for_each_interesection;
     use crate::helper::get_line_segments;
+    use crate::helper::get_planar_faces;
+    use crate::helper::normalize_hex_color;
+    use crate::helper::parse_hex_color;
+    use crate::helper::parse_hex_rgba;
+    #[cfg(feature = "image")]
+    use crate::helper::scanline_spans;
     use crate::vtt::Coordinate;
-    use geo::Line;
+    #[cfg(feature = "image")]
+    use crate::vtt::PixelCoordinate;
+    use geo::{Area, Contains, Coord, Line, Rect};
+    #[cfg(feature = "image")]
+    use geo::{LineString, Polygon};
 
     #[test]
     fn test_checked_div() {
@@ -127,6 +733,32 @@ mod tests {
         assert_eq!(result, expected, "Expected segments from multiple lists");
     }
 
+    #[test]
+    fn test_duplicate_segments() {
+        let wall = vec![Coordinate { x: 0.0, y: 0.0 }, Coordinate { x: 1.0, y: 1.0 }];
+        let result = get_line_segments(vec![wall.clone(), wall]);
+        assert_eq!(
+            result.len(),
+            1,
+            "Expected identical walls to give one segment"
+        );
+
+        let mut segments = vec![
+            Line::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 2.0, y: 0.0 }),
+            Line::new(Coord { x: 2.0, y: 1e-9 }, Coord { x: 0.0, y: 0.0 }),
+            Line::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 2.0, y: 0.5 }),
+        ];
+        dedup_segments(&mut segments);
+        assert_eq!(
+            segments,
+            vec![
+                Line::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 2.0, y: 0.0 }),
+                Line::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 2.0, y: 0.5 }),
+            ],
+            "Expected reversed and nearly equal segments to be removed"
+        );
+    }
+
     #[test]
     fn test_single_point_list() {
         let input = vec![vec![Coordinate { x: 0.0, y: 0.0 }]];
@@ -160,4 +792,482 @@ mod tests {
             "Expected segments for mixed lists with varying points"
         );
     }
+
+    #[test]
+    fn test_planar_faces_square() {
+        let input = vec![vec![
+            Coordinate { x: 0.0, y: 0.0 },
+            Coordinate { x: 4.0, y: 0.0 },
+            Coordinate { x: 4.0, y: 4.0 },
+            Coordinate { x: 0.0, y: 4.0 },
+            Coordinate { x: 0.0, y: 0.0 },
+        ]];
+        let faces = get_planar_faces(&get_line_segments(input));
+        assert_eq!(
+            faces.len(),
+            1,
+            "Expected a single room, found {}",
+            faces.len()
+        );
+        assert!(
+            (faces[0].unsigned_area() - 16.0).abs() < 1e-9,
+            "Expected a room of area 16.0, found {}",
+            faces[0].unsigned_area()
+        );
+    }
+
+    #[test]
+    fn test_planar_faces_split_by_crossing_wall() {
+        // The dividing wall sticks out of the square and does not share any endpoints with it
+        let input = vec![
+            vec![
+                Coordinate { x: 0.0, y: 0.0 },
+                Coordinate { x: 4.0, y: 0.0 },
+                Coordinate { x: 4.0, y: 4.0 },
+                Coordinate { x: 0.0, y: 4.0 },
+                Coordinate { x: 0.0, y: 0.0 },
+            ],
+            vec![
+                Coordinate { x: 1.0, y: -1.0 },
+                Coordinate { x: 1.0, y: 5.0 },
+            ],
+            vec![Coordinate { x: 2.0, y: 2.0 }, Coordinate { x: 3.0, y: 2.0 }],
+        ];
+        let mut areas: Vec<f64> = get_planar_faces(&get_line_segments(input))
+            .iter()
+            .map(|face| face.unsigned_area())
+            .collect();
+        areas.sort_by(f64::total_cmp);
+        assert_eq!(areas.len(), 2, "Expected two rooms, found {:?}", areas);
+        assert!(
+            (areas[0] - 4.0).abs() < 1e-9,
+            "Expected area 4.0, found {}",
+            areas[0]
+        );
+        assert!(
+            (areas[1] - 12.0).abs() < 1e-9,
+            "Expected area 12.0, found {}",
+            areas[1]
+        );
+    }
+
+    #[test]
+    fn test_planar_faces_hole() {
+        let input = vec![
+            vec![
+                Coordinate { x: 0.0, y: 0.0 },
+                Coordinate { x: 4.0, y: 0.0 },
+                Coordinate { x: 4.0, y: 4.0 },
+                Coordinate { x: 0.0, y: 4.0 },
+                Coordinate { x: 0.0, y: 0.0 },
+            ],
+            vec![
+                Coordinate { x: 1.0, y: 1.0 },
+                Coordinate { x: 2.0, y: 1.0 },
+                Coordinate { x: 2.0, y: 2.0 },
+                Coordinate { x: 1.0, y: 2.0 },
+                Coordinate { x: 1.0, y: 1.0 },
+            ],
+        ];
+        let faces = get_planar_faces(&get_line_segments(input));
+        assert_eq!(
+            faces.len(),
+            2,
+            "Expected the room and the pillar, found {}",
+            faces.len()
+        );
+        let room = faces
+            .iter()
+            .find(|face| !face.interiors().is_empty())
+            .expect("Expected the pillar to be a hole in the room");
+        assert!(
+            (room.unsigned_area() - 15.0).abs() < 1e-9,
+            "Expected a room of area 15.0, found {}",
+            room.unsigned_area()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_clip_line() {
+        let bounds = Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 10.0, y: 10.0 });
+        let line = Line::new(Coord { x: -5.0, y: 5.0 }, Coord { x: 5.0, y: 15.0 });
+        assert_eq!(
+            clip_line(line, bounds),
+            Some(Line::new(
+                Coord { x: 0.0, y: 10.0 },
+                Coord { x: 0.0, y: 10.0 }
+            ))
+        );
+        let line = Line::new(Coord { x: -5.0, y: 2.0 }, Coord { x: 15.0, y: 2.0 });
+        assert_eq!(
+            clip_line(line, bounds),
+            Some(Line::new(
+                Coord { x: 0.0, y: 2.0 },
+                Coord { x: 10.0, y: 2.0 }
+            ))
+        );
+        let inside = Line::new(Coord { x: 1.0, y: 1.0 }, Coord { x: 3.0, y: 4.0 });
+        assert_eq!(clip_line(inside, bounds), Some(inside));
+        let outside = Line::new(Coord { x: 11.0, y: 1.0 }, Coord { x: 13.0, y: 4.0 });
+        assert_eq!(clip_line(outside, bounds), None);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_clip_polyline() {
+        let bounds = Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 10.0, y: 10.0 });
+        let coordinate = |x: f64, y: f64| Coordinate { x, y };
+        // a wall that leaves the rectangle on the right and enters it again
+        let wall = vec![
+            coordinate(2.0, 2.0),
+            coordinate(12.0, 2.0),
+            coordinate(12.0, 6.0),
+            coordinate(2.0, 6.0),
+            coordinate(2.0, 8.0),
+        ];
+        let clipped: Vec<Vec<(f64, f64)>> = clip_polyline(&wall, bounds)
+            .iter()
+            .map(|wall| wall.iter().map(|c| (c.x, c.y)).collect())
+            .collect();
+        assert_eq!(
+            clipped,
+            vec![
+                vec![(2.0, 2.0), (10.0, 2.0)],
+                vec![(10.0, 6.0), (2.0, 6.0), (2.0, 8.0)],
+            ],
+            "Expected the wall to be split at the edge of the rectangle"
+        );
+    }
+
+    #[test]
+    fn test_parse_hex_color() {
+        let cases = vec![
+            ("ff575112", Some([0x57, 0x51, 0x12])),
+            ("ffFFE5BF", Some([0xff, 0xe5, 0xbf])),
+            ("#eccd8b", Some([0xec, 0xcd, 0x8b])),
+            ("eccd8b", Some([0xec, 0xcd, 0x8b])),
+            ("#ff000000", Some([0, 0, 0])),
+            ("fff", None),
+            ("ggcd8b", None),
+            ("", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_hex_color(input),
+                expected,
+                "Failed to parse color {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_hex_rgba() {
+        let cases = vec![
+            ("ff0000", Some([0xff, 0, 0, 0xff])),
+            ("#ff0000", Some([0xff, 0, 0, 0xff])),
+            ("80ff0000", Some([0xff, 0, 0, 0x80])),
+            ("#80FF0000", Some([0xff, 0, 0, 0x80])),
+            ("+f0000", None),
+            ("ff00000", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_hex_rgba(input),
+                expected,
+                "Failed to parse color {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_hex_color() {
+        let cases = vec![
+            ("ffFFE5BF", Some("ffffe5bf")),
+            ("#eccd8b", Some("ffeccd8b")),
+            ("80eccd8b", Some("80eccd8b")),
+            ("torch", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                normalize_hex_color(input).as_deref(),
+                expected,
+                "Failed to normalize color {:?}",
+                input
+            );
+        }
+    }
+
+    /// A 4x4 room with a doorway in the bottom wall between x = 1 and x = 2
+    fn room_with_doorway() -> Vec<Line> {
+        get_line_segments(vec![vec![
+            Coordinate { x: 1.0, y: 4.0 },
+            Coordinate { x: 0.0, y: 4.0 },
+            Coordinate { x: 0.0, y: 0.0 },
+            Coordinate { x: 4.0, y: 0.0 },
+            Coordinate { x: 4.0, y: 4.0 },
+            Coordinate { x: 2.0, y: 4.0 },
+        ]])
+    }
+
+    #[test]
+    fn test_indirect_los_closed_door() {
+        let mut walls = room_with_doorway();
+        walls.push(Line::new(
+            Coord { x: 1.0, y: 4.0 },
+            Coord { x: 2.0, y: 4.0 },
+        ));
+        let los = calculate_indirect_los(Coord { x: 3.0, y: 1.0 }, &walls)
+            .expect("Expected the pov to be enclosed by the room");
+        assert!(
+            (los.unsigned_area() - 16.0).abs() < 1e-9,
+            "Expected the room area 16.0, found {}",
+            los.unsigned_area()
+        );
+        assert!(
+            calculate_indirect_los(Coord { x: 3.0, y: 5.0 }, &walls).is_none(),
+            "Expected a pov outside the room to not be enclosed"
+        );
+    }
+
+    #[test]
+    fn test_indirect_los_open_doorway() {
+        let walls = room_with_doorway();
+        assert!(
+            calculate_indirect_los(Coord { x: 3.0, y: 1.0 }, &walls).is_none(),
+            "Expected a room with an open doorway to not enclose the pov"
+        );
+    }
+
+    #[test]
+    fn test_direct_los_matches_serial_sweep() {
+        let bounds = Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 10.0, y: 7.0 });
+        let walls = get_line_segments(vec![vec![
+            Coordinate { x: 2.0, y: 1.0 },
+            Coordinate { x: 6.0, y: 3.5 },
+            Coordinate { x: 4.0, y: 6.0 },
+        ]]);
+        let pov = Coord { x: 3.0, y: 3.0 };
+        let mut serial: Vec<Coord> = Vec::new();
+        for_each_interesection(pov, &walls, bounds, 0.1, |ray| serial.push(ray.end));
+        let parallel = calculate_direct_los(pov, &walls, bounds, 0.1);
+        let parallel: Vec<Coord> = parallel.exterior().0.clone();
+        // the polygon closes its ring by repeating the first point
+        assert_eq!(parallel.len(), serial.len() + 1);
+        assert!(
+            parallel
+                .iter()
+                .zip(serial.iter())
+                .all(|(a, b)| a.x.to_bits() == b.x.to_bits() && a.y.to_bits() == b.y.to_bits()),
+            "Expected the parallel sweep to match the serial sweep exactly"
+        );
+    }
+
+    #[test]
+    fn test_find_intersection() {
+        let walls = vec![
+            Line::new(Coord { x: 3.0, y: -1.0 }, Coord { x: 3.0, y: 1.0 }),
+            Line::new(Coord { x: 2.0, y: -1.0 }, Coord { x: 2.0, y: 1.0 }),
+            Line::new(Coord { x: 5.0, y: 0.0 }, Coord { x: 7.0, y: 0.0 }),
+        ];
+        let ray = Line::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 10.0, y: 0.0 });
+        assert_eq!(
+            find_intersection(ray, &walls),
+            Coord { x: 2.0, y: 0.0 },
+            "Expected the ray to stop at the closest wall"
+        );
+        assert_eq!(
+            find_intersection(ray, &walls[2..]),
+            Coord { x: 5.0, y: 0.0 },
+            "Expected a ray along a wall to stop at the closest end of the wall"
+        );
+        let ray = Line::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 0.0, y: 10.0 });
+        assert_eq!(
+            find_intersection(ray, &walls),
+            Coord { x: 0.0, y: 10.0 },
+            "Expected a ray that hits nothing to reach its end"
+        );
+    }
+
+    #[test]
+    fn test_calculate_direct_los_with_progress() {
+        let walls = vec![
+            Line::new(Coord { x: 3.0, y: 1.0 }, Coord { x: 3.0, y: 5.0 }),
+            Line::new(Coord { x: 1.0, y: 6.0 }, Coord { x: 5.0, y: 7.0 }),
+        ];
+        let bounds = Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 8.0, y: 8.0 });
+        let pov = Coord { x: 1.5, y: 2.5 };
+        let mut reported = Vec::new();
+        let los = calculate_direct_los_with_progress(pov, &walls, bounds, 0.1, &mut |fraction| {
+            reported.push(fraction)
+        });
+        assert_eq!(
+            los,
+            calculate_direct_los(pov, &walls, bounds, 0.1),
+            "Expected reporting progress to not change the line of sight"
+        );
+        assert!(
+            reported.len() > 1,
+            "Expected progress to be reported in steps"
+        );
+        assert!(reported.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(reported.last(), Some(&1.0));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_blur_fow_edges() {
+        let rectangle = |left: i32, top: i32, right: i32, bottom: i32| FoWRectangle {
+            topleft: PixelCoordinate { x: left, y: top },
+            bottomright: PixelCoordinate {
+                x: right,
+                y: bottom,
+            },
+        };
+        // the right half of the image is hidden
+        let tiles = blur_fow_edges(&[rectangle(100, 0, 200, 100)], 200, 100, 4);
+        let corners: Vec<_> = tiles.iter().map(|&(x, y, _)| (x, y)).collect();
+        assert_eq!(
+            corners,
+            vec![(64, 0), (64, 64)],
+            "Expected only the tiles at the edge"
+        );
+        let (_, _, mask) = &tiles[0];
+        assert_eq!((mask.width(), mask.height()), (64, 64));
+        let row: Vec<u8> = (30..42).map(|x| mask.get_pixel(x, 10).0[0]).collect();
+        assert_eq!(
+            row,
+            [0, 0, 28, 56, 85, 113, 141, 170, 198, 226, 255, 255],
+            "Expected the edge to be blurred with a box of 9 pixels"
+        );
+        assert_eq!(
+            tiles[1].2.height(),
+            36,
+            "Expected the tile to end at the image"
+        );
+
+        // two hidden rectangles that touch only have an edge on the border of the image
+        let halves = [rectangle(0, 0, 100, 100), rectangle(100, 0, 200, 100)];
+        assert!(blur_fow_edges(&halves, 200, 100, 4).is_empty());
+        assert!(blur_fow_edges(&[], 200, 100, 4).is_empty());
+    }
+
+    #[test]
+    fn test_find_intersection_close_walls() {
+        // walls closer together than any quantization of the distance along the ray
+        let near = Line::new(Coord { x: 2.0, y: -1.0 }, Coord { x: 2.0, y: 1.0 });
+        let far = Line::new(
+            Coord {
+                x: 2.0 + 1e-9,
+                y: -1.0,
+            },
+            Coord {
+                x: 2.0 + 1e-9,
+                y: 1.0,
+            },
+        );
+        let ray = Line::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 10.0, y: 0.0 });
+        for walls in [[near, far], [far, near]] {
+            assert_eq!(
+                find_intersection(ray, &walls),
+                Coord { x: 2.0, y: 0.0 },
+                "Expected the closest of two nearly overlapping walls regardless of their order"
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_intersection_collinear() {
+        let forward = Line::new(Coord { x: 5.0, y: 0.0 }, Coord { x: 7.0, y: 0.0 });
+        let backward = Line::new(Coord { x: 7.0, y: 0.0 }, Coord { x: 5.0, y: 0.0 });
+        let left_to_right = Line::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 10.0, y: 0.0 });
+        let right_to_left = Line::new(Coord { x: 10.0, y: 0.0 }, Coord { x: 0.0, y: 0.0 });
+        for wall in [forward, backward] {
+            assert_eq!(
+                find_intersection(left_to_right, &[wall]),
+                Coord { x: 5.0, y: 0.0 },
+                "Expected a ray from the left to stop at the left end of {:?}",
+                wall
+            );
+            assert_eq!(
+                find_intersection(right_to_left, &[wall]),
+                Coord { x: 7.0, y: 0.0 },
+                "Expected a ray from the right to stop at the right end of {:?}",
+                wall
+            );
+            // the ray starts on the wall
+            let ray = Line::new(Coord { x: 6.0, y: 0.0 }, Coord { x: 10.0, y: 0.0 });
+            assert_eq!(find_intersection(ray, &[wall]), Coord { x: 6.0, y: 0.0 });
+        }
+        // the ray ends inside the wall
+        let ray = Line::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 6.0, y: 0.0 });
+        assert_eq!(
+            find_intersection(ray, &[backward]),
+            Coord { x: 5.0, y: 0.0 }
+        );
+        // a ray along a wall that lies behind the start is not stopped
+        let ray = Line::new(Coord { x: 8.0, y: 0.0 }, Coord { x: 10.0, y: 0.0 });
+        assert_eq!(
+            find_intersection(ray, &[forward]),
+            Coord { x: 10.0, y: 0.0 }
+        );
+    }
+
+    #[test]
+    fn test_direct_los() {
+        let bounds = Rect::new(Coord { x: 0.0, y: 0.0 }, Coord { x: 10.0, y: 10.0 });
+        let wall = [Line::new(
+            Coord { x: 5.0, y: 0.0 },
+            Coord { x: 5.0, y: 10.0 },
+        )];
+        let pov = Coord { x: 2.5, y: 5.0 };
+        let los = calculate_direct_los(pov, &wall, bounds, 0.2);
+        assert!(
+            (los.unsigned_area() - 50.0).abs() < 1e-6,
+            "Expected the half of the map in front of the wall to be visible, found {}",
+            los.unsigned_area()
+        );
+        let los = calculate_direct_los(pov, &[], bounds, 0.2);
+        assert!(
+            (los.unsigned_area() - 100.0).abs() < 1e-6,
+            "Expected the whole map to be visible, found {}",
+            los.unsigned_area()
+        );
+        let pillar = [Line::new(
+            Coord { x: 5.0, y: 4.0 },
+            Coord { x: 5.0, y: 6.0 },
+        )];
+        let los = calculate_direct_los(pov, &pillar, bounds, 0.2);
+        assert!(
+            !los.contains(&Coord { x: 8.0, y: 5.0 }),
+            "Expected the area behind the pillar to be hidden"
+        );
+        assert!(
+            los.contains(&Coord { x: 8.0, y: 1.0 }),
+            "Expected the area beside the pillar to be visible"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_scanline_spans() {
+        let square = Polygon::new(
+            LineString::from(vec![(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0)]),
+            vec![LineString::from(vec![
+                (1.0, 1.0),
+                (3.0, 1.0),
+                (3.0, 3.0),
+                (1.0, 3.0),
+            ])],
+        );
+        assert_eq!(scanline_spans(&square, 0.5), vec![(0.0, 4.0)]);
+        assert_eq!(
+            scanline_spans(&square, 2.0),
+            vec![(0.0, 1.0), (3.0, 4.0)],
+            "Expected the hole to be skipped"
+        );
+        assert!(scanline_spans(&square, 5.0).is_empty());
+    }
 }
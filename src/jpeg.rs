@@ -0,0 +1,76 @@
+//! JPEG support for vtt files whose exporter embedded a JPEG image instead of a PNG image, using
+//! the image crate. Both baseline and progressive JPEG images can be decoded.
+use std::io::Cursor;
+
+use image::{codecs::jpeg::JpegDecoder, ImageDecoder, ImageError};
+
+use crate::{errors::RustVttError, pixbuf::Pixbuf};
+
+/// Read the width and height of a JPEG image from its frame header, without decoding the image
+pub fn dimensions(bytes: &[u8]) -> Result<(u32, u32), RustVttError> {
+    let decoder = JpegDecoder::new(Cursor::new(bytes)).map_err(decode_error)?;
+    return Ok(decoder.dimensions());
+}
+
+/// Decode a JPEG image into an RGB pixel buffer
+pub fn decode_jpeg(bytes: &[u8]) -> Result<Pixbuf, RustVttError> {
+    let image = image::load_from_memory_with_format(bytes, image::ImageFormat::Jpeg)
+        .map_err(decode_error)?;
+    return Ok(image.into_rgb8().into());
+}
+
+fn decode_error(error: ImageError) -> RustVttError {
+    return RustVttError::ImageDecode {
+        reason: error.to_string(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::is_jpeg;
+    use crate::jpeg::{decode_jpeg, dimensions};
+    use base64::{prelude::BASE64_STANDARD, Engine as _};
+
+    fn example2_image() -> Vec<u8> {
+        let contents = std::fs::read_to_string("tests/resources/example2.dd2vtt")
+            .expect("Could not read file example2.dd2vtt");
+        let json: serde_json::Value =
+            serde_json::from_str(&contents).expect("Could not parse example2.dd2vtt");
+        return BASE64_STANDARD
+            .decode(json["image"].as_str().unwrap())
+            .expect("Could not decode the image");
+    }
+
+    #[test]
+    fn test_decode_jpeg() {
+        let bytes = example2_image();
+        assert!(is_jpeg(&bytes), "Expected example2 to embed a JPEG image");
+        assert_eq!(dimensions(&bytes).unwrap(), (4350, 3300));
+        let image = decode_jpeg(&bytes).expect("Could not decode the JPEG image");
+        assert_eq!((image.width(), image.height()), (4350, 3300));
+        assert!(
+            image.as_raw().iter().any(|&c| c != image.as_raw()[0]),
+            "Expected the image to not be a single color"
+        );
+    }
+
+    #[test]
+    fn test_decode_jpeg_invalid() {
+        assert!(decode_jpeg(b"not a jpeg").is_err());
+        let bytes = example2_image();
+        assert!(
+            decode_jpeg(&bytes[..300]).is_err(),
+            "Expected a truncated image to be rejected"
+        );
+    }
+
+    #[test]
+    fn test_decode_progressive_jpeg() {
+        let bytes = std::fs::read("tests/resources/progressive.jpg")
+            .expect("Could not read file progressive.jpg");
+        assert!(is_jpeg(&bytes), "Expected a JPEG image");
+        assert_eq!(dimensions(&bytes).unwrap(), (493, 58));
+        let image = decode_jpeg(&bytes).expect("Could not decode the progressive JPEG image");
+        assert_eq!((image.width(), image.height()), (493, 58));
+    }
+}
@@ -14,25 +14,249 @@
 //! - Call some function to edit a property (e.g. `set_ambient_light(NightTime)`)
 //! - Update the image using `update_image()`
 //! - Save or get a pixelbuffer of the image using `save_image(path)` or `get_pixbuf()` to use the new
-//! image.
+//!   image.
 //!
 //! If you plan on changing more then one property before revealing the image it is better to edit
 //! all these properties at once and then updating the image.
+//!
+//! The image is only decoded when it is needed, e.g. by `get_pixbuf()`, so a headless server can
+//! use the geometry (`open_vtt`, `fow_change`, `compute_los`, `get_rectangles`) without ever
+//! decoding it. Images are decoded and encoded with the image crate, behind the default `image`
+//! feature. Without it the crate does not compile the image codecs, `Pixbuf` or any method that
+//! draws on the image, and a map built with [`VttBuilder`] has no image.
+//!
+//! With the `tracing` feature enabled, the line of sight and fog of war calculations emit tracing
+//! events with the number of rays, wall segments, polygon vertices and fog of war rectangles at
+//! the debug and trace levels.
 
+mod builder;
 mod errors;
 mod fog_of_war;
+mod format;
+mod gzip;
 mod helper;
+#[cfg(feature = "image")]
+mod jpeg;
+#[cfg(feature = "image")]
+mod pixbuf;
+#[cfg(feature = "image")]
+mod png;
+mod stack;
 mod vtt;
-use anyhow::Result;
 use std::{fs::File, io::Read, path::Path};
+use vtt::VTTPartial;
 
-pub use vtt::VTT;
+pub use builder::VttBuilder;
+pub use errors::RustVttError;
+pub use fog_of_war::{FoWRectangle, FogOfWar, FowDelta, FowState, Operation};
+pub use format::ImageFormat;
+#[cfg(feature = "image")]
+pub use pixbuf::{Pixbuf, Rgb};
+pub use stack::VttStack;
+pub use vtt::{
+    AmbientLight, Coordinate, Falloff, Light, PixelCoordinate, PortalInfo, VisionConfig, WallIssue,
+    VTT,
+};
 
-/// Open a vtt file and store the contents in memory
-pub fn open_vtt<P: AsRef<Path>>(path: P) -> Result<VTT> {
+/// Open a vtt file and store the contents in memory. Returns a [`RustVttError::Io`] error if the
+/// file could not be read, see [`open_vtt_from_bytes`] for the other errors.
+pub fn open_vtt<P: AsRef<Path>>(path: P) -> Result<VTT, RustVttError> {
     let mut file = File::open(path)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    let vtt: VTT = serde_json::from_str(&contents)?;
-    return Ok(vtt);
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    return open_vtt_from_bytes(&contents);
+}
+
+/// Open a vtt file like [`open_vtt`] without blocking the tokio runtime. The file is read with
+/// `tokio::fs` and parsed on a blocking thread. Only available with the `tokio` feature.
+#[cfg(feature = "tokio")]
+pub async fn open_vtt_async<P: AsRef<Path>>(path: P) -> Result<VTT, RustVttError> {
+    let bytes = tokio::fs::read(path).await?;
+    match tokio::task::spawn_blocking(move || open_vtt_from_bytes(&bytes)).await {
+        Ok(result) => return result,
+        Err(error) => std::panic::resume_unwind(error.into_panic()),
+    }
+}
+
+/// Read a vtt from the contents of a vtt file that is already in memory. Gzip compressed contents
+/// (such as `.dd2vtt.gz` files) are decompressed first, where an invalid or truncated gzip stream
+/// returns a [`RustVttError::InvalidGzip`] error. Returns a [`RustVttError::InvalidUtf8`] error if
+/// the bytes are not valid UTF-8 and a [`RustVttError::InvalidJson`] error if they are not a valid
+/// vtt file. A leading UTF-8 byte order mark and surrounding whitespace are ignored. An invalid
+/// resolution of the map returns an error instead of panicking, such as a
+/// [`RustVttError::InvalidMapSize`] error for a map size that is not a positive number of squares.
+pub fn open_vtt_from_bytes(bytes: &[u8]) -> Result<VTT, RustVttError> {
+    if gzip::is_gzip(bytes) {
+        let decompressed = gzip::decompress(bytes)?;
+        return open_vtt_from_bytes(&decompressed);
+    }
+    let contents =
+        std::str::from_utf8(bytes).map_err(|source| RustVttError::InvalidUtf8 { source })?;
+    // files saved by some windows editors start with a byte order mark
+    let contents = contents.trim_start_matches('\u{feff}').trim();
+    // deserialize the partial vtt first so an invalid resolution is returned as a RustVttError
+    let partial: VTTPartial = serde_json::from_str(contents)?;
+    return partial.try_to_vtt();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{open_vtt, open_vtt_from_bytes, RustVttError, VTT};
+    use std::path::PathBuf;
+
+    /// Returns a path in the temporary directory that is unique to this test run, so concurrent
+    /// test runs do not overwrite each other's files
+    pub(crate) fn temp_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("vtt_rust_{}_{}", std::process::id(), name));
+    }
+
+    #[test]
+    fn open_missing_file() {
+        let Err(error) = open_vtt("tests/resources/does_not_exist.dd2vtt") else {
+            panic!("Expected a missing file to be rejected");
+        };
+        assert!(
+            matches!(&error, RustVttError::Io { source } if source.kind() == std::io::ErrorKind::NotFound),
+            "Expected an Io error, found {}",
+            error
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn open_async() {
+        let vtt = crate::open_vtt_async("tests/resources/example1.dd2vtt")
+            .await
+            .expect("Could not open file example1.dd2vtt");
+        assert_eq!(vtt.pixels_per_grid(), 256, "pixels per grid did not match");
+        let Err(error) = crate::open_vtt_async("tests/resources/does_not_exist.dd2vtt").await
+        else {
+            panic!("Expected a missing file to be rejected");
+        };
+        assert!(
+            matches!(error, RustVttError::Io { .. }),
+            "Expected an Io error, found {}",
+            error
+        );
+    }
+
+    #[test]
+    fn open_from_bytes() {
+        let bytes = std::fs::read("tests/resources/example1.dd2vtt")
+            .expect("Could not read file example1.dd2vtt");
+        let vtt = open_vtt_from_bytes(&bytes).expect("Could not parse example1.dd2vtt");
+        assert_eq!(vtt.pixels_per_grid(), 256, "pixels per grid did not match");
+    }
+
+    #[test]
+    fn open_from_bytes_bom() {
+        let mut bytes = "\u{feff}\r\n".as_bytes().to_vec();
+        bytes.extend(
+            std::fs::read("tests/resources/example1.dd2vtt")
+                .expect("Could not read file example1.dd2vtt"),
+        );
+        bytes.extend(b"\r\n\n");
+        let vtt =
+            open_vtt_from_bytes(&bytes).expect("Could not parse a vtt with a byte order mark");
+        assert_eq!(vtt.pixels_per_grid(), 256, "pixels per grid did not match");
+    }
+
+    #[test]
+    fn parse_from_str() {
+        let contents = std::fs::read_to_string("tests/resources/example1.dd2vtt")
+            .expect("Could not read file example1.dd2vtt");
+        let vtt: VTT = contents.parse().expect("Could not parse example1.dd2vtt");
+        assert_eq!(vtt.pixels_per_grid(), 256, "pixels per grid did not match");
+        let same = VTT::try_from(contents.as_str()).expect("Could not convert example1.dd2vtt");
+        assert!(
+            vtt == same,
+            "Expected parsing and converting to give the same vtt"
+        );
+        let Err(error) = "{".parse::<VTT>() else {
+            panic!("Expected malformed json to be rejected");
+        };
+        assert!(
+            matches!(error, RustVttError::InvalidJson { .. }),
+            "Expected an InvalidJson error, found {}",
+            error
+        );
+    }
+
+    #[test]
+    fn open_from_bytes_invalid_utf8() {
+        let Err(error) = open_vtt_from_bytes(&[b'{', 0xff, b'}']) else {
+            panic!("Expected invalid UTF-8 to be rejected");
+        };
+        assert!(
+            matches!(error, RustVttError::InvalidUtf8 { .. }),
+            "Expected an InvalidUtf8 error, found {}",
+            error
+        );
+        let Err(error) = open_vtt_from_bytes(b"{") else {
+            panic!("Expected malformed json to be rejected");
+        };
+        assert!(
+            matches!(error, RustVttError::InvalidJson { .. }),
+            "Expected a json error, found {}",
+            error
+        );
+    }
+
+    #[test]
+    fn open_from_bytes_invalid_resolution() {
+        let contents = std::fs::read_to_string("tests/resources/example1.dd2vtt")
+            .expect("Could not read file example1.dd2vtt");
+        let mut json: serde_json::Value =
+            serde_json::from_str(&contents).expect("Could not parse example1.dd2vtt");
+        json["resolution"]["map_size"]["x"] = serde_json::json!(27.5);
+        let vtt = open_vtt_from_bytes(json.to_string().as_bytes())
+            .expect("Expected a map that does not end on a whole square to open");
+        assert_eq!(vtt.size().x, 27.5);
+        json["resolution"]["map_size"]["x"] = serde_json::json!(0);
+        let Err(error) = open_vtt_from_bytes(json.to_string().as_bytes()) else {
+            panic!("Expected an empty map size to be rejected");
+        };
+        assert!(
+            matches!(error, RustVttError::InvalidMapSize { .. }),
+            "Expected an InvalidMapSize error, found {}",
+            error
+        );
+        json["resolution"]["map_size"]["x"] = serde_json::json!(27);
+        json["resolution"]["map_origin"]["y"] = serde_json::json!(-2);
+        assert!(
+            serde_json::from_str::<crate::VTT>(&json.to_string()).is_err(),
+            "Expected deserializing a VTT directly to validate the resolution"
+        );
+    }
+
+    #[test]
+    fn open_from_bytes_format() {
+        let contents = std::fs::read_to_string("tests/resources/example2.dd2vtt")
+            .expect("Could not read file example2.dd2vtt");
+        let mut json: serde_json::Value =
+            serde_json::from_str(&contents).expect("Could not parse example2.dd2vtt");
+        json["lights"][0]["color"] = serde_json::json!("#FFE5BF");
+        let vtt = open_vtt_from_bytes(json.to_string().as_bytes())
+            .expect("Could not parse example2.dd2vtt");
+        assert_eq!(vtt.format(), 0.2, "format did not match");
+        assert!(vtt.is_supported_format());
+        assert!(
+            vtt.lights()
+                .iter()
+                .all(|light| light.color().len() == 8
+                    && light.color() == light.color().to_lowercase()),
+            "Expected all light colors to be normalized to lowercase aarrggbb"
+        );
+        assert_eq!(vtt.lights()[0].color(), "ffffe5bf");
+
+        json["format"] = serde_json::json!(1.5);
+        let Err(error) = open_vtt_from_bytes(json.to_string().as_bytes()) else {
+            panic!("Expected a newer format to be rejected");
+        };
+        assert!(
+            matches!(error, RustVttError::UnsupportedFormat { .. }),
+            "Expected an UnsupportedFormat error, found {}",
+            error
+        );
+    }
 }
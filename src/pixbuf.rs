@@ -0,0 +1,500 @@
+//! The Pixbuf is a simple RGB pixel buffer that the vtt data (fog of war, lighting, doors etc.) is
+//! drawn on. Pixels are stored row by row with three bytes (red, green, blue) per pixel, so the raw
+//! data can be handed to most image libraries directly.
+use geo::{Coord, Distance, Euclidean, Line};
+use image::{
+    imageops::{self, FilterType},
+    ImageBuffer, RgbImage,
+};
+use rayon::prelude::*;
+
+/// The number of rows each thread blends at once in [`Pixbuf::blend_rects`]
+const BAND_ROWS: usize = 64;
+
+/// A single RGB pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub [u8; 3]);
+
+/// An RGB image stored in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixbuf {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Pixbuf {
+    /// Create a new black image of the given size
+    pub fn new(width: u32, height: u32) -> Self {
+        return Self {
+            width,
+            height,
+            data: vec![0; width as usize * height as usize * 3],
+        };
+    }
+
+    /// Create an image from raw RGB data. Returns None if the length of the data does not match
+    /// the given width and height.
+    pub fn from_raw(width: u32, height: u32, data: Vec<u8>) -> Option<Self> {
+        if data.len() != width as usize * height as usize * 3 {
+            return None;
+        }
+        return Some(Self {
+            width,
+            height,
+            data,
+        });
+    }
+
+    /// Returns the width of the image in pixels
+    pub fn width(&self) -> u32 {
+        return self.width;
+    }
+
+    /// Returns the height of the image in pixels
+    pub fn height(&self) -> u32 {
+        return self.height;
+    }
+
+    /// Returns the raw RGB data of the image
+    pub fn as_raw(&self) -> &[u8] {
+        return &self.data;
+    }
+
+    /// Consumes the image and returns the raw RGB data
+    pub fn into_raw(self) -> Vec<u8> {
+        return self.data;
+    }
+
+    /// Returns the pixel at the given position.
+    ///
+    /// # Panics
+    /// Panics if the position lies outside of the image
+    pub fn get_pixel(&self, x: u32, y: u32) -> Rgb {
+        let i = self.index(x, y);
+        return Rgb([self.data[i], self.data[i + 1], self.data[i + 2]]);
+    }
+
+    /// Sets the pixel at the given position.
+    ///
+    /// # Panics
+    /// Panics if the position lies outside of the image
+    pub fn put_pixel(&mut self, x: u32, y: u32, pixel: Rgb) {
+        let i = self.index(x, y);
+        self.data[i..i + 3].copy_from_slice(&pixel.0);
+    }
+
+    /// Draw a line of the given thickness between two points in pixel coordinates. Parts of the
+    /// line that fall outside of the image are not drawn.
+    pub fn draw_line(&mut self, line: Line, color: Rgb, thickness: f64) {
+        let radius = (thickness / 2.0).max(0.5);
+        let min_x = (line.start.x.min(line.end.x) - radius).floor().max(0.0);
+        let min_y = (line.start.y.min(line.end.y) - radius).floor().max(0.0);
+        let max_x = (line.start.x.max(line.end.x) + radius)
+            .ceil()
+            .min(self.width as f64);
+        let max_y = (line.start.y.max(line.end.y) + radius)
+            .ceil()
+            .min(self.height as f64);
+        for y in min_y as u32..max_y.max(0.0) as u32 {
+            for x in min_x as u32..max_x.max(0.0) as u32 {
+                let center = Coord {
+                    x: x as f64 + 0.5,
+                    y: y as f64 + 0.5,
+                };
+                if Euclidean::distance(center, &line) <= radius {
+                    self.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Returns a copy of a rectangle of the image. Parts of the rectangle that fall outside of the
+    /// image are left out, so the result can be smaller than the given size.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Pixbuf {
+        let left = x.min(self.width);
+        let top = y.min(self.height);
+        let right = x.saturating_add(width).min(self.width);
+        let bottom = y.saturating_add(height).min(self.height);
+        let mut data = Vec::with_capacity((right - left) as usize * (bottom - top) as usize * 3);
+        for row in top..bottom {
+            let start = self.index(left, row);
+            data.extend_from_slice(&self.data[start..start + (right - left) as usize * 3]);
+        }
+        return Pixbuf {
+            width: right - left,
+            height: bottom - top,
+            data,
+        };
+    }
+
+    /// Copy an image onto this image with its top left corner at the given position. Parts of the
+    /// image that fall outside of this image are left out.
+    pub fn paste(&mut self, x: u32, y: u32, image: &Pixbuf) {
+        let right = x.saturating_add(image.width).min(self.width);
+        let bottom = y.saturating_add(image.height).min(self.height);
+        if x >= right || y >= bottom {
+            return;
+        }
+        let length = (right - x) as usize * 3;
+        for row in y..bottom {
+            let start = self.index(x, row);
+            let source = image.index(0, row - y);
+            self.data[start..start + length].copy_from_slice(&image.data[source..source + length]);
+        }
+    }
+
+    /// Returns a copy of the image turned a quarter turn clockwise, so the width and height swap.
+    /// The pixel at (x, y) ends up at (height - 1 - y, x).
+    pub fn rotate_clockwise(&self) -> Pixbuf {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut data = Vec::with_capacity(self.data.len());
+        for x in 0..width {
+            for y in (0..height).rev() {
+                let start = (y * width + x) * 3;
+                data.extend_from_slice(&self.data[start..start + 3]);
+            }
+        }
+        return Pixbuf {
+            width: self.height,
+            height: self.width,
+            data,
+        };
+    }
+
+    /// Returns a copy of the image scaled to the given size with a linear filter, which takes the
+    /// pixels of this image that a pixel covers into account so downscaling does not skip details.
+    pub fn resize(&self, width: u32, height: u32) -> Pixbuf {
+        if self.width == 0 || self.height == 0 {
+            return Pixbuf::new(width, height);
+        }
+        let image = ImageBuffer::<image::Rgb<u8>, &[u8]>::from_raw(
+            self.width,
+            self.height,
+            self.data.as_slice(),
+        )
+        .expect("the data always matches the size of the image");
+        return imageops::resize(&image, width, height, FilterType::Triangle).into();
+    }
+
+    /// Fill a rectangle of the image with a color. Parts of the rectangle that fall outside of the
+    /// image are not drawn.
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Rgb) {
+        let min_x = x.clamp(0, self.width as i32) as usize;
+        let min_y = y.clamp(0, self.height as i32) as usize;
+        let max_x = (x as i64 + width as i64).clamp(0, self.width as i64) as usize;
+        let max_y = (y as i64 + height as i64).clamp(0, self.height as i64) as usize;
+        for row in min_y..max_y {
+            let start = (row * self.width as usize + min_x) * 3;
+            let end = (row * self.width as usize + max_x) * 3;
+            for pixel in self.data[start..end].chunks_exact_mut(3) {
+                pixel.copy_from_slice(&color.0);
+            }
+        }
+    }
+
+    /// Blend a color over a rectangle of the image, where an `alpha` of 255 fully replaces the
+    /// pixels and an `alpha` of 0 leaves them untouched. Parts of the rectangle that fall outside
+    /// of the image are not drawn.
+    pub fn blend_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Rgb, alpha: u8) {
+        if alpha == u8::MAX {
+            self.fill_rect(x, y, width, height, color);
+            return;
+        }
+        let min_x = x.clamp(0, self.width as i32) as usize;
+        let min_y = y.clamp(0, self.height as i32) as usize;
+        let max_x = (x as i64 + width as i64).clamp(0, self.width as i64) as usize;
+        let max_y = (y as i64 + height as i64).clamp(0, self.height as i64) as usize;
+        for row in min_y..max_y {
+            let start = (row * self.width as usize + min_x) * 3;
+            let end = (row * self.width as usize + max_x) * 3;
+            blend_pixels(&mut self.data[start..end], color, alpha);
+        }
+    }
+
+    /// Blend all rectangles `(x, y, width, height)` with the given color, giving the same result
+    /// as calling [`Pixbuf::blend_rect`] for each of them in order. The image is split into
+    /// horizontal bands that are blended on multiple threads, where every band only draws the
+    /// rectangles that intersect it.
+    pub fn blend_rects(&mut self, rectangles: &[(i32, i32, u32, u32)], color: Rgb, alpha: u8) {
+        let row_len = self.width as usize * 3;
+        if row_len == 0 || rectangles.is_empty() {
+            return;
+        }
+        let width = self.width as i64;
+        self.data
+            .par_chunks_mut(row_len * BAND_ROWS)
+            .enumerate()
+            .for_each(|(band, data)| {
+                let top = (band * BAND_ROWS) as i64;
+                let bottom = top + (data.len() / row_len) as i64;
+                for &(x, y, w, h) in rectangles {
+                    let min_y = (y as i64).clamp(top, bottom);
+                    let max_y = (y as i64 + h as i64).clamp(top, bottom);
+                    if min_y >= max_y {
+                        continue;
+                    }
+                    let min_x = (x as i64).clamp(0, width) as usize;
+                    let max_x = (x as i64 + w as i64).clamp(0, width) as usize;
+                    for row in (min_y - top) as usize..(max_y - top) as usize {
+                        let start = row * row_len + min_x * 3;
+                        let end = row * row_len + max_x * 3;
+                        blend_pixels(&mut data[start..end], color, alpha);
+                    }
+                }
+            });
+    }
+
+    /// Blend every pixel with the given color, where the opacity is scaled by the value of the mask
+    /// for that pixel: 0 leaves the pixel untouched and 255 blends it with the full opacity. The
+    /// mask has one value per pixel, row by row. Returns false without drawing anything if the mask
+    /// does not have the size of the image.
+    pub fn blend_mask(&mut self, mask: &[u8], color: Rgb, opacity: u8) -> bool {
+        if mask.len() != self.width as usize * self.height as usize {
+            return false;
+        }
+        if mask.is_empty() {
+            return true;
+        }
+        let row_len = self.width as usize;
+        self.data
+            .par_chunks_mut(row_len * 3)
+            .zip(mask.par_chunks(row_len))
+            .for_each(|(pixels, mask)| {
+                for (pixel, &value) in pixels.chunks_exact_mut(3).zip(mask) {
+                    let alpha = (value as u16 * opacity as u16 + 127) / 255;
+                    if alpha > 0 {
+                        blend_pixels(pixel, color, alpha as u8);
+                    }
+                }
+            });
+        return true;
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel ({}, {}) lies outside of the {}x{} image",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        return (y as usize * self.width as usize + x as usize) * 3;
+    }
+}
+
+impl From<RgbImage> for Pixbuf {
+    fn from(image: RgbImage) -> Self {
+        return Self {
+            width: image.width(),
+            height: image.height(),
+            data: image.into_raw(),
+        };
+    }
+}
+
+/// Blend a run of RGB pixels with the given color
+fn blend_pixels(pixels: &mut [u8], color: Rgb, alpha: u8) {
+    let alpha = alpha as u16;
+    for pixel in pixels.chunks_exact_mut(3) {
+        for (channel, &value) in pixel.iter_mut().zip(color.0.iter()) {
+            *channel = ((*channel as u16 * (255 - alpha) + value as u16 * alpha) / 255) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pixbuf::{Pixbuf, Rgb};
+    use geo::{Coord, Line};
+
+    #[test]
+    fn test_from_raw() {
+        assert!(
+            Pixbuf::from_raw(2, 2, vec![0; 12]).is_some(),
+            "Expected 12 bytes to fit a 2x2 image"
+        );
+        assert!(
+            Pixbuf::from_raw(2, 2, vec![0; 11]).is_none(),
+            "Expected 11 bytes to not fit a 2x2 image"
+        );
+    }
+
+    #[test]
+    fn test_crop() {
+        let mut pixbuf = Pixbuf::new(10, 8);
+        pixbuf.put_pixel(3, 2, Rgb([1, 2, 3]));
+        pixbuf.put_pixel(9, 7, Rgb([4, 5, 6]));
+        let cropped = pixbuf.crop(3, 2, 4, 3);
+        assert_eq!((cropped.width(), cropped.height()), (4, 3));
+        assert_eq!(cropped.get_pixel(0, 0), Rgb([1, 2, 3]));
+        let corner = pixbuf.crop(8, 6, 5, 5);
+        assert_eq!(
+            (corner.width(), corner.height()),
+            (2, 2),
+            "Expected the crop to be limited to the image"
+        );
+        assert_eq!(corner.get_pixel(1, 1), Rgb([4, 5, 6]));
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut pixbuf = Pixbuf::new(4, 3);
+        let tile = Pixbuf::from_raw(2, 2, vec![7; 12]).unwrap();
+        pixbuf.paste(3, 1, &tile);
+        assert_eq!(pixbuf.get_pixel(3, 1), Rgb([7, 7, 7]));
+        assert_eq!(pixbuf.get_pixel(3, 2), Rgb([7, 7, 7]));
+        assert_eq!(pixbuf.get_pixel(2, 1), Rgb([0, 0, 0]));
+        assert_eq!(pixbuf.get_pixel(3, 0), Rgb([0, 0, 0]));
+        pixbuf.paste(4, 0, &tile);
+        assert_eq!(
+            pixbuf.crop(0, 0, 4, 3),
+            pixbuf,
+            "Expected nothing to be pasted"
+        );
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut pixbuf = Pixbuf::new(4, 2);
+        pixbuf.put_pixel(0, 0, Rgb([200, 100, 40]));
+        pixbuf.put_pixel(3, 1, Rgb([255, 255, 255]));
+        let resized = pixbuf.resize(2, 1);
+        assert_eq!((resized.width(), resized.height()), (2, 1));
+        let Rgb([red, green, blue]) = resized.get_pixel(0, 0);
+        assert!(
+            red > 0 && red < 200 && green < 100 && blue < 40,
+            "Expected the colored pixel to be blended with its neighbours, found {:?}",
+            (red, green, blue)
+        );
+        let uniform = Pixbuf::from_raw(64, 32, vec![90; 64 * 32 * 3]).unwrap();
+        assert_eq!(
+            uniform.resize(7, 5),
+            Pixbuf::from_raw(7, 5, vec![90; 7 * 5 * 3]).unwrap(),
+            "Expected a uniform image to stay uniform"
+        );
+        let enlarged = pixbuf.resize(8, 4);
+        assert_eq!((enlarged.width(), enlarged.height()), (8, 4));
+        assert_eq!(enlarged.get_pixel(0, 0), Rgb([200, 100, 40]));
+        assert_eq!(enlarged.get_pixel(7, 3), Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn test_draw_line() {
+        let mut pixbuf = Pixbuf::new(10, 10);
+        let red = Rgb([255, 0, 0]);
+        let line = Line::new(Coord { x: 0.0, y: 5.0 }, Coord { x: 10.0, y: 5.0 });
+        pixbuf.draw_line(line, red, 2.0);
+        for x in 0..10 {
+            assert_eq!(
+                pixbuf.get_pixel(x, 4),
+                red,
+                "Expected ({}, 4) to be drawn",
+                x
+            );
+            assert_eq!(
+                pixbuf.get_pixel(x, 5),
+                red,
+                "Expected ({}, 5) to be drawn",
+                x
+            );
+            assert_eq!(
+                pixbuf.get_pixel(x, 2),
+                Rgb([0, 0, 0]),
+                "Expected ({}, 2) to be untouched",
+                x
+            );
+        }
+    }
+
+    #[test]
+    fn test_fill_rect() {
+        let mut pixbuf = Pixbuf::new(4, 4);
+        let white = Rgb([255, 255, 255]);
+        pixbuf.fill_rect(-1, 2, 3, 5, white);
+        for y in 0..4 {
+            for x in 0..4 {
+                let expected = if x < 2 && y >= 2 {
+                    white
+                } else {
+                    Rgb([0, 0, 0])
+                };
+                assert_eq!(pixbuf.get_pixel(x, y), expected, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rotate_clockwise() {
+        let pixbuf = Pixbuf::from_raw(3, 2, (0..18).collect()).unwrap();
+        let rotated = pixbuf.rotate_clockwise();
+        assert_eq!((rotated.width(), rotated.height()), (2, 3));
+        for y in 0..2 {
+            for x in 0..3 {
+                assert_eq!(
+                    rotated.get_pixel(1 - y, x),
+                    pixbuf.get_pixel(x, y),
+                    "pixel ({}, {}) was not rotated",
+                    x,
+                    y
+                );
+            }
+        }
+        let full_turn = (0..4).fold(pixbuf.clone(), |image, _| image.rotate_clockwise());
+        assert_eq!(
+            full_turn, pixbuf,
+            "Expected four quarter turns to change nothing"
+        );
+    }
+
+    #[test]
+    fn test_blend_mask() {
+        let mut pixbuf = Pixbuf::from_raw(3, 1, vec![200; 9]).unwrap();
+        assert!(!pixbuf.blend_mask(&[0; 2], Rgb([0, 0, 0]), 255));
+        assert!(pixbuf.blend_mask(&[0, 128, 255], Rgb([0, 0, 0]), 255));
+        assert_eq!(pixbuf.get_pixel(0, 0), Rgb([200, 200, 200]));
+        assert_eq!(pixbuf.get_pixel(1, 0), Rgb([99, 99, 99]));
+        assert_eq!(pixbuf.get_pixel(2, 0), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_blend_rect() {
+        let mut pixbuf = Pixbuf::from_raw(2, 1, vec![200; 6]).unwrap();
+        pixbuf.blend_rect(0, 0, 1, 1, Rgb([0, 0, 0]), 128);
+        assert_eq!(pixbuf.get_pixel(0, 0), Rgb([99, 99, 99]));
+        assert_eq!(pixbuf.get_pixel(1, 0), Rgb([200, 200, 200]));
+        pixbuf.blend_rect(0, 0, 2, 1, Rgb([10, 20, 30]), 255);
+        assert_eq!(pixbuf.get_pixel(1, 0), Rgb([10, 20, 30]));
+    }
+
+    #[test]
+    fn test_draw_line_outside_image() {
+        let mut pixbuf = Pixbuf::new(4, 4);
+        let line = Line::new(Coord { x: -10.0, y: -10.0 }, Coord { x: 20.0, y: 20.0 });
+        pixbuf.draw_line(line, Rgb([255, 255, 255]), 1.0);
+        assert_eq!(pixbuf.get_pixel(2, 2), Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn test_blend_rects() {
+        let raw = (0..300 * 200 * 3).map(|i| (i % 251) as u8).collect();
+        let mut serial = Pixbuf::from_raw(300, 200, raw).unwrap();
+        let mut parallel = serial.clone();
+        let rectangles = [
+            (-20, -20, 100, 100),
+            (50, 60, 200, 100),
+            (10, 120, 400, 90),
+            (280, 190, 50, 50),
+            (100, 63, 20, 2),
+            (400, 0, 10, 10),
+        ];
+        for alpha in [128, 255] {
+            for &(x, y, width, height) in &rectangles {
+                serial.blend_rect(x, y, width, height, Rgb([10, 20, 30]), alpha);
+            }
+            parallel.blend_rects(&rectangles, Rgb([10, 20, 30]), alpha);
+            assert_eq!(serial, parallel, "alpha {} blends differently", alpha);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! PNG support for the images embedded in vtt files, using the image crate. Decoding supports every
+//! PNG color type, where the alpha channel is dropped and 16 bit samples are reduced to 8 bits.
+//! Encoding writes an 8 bit RGB or RGBA PNG.
+use image::{codecs::png::PngEncoder, ExtendedColorType, ImageEncoder, ImageError};
+
+use crate::{errors::RustVttError, pixbuf::Pixbuf};
+
+/// Decode a PNG image into an RGB pixel buffer
+pub fn decode_png(bytes: &[u8]) -> Result<Pixbuf, RustVttError> {
+    let image =
+        image::load_from_memory_with_format(bytes, image::ImageFormat::Png).map_err(image_error)?;
+    return Ok(image.into_rgb8().into());
+}
+
+/// Encode an RGB pixel buffer as a PNG image
+pub fn encode_png(image: &Pixbuf) -> Vec<u8> {
+    return encode(
+        image.width(),
+        image.height(),
+        ExtendedColorType::Rgb8,
+        image.as_raw(),
+    );
+}
+
+/// Encode raw RGBA data, with four bytes (red, green, blue, alpha) per pixel stored row by row, as
+/// an 8 bit RGBA PNG image
+pub fn encode_png_rgba(width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+    return encode(width, height, ExtendedColorType::Rgba8, data);
+}
+
+fn encode(width: u32, height: u32, color_type: ExtendedColorType, data: &[u8]) -> Vec<u8> {
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(data, width, height, color_type)
+        .expect("encoding a png into memory can not fail");
+    return png;
+}
+
+fn image_error(error: ImageError) -> RustVttError {
+    return RustVttError::ImageDecode {
+        reason: error.to_string(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::png_dimensions as dimensions;
+    use crate::pixbuf::{Pixbuf, Rgb};
+    use crate::png::{decode_png, encode_png};
+
+    #[test]
+    fn test_png_round_trip() {
+        let mut image = Pixbuf::new(3, 2);
+        image.put_pixel(0, 0, Rgb([255, 0, 0]));
+        image.put_pixel(2, 1, Rgb([10, 20, 30]));
+        let decoded = decode_png(&encode_png(&image)).expect("Could not decode encoded png");
+        assert_eq!(
+            decoded, image,
+            "Decoded image did not match the encoded image"
+        );
+    }
+
+    #[test]
+    fn test_dimensions() {
+        let png = encode_png(&Pixbuf::new(7, 3));
+        assert_eq!(dimensions(&png).expect("Could not read dimensions"), (7, 3));
+        assert!(
+            dimensions(&png[..20]).is_err(),
+            "Expected a truncated header to be rejected"
+        );
+    }
+
+    #[test]
+    fn test_png_compressed() {
+        let image = Pixbuf::from_raw(256, 256, vec![200; 256 * 256 * 3]).unwrap();
+        let png = encode_png(&image);
+        assert!(
+            png.len() < image.as_raw().len() / 10,
+            "Expected a uniform image to be compressed, found {} bytes",
+            png.len()
+        );
+    }
+
+    #[test]
+    fn test_decode_not_png() {
+        assert!(
+            decode_png(&[0xff, 0xd8, 0xff, 0xe0]).is_err(),
+            "Expected a jpeg header to be rejected"
+        );
+    }
+}
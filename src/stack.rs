@@ -0,0 +1,201 @@
+//! The VttStack keeps several maps on top of each other, such as the floors of a tower. The uvtt
+//! format only stores a single level, so every level is a separate VTT. One level is active at a
+//! time, and fog of war updates and images go to the active level.
+#[cfg(feature = "image")]
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::{
+    errors::RustVttError,
+    fog_of_war::Operation,
+    vtt::{Coordinate, VTT},
+};
+
+/// A stack of maps, where every map is one level, e.g. a floor of a building.
+///
+/// # Example
+/// ```
+/// use vtt_rust::{open_vtt, VttStack};
+///
+/// let ground = open_vtt("tests/resources/example1.dd2vtt").unwrap();
+/// let cellar = open_vtt("tests/resources/example3.dd2vtt").unwrap();
+/// let mut stack = VttStack::new(ground);
+/// let cellar = stack.push_level(cellar);
+/// assert!(stack.set_active_level(cellar));
+/// assert_eq!(stack.levels_len(), 2);
+/// ```
+pub struct VttStack {
+    levels: Vec<VTT>,
+    active: usize,
+}
+
+impl VttStack {
+    /// Create a stack with a single level, which is active
+    pub fn new(level: VTT) -> Self {
+        return Self {
+            levels: vec![level],
+            active: 0,
+        };
+    }
+
+    /// Add a level on top of the stack and return its index. The active level does not change.
+    pub fn push_level(&mut self, level: VTT) -> usize {
+        self.levels.push(level);
+        return self.levels.len() - 1;
+    }
+
+    /// Remove the level with the given index and return it. Returns None if there is no level with
+    /// this index or it is the only level. The indices of all following levels shift down by one,
+    /// and when the active level is removed, the level below it becomes active.
+    pub fn remove_level(&mut self, index: usize) -> Option<VTT> {
+        if index >= self.levels.len() || self.levels.len() == 1 {
+            return None;
+        }
+        if self.active > index || (self.active == index && index > 0) {
+            self.active -= 1;
+        }
+        return Some(self.levels.remove(index));
+    }
+
+    /// Returns the number of levels in the stack, which is at least 1
+    pub fn levels_len(&self) -> usize {
+        return self.levels.len();
+    }
+
+    /// Returns the level with the given index, or None if there is no level with this index
+    pub fn level(&self, index: usize) -> Option<&VTT> {
+        return self.levels.get(index);
+    }
+
+    /// Returns a mutable reference to the level with the given index, or None if there is no level
+    /// with this index
+    pub fn level_mut(&mut self, index: usize) -> Option<&mut VTT> {
+        return self.levels.get_mut(index);
+    }
+
+    /// Returns the index of the active level
+    pub fn active_level(&self) -> usize {
+        return self.active;
+    }
+
+    /// Make the level with the given index active. Returns false if there is no level with this
+    /// index.
+    pub fn set_active_level(&mut self, index: usize) -> bool {
+        if index >= self.levels.len() {
+            return false;
+        }
+        self.active = index;
+        return true;
+    }
+
+    /// Returns the active level
+    pub fn active(&self) -> &VTT {
+        return &self.levels[self.active];
+    }
+
+    /// Returns a mutable reference to the active level, e.g. for fog of war operations that the
+    /// stack does not forward
+    pub fn active_mut(&mut self) -> &mut VTT {
+        return &mut self.levels[self.active];
+    }
+
+    /// Hide or show everything that a person standing at the pov on the active level could see, see
+    /// [`VTT::fow_change`]. The other levels are not changed.
+    pub fn fow_change(
+        &mut self,
+        pov: Coordinate,
+        operation: Operation,
+        around_walls: bool,
+    ) -> Result<(), RustVttError> {
+        return self.active_mut().fow_change(pov, operation, around_walls);
+    }
+
+    /// Show everything that a person standing at the pov on the active level could see, see
+    /// [`VTT::fow_show`]
+    pub fn fow_show(&mut self, pov: Coordinate, around_walls: bool) -> Result<(), RustVttError> {
+        return self.fow_change(pov, Operation::Show, around_walls);
+    }
+
+    /// Hide everything that a person standing at the pov on the active level could see, see
+    /// [`VTT::fow_hide`]
+    pub fn fow_hide(&mut self, pov: Coordinate, around_walls: bool) -> Result<(), RustVttError> {
+        return self.fow_change(pov, Operation::Hide, around_walls);
+    }
+
+    /// Save the image of the active level with its lighting and fog of war to a png file, see
+    /// [`VTT::save_img`]
+    #[cfg(feature = "image")]
+    pub fn save_img<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        return self.active().save_img(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::VttBuilder;
+    use crate::stack::VttStack;
+    use crate::vtt::{Coordinate, VTT};
+
+    fn level(width: f64) -> VTT {
+        return VttBuilder::new(Coordinate::new(0.0, 0.0), Coordinate::new(width, 4.0), 16)
+            .build()
+            .expect("Could not build the vtt");
+    }
+
+    #[test]
+    fn test_active_level() {
+        let mut stack = VttStack::new(level(2.0));
+        assert_eq!(stack.push_level(level(3.0)), 1);
+        assert_eq!(stack.push_level(level(4.0)), 2);
+        assert_eq!(stack.active_level(), 0);
+        assert!(!stack.set_active_level(3), "Expected no level at index 3");
+        assert!(stack.set_active_level(2));
+        assert_eq!(stack.active().size().x, 4.0);
+
+        for level in 0..stack.levels_len() {
+            stack.level_mut(level).unwrap().fow_hide_all();
+        }
+        stack
+            .fow_show(Coordinate::new(1.0, 1.0), false)
+            .expect("Could not reveal the fog of war");
+        assert!(stack.active().is_visible(Coordinate::new(3.5, 1.0)));
+        assert!(
+            !stack
+                .level(1)
+                .unwrap()
+                .is_visible(Coordinate::new(1.0, 1.0)),
+            "Expected only the active level to be revealed"
+        );
+
+        #[cfg(feature = "image")]
+        {
+            let path = crate::tests::temp_path("stack.png");
+            stack.save_img(&path).expect("Could not save the image");
+            assert!(path.exists());
+        }
+    }
+
+    #[test]
+    fn test_remove_level() {
+        let mut stack = VttStack::new(level(2.0));
+        assert!(
+            stack.remove_level(0).is_none(),
+            "Expected the last level to stay"
+        );
+        stack.push_level(level(3.0));
+        stack.push_level(level(4.0));
+        stack.set_active_level(2);
+        assert!(stack.remove_level(0).is_some());
+        assert_eq!(
+            stack.active_level(),
+            1,
+            "Expected the active level to shift down"
+        );
+        assert_eq!(stack.active().size().x, 4.0);
+        assert!(stack.remove_level(1).is_some());
+        assert_eq!(stack.active_level(), 0);
+        assert_eq!(stack.active().size().x, 3.0);
+        assert!(stack.remove_level(5).is_none());
+    }
+}
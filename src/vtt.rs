@@ -1,13 +1,45 @@
 use anyhow::Result;
 use base64::{prelude::BASE64_STANDARD, Engine as _};
-use geo::Coord;
-use std::{f64, fs::File, io::Write, path::Path};
+use geo::{
+    orient::Direction, BooleanOps, Coord, Distance, Euclidean, Intersects, Line, LineString,
+    MapCoords, MultiPolygon, Orient, Point, Polygon, Rect,
+};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    f64, fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    sync::OnceLock,
+};
 
-use crate::{errors::RustVttError, fog_of_war::FogOfWar};
+use crate::{
+    errors::RustVttError,
+    fog_of_war::{FoWRectangle, FogOfWar, Operation},
+    format::{self, guess_format, ImageFormat},
+    helper::{
+        calculate_direct_los, calculate_direct_los_with_progress, calculate_indirect_los,
+        dedup_segments, for_each_interesection, get_line_segments, get_planar_faces,
+        normalize_hex_color, parse_hex_color, STEP_SIZE,
+    },
+    open_vtt_from_bytes,
+};
+#[cfg(feature = "image")]
+use crate::{
+    helper::{blur_fow_edges, clip_line, clip_polyline, parse_hex_rgba, scanline_spans},
+    jpeg::decode_jpeg,
+    pixbuf::{Pixbuf, Rgb},
+    png::{decode_png, encode_png, encode_png_rgba},
+};
 use serde::{Deserialize, Serialize};
 
-/// The main VTT structure containing all the data that is in the .vtt file.
+/// The main VTT structure containing all the data that is in the .vtt file. Serializing a vtt with
+/// serde includes the fog of war, so the whole state of a session can be stored at once, use
+/// [`save_vtt`][crate::vtt::VTT::save_vtt()] to write a uvtt file without it.
 #[derive(Serialize, Deserialize)]
+#[serde(try_from = "VTTPartial")]
 pub struct VTT {
     format: f32,
     resolution: Resolution,
@@ -16,63 +48,687 @@ pub struct VTT {
     portals: Vec<Portal>,
     environment: Environment,
     lights: Vec<Light>,
-    #[serde(skip)]
     fog_of_war: FogOfWar,
+    #[serde(skip)]
+    rooms: OnceLock<Vec<Polygon>>,
+    /// The opacity of the overlay that dims remembered areas
+    #[serde(skip)]
+    remembered_opacity: u8,
+    /// The radius in pixels over which the edges of the fog of war are blurred, see
+    /// [`set_fow_edge_blur`][crate::vtt::VTT::set_fow_edge_blur()]
+    #[serde(skip)]
+    fow_edge_blur: u32,
+    /// Additional fog of war states by name, see [`add_fow_layer`][crate::vtt::VTT::add_fow_layer()]
+    #[serde(skip)]
+    fow_layers: HashMap<String, FogOfWar>,
+    #[serde(skip)]
+    los_step_size: f64,
+    /// The wall segments that block vision without and with objects, see
+    /// [`wall_segments`][crate::vtt::VTT::wall_segments()]
+    #[serde(skip)]
+    wall_segments: [OnceLock<Vec<Line>>; 2],
+    /// The segments of the walls and of the objects, see
+    /// [`line_segments`][crate::vtt::VTT::line_segments()]
+    #[serde(skip)]
+    line_segments: [OnceLock<Vec<Line>>; 2],
     image: String,
+    /// The decoded image, so the png is only decoded once
+    #[cfg(feature = "image")]
+    #[serde(skip)]
+    decoded_image: OnceLock<Pixbuf>,
+}
+
+/// The oldest vtt format version that is known to be read correctly
+const MIN_SUPPORTED_FORMAT: f32 = 0.2;
+/// The newest vtt format version that is known to be read correctly, newer files are rejected
+const MAX_SUPPORTED_FORMAT: f32 = 1.0;
+
+/// The distance in squares within which a point is considered to lie on a wall
+const WALL_TOLERANCE: f64 = 1e-6;
+
+/// The default opacity of the overlay that dims areas that are remembered but not seen right now
+const REMEMBERED_OPACITY: u8 = 160;
+
+/// How far [`VTT::nudge_off_wall`] moves a point off a wall, in squares
+const WALL_NUDGE: f64 = 10.0 * WALL_TOLERANCE;
+
+/// The default number of segments used to approximate a circle
+const CIRCLE_SEGMENTS: usize = 64;
+
+/// The largest angle in degrees between two points on the arc of a vision cone
+const CONE_ARC_STEP: f64 = 10.0;
+
+// Foundry VTT wall flags, see `CONST.WALL_DOOR_TYPES`, `CONST.WALL_DOOR_STATES` and
+// `CONST.WALL_SENSE_TYPES` in Foundry
+const FOUNDRY_DOOR_NONE: u8 = 0;
+const FOUNDRY_DOOR: u8 = 1;
+const FOUNDRY_DOOR_CLOSED: u8 = 0;
+const FOUNDRY_DOOR_OPEN: u8 = 1;
+const FOUNDRY_SENSE_NORMAL: u8 = 1;
+const FOUNDRY_SENSE_LIMITED: u8 = 2;
+
+// Roll20 dynamic lighting paths are drawn on the walls layer
+const ROLL20_LAYER: &str = "walls";
+const ROLL20_STROKE: &str = "#0000ff";
+const ROLL20_STROKE_WIDTH: u32 = 5;
+
+/// The data that is stored in a .vtt file, used to create a VTT with its fog of war
+#[derive(Deserialize)]
+pub(crate) struct VTTPartial {
+    pub(crate) format: f32,
+    pub(crate) resolution: Resolution,
+    pub(crate) line_of_sight: Vec<Vec<Coordinate>>,
+    /// Older vtt files do not have objects
+    #[serde(default)]
+    pub(crate) objects_line_of_sight: Vec<Vec<Coordinate>>,
+    pub(crate) portals: Vec<Portal>,
+    pub(crate) environment: Environment,
+    pub(crate) lights: Vec<Light>,
+    pub(crate) image: String,
+    /// Only present when the vtt was serialized with its fog of war, uvtt files do not have it
+    #[serde(default)]
+    pub(crate) fog_of_war: Option<FogOfWar>,
+}
+
+/// The data that is written to a uvtt file, which borrows the data of a vtt instead of copying it
+/// and leaves out the fog of war
+#[derive(Serialize)]
+struct UvttFile<'a> {
+    format: f32,
+    resolution: Cow<'a, Resolution>,
+    line_of_sight: &'a [Vec<Coordinate>],
+    objects_line_of_sight: &'a [Vec<Coordinate>],
+    portals: &'a [Portal],
+    environment: Cow<'a, Environment>,
+    lights: &'a [Light],
+    image: &'a str,
+}
+
+impl VTTPartial {
+    /// Create the VTT, returns an error if the resolution of the map is invalid, see
+    /// [`Resolution::validate`].
+    pub(crate) fn try_to_vtt(mut self) -> Result<VTT, RustVttError> {
+        if self.format.is_nan() || self.format > MAX_SUPPORTED_FORMAT {
+            return Err(RustVttError::UnsupportedFormat {
+                format: self.format,
+                supported: MAX_SUPPORTED_FORMAT,
+            });
+        }
+        self.resolution.validate()?;
+        // zero length segments break the planar graph of the rooms
+        for wall in self
+            .line_of_sight
+            .iter_mut()
+            .chain(self.objects_line_of_sight.iter_mut())
+        {
+            remove_zero_length_segments(wall);
+        }
+        // older versions write colors without alpha or in mixed case
+        for light in &mut self.lights {
+            if let Some(color) = normalize_hex_color(&light.color) {
+                light.color = color;
+            }
+        }
+        if let Some(ambient_light) = &mut self.environment.ambient_light {
+            if let Some(color) = normalize_hex_color(ambient_light) {
+                *ambient_light = color;
+            }
+        }
+        let fog_of_war = match self.fog_of_war {
+            Some(fog_of_war) => {
+                fog_of_war.check_resolution(&self.resolution)?;
+                fog_of_war
+            }
+            None => FogOfWar::new(&self.resolution),
+        };
+        return Ok(VTT {
+            format: self.format,
+            resolution: self.resolution,
+            line_of_sight: self.line_of_sight,
+            objects_line_of_sight: self.objects_line_of_sight,
+            portals: self.portals,
+            environment: self.environment,
+            lights: self.lights,
+            fog_of_war,
+            remembered_opacity: REMEMBERED_OPACITY,
+            fow_edge_blur: 0,
+            fow_layers: HashMap::new(),
+            rooms: OnceLock::new(),
+            los_step_size: STEP_SIZE,
+            wall_segments: Default::default(),
+            line_segments: Default::default(),
+            image: self.image,
+            #[cfg(feature = "image")]
+            decoded_image: OnceLock::new(),
+        });
+    }
+}
+
+/// Two vtts are equal when everything that is stored when serializing them is equal: the format,
+/// resolution, walls, objects, doors, lights, environment and image, and the area that is hidden
+/// by the fog of war (see [`FogOfWar`]'s `PartialEq`). This includes the settings that are not part of
+/// the vtt format but are kept on the walls and lights, such as
+/// [`set_portal_blocks_vision`][crate::vtt::VTT::set_portal_blocks_vision()] and the falloff of
+/// lights. What can be seen right now, the fog of war layers, the line of sight resolution and
+/// the cached data are not compared. The image is compared as it is stored, so the same image
+/// encoded differently is not equal.
+impl PartialEq for VTT {
+    fn eq(&self, other: &Self) -> bool {
+        return self.format == other.format
+            && self.resolution == other.resolution
+            && self.line_of_sight == other.line_of_sight
+            && self.objects_line_of_sight == other.objects_line_of_sight
+            && self.portals == other.portals
+            && self.environment == other.environment
+            && self.lights == other.lights
+            && self.image == other.image
+            && self.fog_of_war == other.fog_of_war;
+    }
+}
+
+impl TryFrom<VTTPartial> for VTT {
+    type Error = RustVttError;
+
+    fn try_from(partial: VTTPartial) -> Result<Self, Self::Error> {
+        return partial.try_to_vtt();
+    }
+}
+
+/// Parse a vtt from the contents of a vtt file, e.g. with `str::parse::<VTT>()`. This validates
+/// the vtt like [`open_vtt_from_bytes`][crate::open_vtt_from_bytes()] and returns the same errors.
+impl FromStr for VTT {
+    type Err = RustVttError;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        return open_vtt_from_bytes(contents.as_bytes());
+    }
+}
+
+impl TryFrom<&str> for VTT {
+    type Error = RustVttError;
+
+    fn try_from(contents: &str) -> Result<Self, Self::Error> {
+        return contents.parse();
+    }
 }
 
 #[doc(hidden)]
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct Resolution {
     map_origin: Coordinate,
     map_size: Coordinate,
     pixels_per_grid: i32,
+    /// The true size of the image in pixels, when it differs from the map size multiplied by the
+    /// pixels per grid. Only serialized along with the fog of war, which is made for this size,
+    /// uvtt files do not have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pixel_dimensions: Option<PixelCoordinate>,
 }
 
-#[doc(hidden)]
-#[derive(Serialize, Deserialize)]
+/// A problem with a wall of the map, see [`VTT::validate_walls`]. `wall` is the index of the wall
+/// and `point` the index of a point of the wall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WallIssue {
+    /// The point was already part of the wall before, other than a wall that ends where it started
+    DuplicatePoint { wall: usize, point: usize },
+    /// No part of the wall lies on the map
+    OutsideMap { wall: usize },
+}
+
+/// A light source on the map
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct Light {
     position: Coordinate,
     range: f64,
     intensity: f64,
     color: String,
     shadows: bool,
+    /// How the light fades towards its range, this is not stored in vtt files
+    #[serde(skip)]
+    falloff: Falloff,
+}
+
+/// How the brightness of a light decreases from its position to its range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Falloff {
+    /// Full brightness up to the range
+    Constant,
+    /// Fades linearly from full brightness at the position to nothing at the range
+    #[default]
+    Linear,
+    /// Fades with the inverse square of the distance in squares, smoothed to nothing at the range
+    InverseSquare,
+}
+
+impl Falloff {
+    /// Returns the brightness relative to the brightness at the position of a light, at the given
+    /// distance from the light in squares
+    #[cfg(feature = "image")]
+    fn strength(&self, distance: f64, range: f64) -> f64 {
+        if distance >= range {
+            return 0.0;
+        }
+        return match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => 1.0 - distance / range,
+            Falloff::InverseSquare => {
+                let window = 1.0 - (distance / range).powi(2);
+                window * window / (1.0 + distance * distance)
+            }
+        };
+    }
 }
 
 #[doc(hidden)]
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
 pub struct Environment {
     baked_lighting: bool,
     ambient_light: Option<String>,
 }
 
+impl Light {
+    /// Returns the position of the light in grid coordinates
+    pub fn position(&self) -> &Coordinate {
+        return &self.position;
+    }
+
+    /// Returns the range of the light in grid squares
+    pub fn range(&self) -> f64 {
+        return self.range;
+    }
+
+    /// Returns the intensity of the light
+    pub fn intensity(&self) -> f64 {
+        return self.intensity;
+    }
+
+    /// Returns the color of the light as a hex string
+    pub fn color(&self) -> &str {
+        return &self.color;
+    }
+
+    /// Returns whether the light casts shadows
+    pub fn shadows(&self) -> bool {
+        return self.shadows;
+    }
+
+    /// Set the position of the light in grid coordinates
+    pub fn set_position(&mut self, position: Coordinate) -> &mut Self {
+        self.position = position;
+        return self;
+    }
+
+    /// Set the range of the light in grid squares
+    pub fn set_range(&mut self, range: f64) -> &mut Self {
+        self.range = range;
+        return self;
+    }
+
+    /// Set the intensity of the light
+    pub fn set_intensity(&mut self, intensity: f64) -> &mut Self {
+        self.intensity = intensity;
+        return self;
+    }
+
+    /// Set the color of the light to a hex color in the form `rrggbb` or `aarrggbb`, optionally
+    /// prefixed with a `#`. Returns an error if the color is not a valid hex color.
+    pub fn set_color(&mut self, color: String) -> Result<&mut Self, RustVttError> {
+        if parse_hex_color(&color).is_none() {
+            return Err(RustVttError::InvalidColor { color });
+        }
+        self.color = color;
+        return Ok(self);
+    }
+
+    /// Set whether the light casts shadows
+    pub fn set_shadows(&mut self, shadows: bool) -> &mut Self {
+        self.shadows = shadows;
+        return self;
+    }
+
+    /// Returns how the light fades towards its range
+    pub fn falloff(&self) -> Falloff {
+        return self.falloff;
+    }
+
+    /// Set how the light fades towards its range. The default is [`Falloff::Linear`]. The falloff
+    /// is not stored in vtt files.
+    pub fn set_falloff(&mut self, falloff: Falloff) -> &mut Self {
+        self.falloff = falloff;
+        return self;
+    }
+}
+
+/// The ambient light of a map, which tints the entire image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmbientLight {
+    /// Untinted light, the image is shown as is
+    Daylight,
+    /// A dark blue tint
+    NightTime,
+    /// A custom hex color in the form `rrggbb` or `aarrggbb`, optionally prefixed with a `#`
+    Custom(String),
+}
+
+impl AmbientLight {
+    /// Returns the hex color of this ambient light in the `aarrggbb` form used by vtt files
+    fn to_hex(&self) -> Result<String, RustVttError> {
+        return match self {
+            AmbientLight::Daylight => Ok("ffffffff".to_string()),
+            AmbientLight::NightTime => Ok("ff515b6a".to_string()),
+            AmbientLight::Custom(color) => match parse_hex_color(color) {
+                Some([r, g, b]) => Ok(format!("ff{:02x}{:02x}{:02x}", r, g, b)),
+                None => Err(RustVttError::InvalidColor {
+                    color: color.clone(),
+                }),
+            },
+        };
+    }
+}
+
 #[doc(hidden)]
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, PartialEq)]
 pub struct Portal {
     position: Coordinate,
     bounds: Vec<Coordinate>,
     rotation: f64,
     closed: bool,
     freestanding: bool,
+    /// Whether the portal blocks vision even when it is open, such as a portcullis. This is not
+    /// part of the vtt format.
+    #[serde(skip)]
+    always_blocks_vision: bool,
+    /// When set, the portal only blocks vision for povs on one side of it, such as a secret door:
+    /// true for the left side of the line from the first to the second bound, as seen on the
+    /// image. This is not part of the vtt format.
+    #[serde(skip)]
+    one_way: Option<bool>,
 }
 
-#[doc(hidden)]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A coordinate on the map in grid squares
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct Coordinate {
     pub x: f64,
     pub y: f64,
 }
 
-impl Into<Coord> for Coordinate {
-    fn into(self) -> Coord {
+impl Resolution {
+    pub(crate) fn new(map_origin: Coordinate, map_size: Coordinate, pixels_per_grid: i32) -> Self {
+        return Self {
+            map_origin,
+            map_size,
+            pixels_per_grid,
+            pixel_dimensions: None,
+        };
+    }
+
+    /// Returns an error if the origin is negative, the size is not a positive finite number of
+    /// squares, the pixels per grid is not positive or the pixel dimensions are not positive. Maps
+    /// that do not end on a whole square are allowed.
+    pub(crate) fn validate(&self) -> Result<(), RustVttError> {
+        let origin = &self.map_origin;
+        if !(origin.x >= 0.0 && origin.y >= 0.0 && origin.x.is_finite() && origin.y.is_finite()) {
+            return Err(RustVttError::InvalidOrigin {
+                origin: origin.clone(),
+            });
+        }
+        let size = &self.map_size;
+        let positive = |value: f64| value > 0.0 && value.is_finite();
+        if !positive(size.x) || !positive(size.y) {
+            return Err(RustVttError::InvalidMapSize { size: size.clone() });
+        }
+        if self.pixels_per_grid <= 0 {
+            return Err(RustVttError::InvalidPixelsPerGrid {
+                pixels_per_grid: self.pixels_per_grid,
+            });
+        }
+        if let Some(dimensions) = self.pixel_dimensions {
+            if dimensions.x <= 0 || dimensions.y <= 0 {
+                return Err(RustVttError::InvalidPixelDimensions { dimensions });
+            }
+        }
+        return Ok(());
+    }
+
+    /// Returns an error if an image of the given size does not match the size of the map in pixels
+    pub(crate) fn check_image_size(&self, width: u32, height: u32) -> Result<(), RustVttError> {
+        let expected = self.pixel_size();
+        if width as i64 != expected.x as i64 || height as i64 != expected.y as i64 {
+            return Err(RustVttError::ImageSizeMismatch {
+                expected,
+                found: PixelCoordinate {
+                    x: width as i32,
+                    y: height as i32,
+                },
+            });
+        }
+        return Ok(());
+    }
+
+    /// Returns the size of the map image in pixels
+    pub(crate) fn pixel_size(&self) -> PixelCoordinate {
+        if let Some(dimensions) = self.pixel_dimensions {
+            return dimensions;
+        }
+        let ppg = self.pixels_per_grid as f64;
+        return PixelCoordinate {
+            x: (self.map_size.x * ppg).round() as i32,
+            y: (self.map_size.y * ppg).round() as i32,
+        };
+    }
+
+    /// Returns the number of pixels per square along the x and y axis. This is the pixels per grid
+    /// on both axes, unless the true pixel dimensions of the image are set.
+    pub(crate) fn scale(&self) -> Coord {
+        let Some(dimensions) = self.pixel_dimensions else {
+            let ppg = self.pixels_per_grid as f64;
+            return Coord { x: ppg, y: ppg };
+        };
+        return Coord {
+            x: dimensions.x as f64 / self.map_size.x,
+            y: dimensions.y as f64 / self.map_size.y,
+        };
+    }
+}
+
+/// A coordinate on the image in pixels
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PixelCoordinate {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Which walls block vision and whether a person can look around corners, see
+/// [`VTT::fow_change_with_config`]. The default is the vision of
+/// [`fow_change`][VTT::fow_change()]: walls and closed doors block vision, objects do not, and the
+/// person can not look around corners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VisionConfig {
+    /// Whether the walls of the map block vision
+    pub blocked_by_walls: bool,
+    /// Whether objects, such as furniture, block vision
+    pub blocked_by_objects: bool,
+    /// Whether closed doors and doors that always block vision block vision
+    pub blocked_by_closed_doors: bool,
+    /// Whether the person can look around corners, revealing the entire room they stand in
+    pub around_corners: bool,
+}
+
+impl Default for VisionConfig {
+    fn default() -> Self {
+        return VisionConfig {
+            blocked_by_walls: true,
+            blocked_by_objects: false,
+            blocked_by_closed_doors: true,
+            around_corners: false,
+        };
+    }
+}
+
+/// A read only view of a door (portal) on the map, see [`VTT::portals`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PortalInfo {
+    /// The middle of the door in grid coordinates
+    pub position: Coord,
+    /// The line between the two ends of the door in grid coordinates, or None if the portal does
+    /// not have two bounds
+    pub bounds: Option<Line>,
+    /// The angle of the door in radians
+    pub rotation: f64,
+    pub closed: bool,
+    /// Whether the portal is not part of a wall, such as a window
+    pub freestanding: bool,
+    /// Whether the portal blocks vision even when it is open, see
+    /// [`set_portal_blocks_vision`][crate::vtt::VTT::set_portal_blocks_vision()]
+    pub always_blocks_vision: bool,
+    /// Whether the portal only blocks vision from one side, see
+    /// [`set_portal_one_way`][crate::vtt::VTT::set_portal_one_way()]
+    pub one_way: bool,
+}
+
+impl From<&Portal> for PortalInfo {
+    fn from(portal: &Portal) -> Self {
+        return PortalInfo {
+            position: portal.position.clone().into(),
+            bounds: portal.line(),
+            rotation: portal.rotation,
+            closed: portal.closed,
+            freestanding: portal.freestanding,
+            always_blocks_vision: portal.always_blocks_vision,
+            one_way: portal.one_way.is_some(),
+        };
+    }
+}
+
+impl Portal {
+    /// Create a portal between two bounds, the position is the middle of the bounds and the
+    /// rotation is the angle of the line between them in radians.
+    pub(crate) fn new(
+        start: Coordinate,
+        end: Coordinate,
+        closed: bool,
+        freestanding: bool,
+    ) -> Self {
+        let position = Coordinate {
+            x: (start.x + end.x) / 2.0,
+            y: (start.y + end.y) / 2.0,
+        };
+        return Self {
+            position,
+            rotation: (end.y - start.y).atan2(end.x - start.x),
+            bounds: vec![start, end],
+            closed,
+            freestanding,
+            always_blocks_vision: false,
+            one_way: None,
+        };
+    }
+
+    /// Returns whether this portal blocks vision, which it does when it is closed or always
+    /// blocks vision
+    fn blocks_vision(&self) -> bool {
+        return self.closed || self.always_blocks_vision;
+    }
+
+    /// Returns whether this portal blocks vision for a person standing at the pov, which a one way
+    /// portal only does on its blocked side. A pov on the line of the portal is not blocked.
+    fn blocks_vision_from(&self, pov: Coord) -> bool {
+        let (Some(left), Some(line)) = (self.one_way, self.line()) else {
+            return self.blocks_vision();
+        };
+        let side = side_of_line(line, pov);
+        return self.blocks_vision() && side != 0.0 && (side < 0.0) == left;
+    }
+
+    /// Returns the line between the two bounds of this portal or None if the portal does not have
+    /// two bounds.
+    fn line(&self) -> Option<Line> {
+        match self.bounds.as_slice() {
+            [start, end, ..] => Some(Line::new(start.clone(), end.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl Coordinate {
+    /// Create a coordinate from its x and y position in grid squares
+    pub fn new(x: f64, y: f64) -> Self {
+        return Coordinate { x, y };
+    }
+
+    /// Convert a pixel on the image to the grid coordinate of the center of that pixel, relative to
+    /// the top left of the image. This does not take the origin of the map into account, see
+    /// [`VTT::pixel_to_grid`] for the coordinate on the map.
+    pub fn from_pixel(pixel: PixelCoordinate, pixels_per_grid: i32) -> Coordinate {
+        let ppg = pixels_per_grid as f64;
+        return Coordinate {
+            x: (pixel.x as f64 + 0.5) / ppg,
+            y: (pixel.y as f64 + 0.5) / ppg,
+        };
+    }
+}
+
+impl From<Coord> for Coordinate {
+    fn from(coord: Coord) -> Self {
+        Coordinate {
+            x: coord.x,
+            y: coord.y,
+        }
+    }
+}
+
+impl From<Coordinate> for Coord {
+    fn from(coordinate: Coordinate) -> Self {
         Coord {
-            x: self.x,
-            y: self.y,
+            x: coordinate.x,
+            y: coordinate.y,
         }
     }
 }
 
+impl From<(f64, f64)> for Coordinate {
+    fn from((x, y): (f64, f64)) -> Self {
+        Coordinate { x, y }
+    }
+}
+
+impl fmt::Display for Coordinate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+impl PixelCoordinate {
+    /// Create a pixel coordinate from its x and y position in pixels
+    pub fn new(x: i32, y: i32) -> Self {
+        return PixelCoordinate { x, y };
+    }
+}
+
+impl From<(i32, i32)> for PixelCoordinate {
+    fn from((x, y): (i32, i32)) -> Self {
+        PixelCoordinate { x, y }
+    }
+}
+
+impl fmt::Display for PixelCoordinate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
 impl VTT {
+    /// Returns the version of the vtt format the map was stored in
+    pub fn format(&self) -> f32 {
+        return self.format;
+    }
+
+    /// Returns whether the format version lies in the range of versions that are known to be read
+    /// correctly. Files with a newer version than supported can not be opened.
+    pub fn is_supported_format(&self) -> bool {
+        return (MIN_SUPPORTED_FORMAT..=MAX_SUPPORTED_FORMAT).contains(&self.format);
+    }
+
     /// Return the origin point of the VTT in squares
     pub fn origin(&self) -> &Coordinate {
         return &self.resolution.map_origin;
@@ -91,122 +747,5299 @@ impl VTT {
         return self.resolution.pixels_per_grid;
     }
 
-    /// Add fog of war to cover the entire image
-    pub fn fow_hide_all(&mut self) -> &mut Self {
-        self.fog_of_war.hide_all();
-        return self;
+    /// Returns the resolution of the map
+    pub fn resolution(&self) -> &Resolution {
+        return &self.resolution;
     }
 
-    /// Remove fog of war from the entire image
-    pub fn fow_show_all(&mut self) -> &mut Self {
-        self.fog_of_war.show_all();
-        return self;
+    /// Returns the size of the map in pixels, which is the size in squares multiplied by the pixels
+    /// per square, or the pixel dimensions set with
+    /// [`set_pixel_dimensions`][crate::vtt::VTT::set_pixel_dimensions()].
+    pub fn pixel_size(&self) -> PixelCoordinate {
+        return self.resolution.pixel_size();
     }
 
-    /// Given a coordinate on the image, this function should show everything that a person
-    /// standing at this coordinate could see, any objects blocking line of sight (defined in the
-    /// objects_line_of_sight parameter) are disregarded.
-    /// ## `pov`
-    /// The coordinate at which the person you want to reveal area for is standing
-    /// ## `around_walls`
-    /// Whether the person at the pov point can look around walls perfectly. When false, this will
-    /// function as a 'line of sight' fog of war reveal.
-    pub fn fow_show(&mut self, pov: Coordinate, around_walls: bool) -> Result<(), RustVttError> {
-        // this implementation will be around walls false for now
-        // First check if the given coordinate is not on the bounds of the grid
-        if pov.x >= self.size().x || pov.x < self.origin().x {
-            return Err(RustVttError::OutOfBounds { coordinate: pov });
+    /// Returns the origin of the map in pixels, which is the origin in squares multiplied by the
+    /// pixels per square.
+    pub fn pixel_origin(&self) -> PixelCoordinate {
+        let scale = self.resolution.scale();
+        return PixelCoordinate {
+            x: (self.origin().x * scale.x).round() as i32,
+            y: (self.origin().y * scale.y).round() as i32,
+        };
+    }
+
+    /// Returns the number of pixels per square along the x and y axis. Both are the
+    /// [`pixels_per_grid`][crate::vtt::VTT::pixels_per_grid()], unless the pixel dimensions are
+    /// set with [`set_pixel_dimensions`][crate::vtt::VTT::set_pixel_dimensions()].
+    pub fn pixel_scale(&self) -> (f64, f64) {
+        let scale = self.resolution.scale();
+        return (scale.x, scale.y);
+    }
+
+    /// Set the true size of the image in pixels, for images whose size is not exactly the map size
+    /// multiplied by the pixels per grid (see [`image_dimensions`][crate::vtt::VTT::image_dimensions()]).
+    /// Grid coordinates are then scaled separately along the x and y axis. None falls back to the
+    /// pixels per grid on both axes. The fog of war is reset, because its size changes. Returns an
+    /// error if the dimensions are not positive.
+    pub fn set_pixel_dimensions(
+        &mut self,
+        dimensions: Option<PixelCoordinate>,
+    ) -> Result<&mut Self, RustVttError> {
+        if let Some(dimensions) = dimensions {
+            if dimensions.x <= 0 || dimensions.y <= 0 {
+                return Err(RustVttError::InvalidPixelDimensions { dimensions });
+            }
+        }
+        self.resolution.pixel_dimensions = dimensions;
+        self.reset_fow();
+        return Ok(self);
+    }
+
+    /// Change the number of pixels per square, e.g. to save memory on large maps. The image is
+    /// resized to the new size of the map and the fog of war is scaled along with it. Walls, lights
+    /// and doors are in squares, so they stay on the same squares. Returns an error if the pixels
+    /// per grid is not positive or the image can not be decoded.
+    #[cfg(feature = "image")]
+    pub fn scale_pixels_per_grid(&mut self, pixels_per_grid: i32) -> Result<&mut Self> {
+        if pixels_per_grid <= 0 {
+            return Err(RustVttError::InvalidPixelsPerGrid { pixels_per_grid }.into());
         }
-        if pov.y >= self.size().y || pov.y < self.origin().y {
-            return Err(RustVttError::OutOfBounds { coordinate: pov });
+        let factor = pixels_per_grid as f64 / self.pixels_per_grid() as f64;
+        let scale = |pixel: PixelCoordinate| PixelCoordinate {
+            x: (pixel.x as f64 * factor).round() as i32,
+            y: (pixel.y as f64 * factor).round() as i32,
+        };
+        let image = self.take_decoded_image()?;
+        let hidden = self.fog_of_war.get_rectangles();
+        let layers_hidden: Vec<(String, Vec<FoWRectangle>)> = self
+            .fow_layers
+            .iter()
+            .map(|(name, layer)| (name.clone(), layer.get_rectangles()))
+            .collect();
+        self.resolution.pixels_per_grid = pixels_per_grid;
+        self.resolution.pixel_dimensions = self
+            .resolution
+            .pixel_dimensions
+            .map(scale)
+            .filter(|dimensions| dimensions.x > 0 && dimensions.y > 0);
+        let size = self.pixel_size();
+        self.set_image_from_pixbuf(&image.resize(size.x as u32, size.y as u32))?;
+        self.reset_fow();
+        let scale_fog = |fog_of_war: &mut FogOfWar, hidden: Vec<FoWRectangle>| {
+            for rectangle in hidden {
+                fog_of_war.update_rect(
+                    FoWRectangle {
+                        topleft: scale(rectangle.topleft),
+                        bottomright: scale(rectangle.bottomright),
+                    },
+                    Operation::Hide,
+                );
+            }
+        };
+        scale_fog(&mut self.fog_of_war, hidden);
+        for (name, hidden) in layers_hidden {
+            if let Some(layer) = self.fow_layers.get_mut(&name) {
+                scale_fog(layer, hidden);
+            }
         }
-        // Then check if the coordinate is not on a wall line
+        return Ok(self);
+    }
 
-        Ok(())
+    /// Returns the embedded image as it is stored in the vtt file, without decoding or re-encoding
+    /// it. This is usually a PNG image, see [`image_format`][crate::vtt::VTT::image_format()].
+    pub fn image_bytes(&self) -> Result<Vec<u8>> {
+        return Ok(BASE64_STANDARD.decode(self.image.as_str())?);
     }
 
-    /// Given a coordinate on the image, this function should hide everything that a person
-    /// standing at this coordinate could see. See [`fow_show`][crate::vtt::VTT::fow_show()] for param specifications.
-    pub fn fow_hide(&mut self, pov: Coordinate, around_walls: bool) {
-        todo!("Implement this function");
+    /// Returns the format of the embedded image, or None if it is not recognized. Some exporters
+    /// embed JPEG images, only PNG and JPEG images can be drawn on by this crate.
+    pub fn image_format(&self) -> Result<Option<ImageFormat>> {
+        // 16 base64 characters decode to 12 bytes, enough to recognize every format
+        let header = self.image.get(..16).unwrap_or(&self.image);
+        return Ok(guess_format(&BASE64_STANDARD.decode(header)?));
     }
 
-    /// Save the base64 encoded image of this vtt to a .png file.
-    /// ## `path`
-    /// The path to the file that the image will be exported to **excluding** the extension.
-    /// # Example
-    /// `save_image("path/to/filename")`
-    pub fn save_img_raw<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        // you can do path.as_ref() to get the path object
-        let decoded = BASE64_STANDARD.decode(self.image.as_str())?;
-        let mut file = File::options()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(&path)?;
-        file.write_all(&decoded)?;
-        Ok(())
+    /// Returns the actual width and height of the embedded image in pixels. Only the header of the
+    /// image is decoded. This can differ from [`pixel_size`][crate::vtt::VTT::pixel_size()] when
+    /// the image was not exported at exactly the map size.
+    pub fn image_dimensions(&self) -> Result<(u32, u32)> {
+        // 44 base64 characters decode to 33 bytes, enough for the signature and IHDR chunk
+        let header = self.image.get(..44).unwrap_or(&self.image);
+        let decoded = BASE64_STANDARD.decode(header)?;
+        if format::is_png(&decoded) {
+            return Ok(format::png_dimensions(&decoded)?);
+        }
+        // the frame header of a JPEG image can come after large metadata segments
+        return Ok(format::dimensions(&self.image_bytes()?)?);
     }
 
-    /// Apply all vtt data (fog of war, lighting, etc.) to the image stored in this vtt and save it to a .png file. This
-    /// function will **not** overwrite the existing image stored in the vtt.  
-    pub fn save_img<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        // clone the image
-        // self.fog_of_war.apply_to_image(image);
-        // self.environment.apply_to_image(image);
-        // self.lights.apply_to_image(image);
-        // save the image
-        todo!("Implement this function")
+    /// Replace the image of the map, which is stored as a PNG. Walls, lights and fog of war are
+    /// kept. Returns an error if the image is not the size of the map in pixels, see
+    /// [`set_pixel_dimensions`][crate::vtt::VTT::set_pixel_dimensions()] for images that are not
+    /// exported at exactly the map size.
+    #[cfg(feature = "image")]
+    pub fn set_image_from_pixbuf(&mut self, image: &Pixbuf) -> Result<&mut Self, RustVttError> {
+        self.resolution
+            .check_image_size(image.width(), image.height())?;
+        self.image = BASE64_STANDARD.encode(encode_png(image));
+        self.decoded_image = OnceLock::from(image.clone());
+        return Ok(self);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::open_vtt;
+    /// Replace the image of the map with a base64 encoded PNG or JPEG image like
+    /// [`set_image_from_pixbuf`][crate::vtt::VTT::set_image_from_pixbuf()]. The image is stored as
+    /// it is, only its header is read. Returns an error if the string is not valid base64 or the
+    /// image is not a PNG or JPEG image of the size of the map in pixels.
+    pub fn set_image_base64(&mut self, image: String) -> Result<&mut Self, RustVttError> {
+        let decoded =
+            BASE64_STANDARD
+                .decode(&image)
+                .map_err(|error| RustVttError::ImageDecode {
+                    reason: error.to_string(),
+                })?;
+        let (width, height) = format::dimensions(&decoded)?;
+        self.resolution.check_image_size(width, height)?;
+        self.image = image;
+        #[cfg(feature = "image")]
+        {
+            self.decoded_image = OnceLock::new();
+        }
+        return Ok(self);
+    }
 
-    #[test]
-    fn vtt_origin() {
-        let vtt = open_vtt("tests/resources/example1.dd2vtt")
-            .expect("Could not open file example1.dd2vtt");
-        let origin = vtt.origin();
-        assert_eq!(
-            origin.x, 0.0,
-            "x origin did not match. Expected 0.0, found {}",
-            origin.x
-        );
-        assert_eq!(
-            origin.y, 0.0,
-            "y origin did not match. Expected 0.0, found {}",
-            origin.y
-        );
+    /// Returns the fog of war of this vtt
+    pub fn fog_of_war(&self) -> &FogOfWar {
+        return &self.fog_of_war;
     }
 
-    #[test]
-    fn vtt_size() {
-        let vtt = open_vtt("tests/resources/example1.dd2vtt")
-            .expect("Could not open file example1.dd2vtt");
-        let size = vtt.size();
-        assert_eq!(
-            size.x, 27.0,
-            "x size did not match. Expected 27.0, found {}",
-            size.x
-        );
-        assert_eq!(
-            size.y, 15.0,
-            "y size did not match. Expected 15.0, found {}",
-            size.y
-        );
+    /// Returns every enclosed room in the map as a polygon in grid coordinates. The rooms are the
+    /// faces of the planar graph formed by the walls and doors of the map, so a door always
+    /// separates two rooms regardless of its state. Object walls (e.g. furniture) do not form rooms.
+    /// The rooms are calculated on the first call and cached afterwards.
+    pub fn rooms(&self) -> Vec<Polygon> {
+        return self
+            .rooms
+            .get_or_init(|| {
+                let mut segments = get_line_segments(self.line_of_sight.clone());
+                segments.extend(self.portals.iter().filter_map(Portal::line));
+                get_planar_faces(&segments)
+            })
+            .clone();
     }
 
-    #[test]
-    fn vtt_pixels_per_grid() {
-        let vtt = open_vtt("tests/resources/example1.dd2vtt")
-            .expect("Could not open file example1.dd2vtt");
-        assert_eq!(
-            vtt.pixels_per_grid(),
-            256,
-            "pixels per grid did not match. Expected 256, found {}",
-            vtt.pixels_per_grid()
+    /// Returns every enclosed room in the map like [`rooms`][crate::vtt::VTT::rooms()], except that
+    /// rooms connected by an open door are merged into a single room.
+    pub fn rooms_joined_by_open_doors(&self) -> Vec<Polygon> {
+        let rooms = self.rooms();
+        // union-find over the rooms, joining the rooms on both sides of every open door
+        let mut parent: Vec<usize> = (0..rooms.len()).collect();
+        fn find(parent: &mut Vec<usize>, i: usize) -> usize {
+            if parent[i] != i {
+                parent[i] = find(parent, parent[i]);
+            }
+            return parent[i];
+        }
+        for portal in self.portals.iter().filter(|p| !p.blocks_vision()) {
+            let Some(line) = portal.line() else {
+                continue;
+            };
+            let midpoint = Point::from((line.start + line.end) / 2.0);
+            let touching: Vec<usize> = rooms
+                .iter()
+                .enumerate()
+                .filter(|(_, room)| Euclidean::distance(&midpoint, *room) < 1e-6)
+                .map(|(i, _)| i)
+                .collect();
+            for pair in touching.windows(2) {
+                let (a, b) = (find(&mut parent, pair[0]), find(&mut parent, pair[1]));
+                parent[a] = b;
+            }
+        }
+
+        let mut merged: Vec<Option<MultiPolygon>> = vec![None; rooms.len()];
+        for (i, room) in rooms.into_iter().enumerate() {
+            let root = find(&mut parent, i);
+            merged[root] = Some(match merged[root].take() {
+                Some(joined) => joined.union(&MultiPolygon::new(vec![room])),
+                None => MultiPolygon::new(vec![room]),
+            });
+        }
+        return merged.into_iter().flatten().flatten().collect();
+    }
+
+    /// Returns the line segment of every door (portal) in the map in grid coordinates, together
+    /// with whether the door is closed. Portals without two bounds are skipped.
+    pub fn portal_segments(&self) -> Vec<(Line, bool)> {
+        return self
+            .portals
+            .iter()
+            .filter_map(|portal| Some((portal.line()?, portal.closed)))
+            .collect();
+    }
+
+    /// Rewrite the colors of all lights and the ambient light to the lowercase `aarrggbb` form used
+    /// by current versions of Dungeondraft, e.g. `#FF0000` becomes `ffff0000`. Returns an error
+    /// without changing any color if a light or the ambient light has an invalid color.
+    pub fn normalize_colors(&mut self) -> Result<&mut Self, RustVttError> {
+        let mut light_colors = Vec::with_capacity(self.lights.len());
+        for (index, light) in self.lights.iter().enumerate() {
+            let Some(color) = normalize_hex_color(&light.color) else {
+                return Err(RustVttError::InvalidLightColor {
+                    index,
+                    color: light.color.clone(),
+                });
+            };
+            light_colors.push(color);
+        }
+        let ambient_light = match &self.environment.ambient_light {
+            Some(color) => {
+                Some(
+                    normalize_hex_color(color).ok_or_else(|| RustVttError::InvalidColor {
+                        color: color.clone(),
+                    })?,
+                )
+            }
+            None => None,
+        };
+        for (light, color) in self.lights.iter_mut().zip(light_colors) {
+            light.color = color;
+        }
+        self.environment.ambient_light = ambient_light;
+        return Ok(self);
+    }
+
+    /// Returns every door (portal) of the map, in the same order as the indices used by
+    /// [`set_door_state`][crate::vtt::VTT::set_door_state()].
+    pub fn portals(&self) -> impl Iterator<Item = PortalInfo> + '_ {
+        return self.portals.iter().map(PortalInfo::from);
+    }
+
+    /// Returns the indices of all doors (portals) that lie within one square of the given position,
+    /// ordered from closest to furthest. The distance is measured to the line between the bounds
+    /// of the door, so wide doors are found from either end, or to the position of the door if it
+    /// does not have bounds.
+    pub fn doors_within_square(&self, position: Coordinate) -> Vec<usize> {
+        let position = Point::from(Coord::from(position));
+        let mut doors: Vec<(usize, f64)> = self
+            .portals
+            .iter()
+            .enumerate()
+            .map(|(i, portal)| {
+                let distance = match portal.line() {
+                    Some(line) => Euclidean::distance(&line, &position),
+                    None => {
+                        let door = Point::from(Coord::from(portal.position.clone()));
+                        Euclidean::distance(position, door)
+                    }
+                };
+                (i, distance)
+            })
+            .filter(|(_, distance)| *distance <= 1.0)
+            .collect();
+        doors.sort_by(|a, b| a.1.total_cmp(&b.1));
+        return doors.into_iter().map(|(i, _)| i).collect();
+    }
+
+    /// Returns the indices of all doors (portals) that a person standing at the pov can see, in the
+    /// order of [`portals`][crate::vtt::VTT::portals()]. A door is seen when the line between its
+    /// bounds crosses, touches or lies inside the line of sight, so closed doors that block the
+    /// view are included. Doors without bounds are seen when their position is. See
+    /// [`compute_los`][crate::vtt::VTT::compute_los()] for the params and errors.
+    pub fn visible_portals(
+        &self,
+        pov: Coordinate,
+        around_walls: bool,
+        through_objects: bool,
+    ) -> Result<Vec<usize>, RustVttError> {
+        let los = self.compute_los(pov, around_walls, through_objects)?;
+        return Ok(self
+            .portals
+            .iter()
+            .enumerate()
+            .filter(|(_, portal)| {
+                // the line of sight ends on a closed door, which may be off by rounding
+                let distance = match portal.line() {
+                    Some(line) => Euclidean::distance(&los, &line),
+                    None => Euclidean::distance(
+                        &los,
+                        &Point::from(Coord::from(portal.position.clone())),
+                    ),
+                };
+                distance <= WALL_TOLERANCE
+            })
+            .map(|(i, _)| i)
+            .collect());
+    }
+
+    /// Open or close the door (portal) with the given index. Returns false if there is no door
+    /// with this index.
+    pub fn set_door_state(&mut self, index: usize, closed: bool) -> bool {
+        let Some(portal) = self.portals.get_mut(index) else {
+            return false;
+        };
+        if portal.closed != closed {
+            portal.closed = closed;
+            self.invalidate_wall_segments();
+        }
+        return true;
+    }
+
+    /// Set whether the door (portal) with the given index blocks vision even when it is open, e.g.
+    /// for a portcullis or curtain that can be passed but not seen through. A closed door always
+    /// blocks vision. Returns false if there is no door with this index.
+    pub fn set_portal_blocks_vision(&mut self, index: usize, blocks_vision: bool) -> bool {
+        let Some(portal) = self.portals.get_mut(index) else {
+            return false;
+        };
+        if portal.always_blocks_vision != blocks_vision {
+            portal.always_blocks_vision = blocks_vision;
+            self.invalidate_wall_segments();
+        }
+        return true;
+    }
+
+    /// Make the door (portal) with the given index block vision only for povs on the same side of
+    /// it as `blocked_from`, e.g. for a secret door that is seen as a wall from outside of the room
+    /// but can be seen through from inside. None makes it block vision from both sides again. The
+    /// door still only blocks vision when it is closed or always blocks vision. Returns false if
+    /// there is no door with this index, or the door does not have two bounds or `blocked_from`
+    /// lies on the line of the door.
+    pub fn set_portal_one_way(&mut self, index: usize, blocked_from: Option<Coordinate>) -> bool {
+        let Some(portal) = self.portals.get_mut(index) else {
+            return false;
+        };
+        let one_way = match blocked_from {
+            Some(point) => {
+                let Some(line) = portal.line() else {
+                    return false;
+                };
+                let side = side_of_line(line, point.into());
+                if side == 0.0 {
+                    return false;
+                }
+                Some(side < 0.0)
+            }
+            None => None,
+        };
+        portal.one_way = one_way;
+        self.invalidate_wall_segments();
+        return true;
+    }
+
+    /// Open the door closest to the given position, if there is a door within one square of the
+    /// position. Returns whether a door was found.
+    pub fn open_door(&mut self, position: Coordinate) -> bool {
+        return match self.doors_within_square(position).first() {
+            Some(&index) => self.set_door_state(index, false),
+            None => false,
+        };
+    }
+
+    /// Close the door closest to the given position, if there is a door within one square of the
+    /// position. Returns whether a door was found.
+    pub fn close_door(&mut self, position: Coordinate) -> bool {
+        return match self.doors_within_square(position).first() {
+            Some(&index) => self.set_door_state(index, true),
+            None => false,
+        };
+    }
+
+    /// Open the door closest to the given position if it is closed and close it otherwise, if there
+    /// is a door within one square of the position. Returns whether the door is now closed, or
+    /// None if no door was found.
+    pub fn toggle_door(&mut self, position: Coordinate) -> Option<bool> {
+        let index = *self.doors_within_square(position).first()?;
+        let closed = !self.portals[index].closed;
+        self.set_door_state(index, closed);
+        return Some(closed);
+    }
+
+    /// Open every door of the map
+    pub fn open_all_doors(&mut self) -> &mut Self {
+        self.set_all_doors_state(false);
+        return self;
+    }
+
+    /// Close every door of the map
+    pub fn close_all_doors(&mut self) -> &mut Self {
+        self.set_all_doors_state(true);
+        return self;
+    }
+
+    /// Helper function: opens or closes every door, invalidating the wall segments if any door
+    /// changed
+    fn set_all_doors_state(&mut self, closed: bool) {
+        let mut changed = false;
+        for portal in &mut self.portals {
+            changed |= portal.closed != closed;
+            portal.closed = closed;
+        }
+        if changed {
+            self.invalidate_wall_segments();
+        }
+    }
+
+    /// Draw every door of the map onto an image of this vtt. Open doors are drawn in `open_color`
+    /// and closed doors in `closed_color`. The image is expected to be the size of the map in
+    /// pixels, doors that fall outside of the image are not drawn.
+    #[cfg(feature = "image")]
+    pub fn draw_portals(&self, image: &mut Pixbuf, open_color: Rgb, closed_color: Rgb) {
+        let thickness = (self.pixels_per_grid() as f64 / 16.0).max(1.0);
+        for (line, closed) in self.portal_segments() {
+            let line = Line::new(self.to_pixel(line.start), self.to_pixel(line.end));
+            let color = if closed { closed_color } else { open_color };
+            image.draw_line(line, color, thickness);
+        }
+    }
+
+    /// Export the walls, objects and portals of the map as Foundry VTT scene walls in pixel
+    /// coordinates. Every wall is an object of the form `{c: [x1, y1, x2, y2], door, ds, sense,
+    /// move}`. Portals become doors with their open or closed state, except for freestanding
+    /// portals (windows), which become walls with limited (terrain) sense.
+    pub fn export_foundry_walls(&self) -> serde_json::Value {
+        let mut walls: Vec<serde_json::Value> = Vec::new();
+        let mut push_wall = |line: Line, door: u8, state: u8, sense: u8| {
+            let start = self.to_pixel(line.start);
+            let end = self.to_pixel(line.end);
+            walls.push(serde_json::json!({
+                "c": [start.x, start.y, end.x, end.y],
+                "door": door,
+                "ds": state,
+                "sense": sense,
+                "move": 1,
+            }));
+        };
+        let mut segments = get_line_segments(self.line_of_sight.clone());
+        segments.extend(get_line_segments(self.objects_line_of_sight.clone()));
+        dedup_segments(&mut segments);
+        for line in segments {
+            push_wall(
+                line,
+                FOUNDRY_DOOR_NONE,
+                FOUNDRY_DOOR_CLOSED,
+                FOUNDRY_SENSE_NORMAL,
+            );
+        }
+        for portal in &self.portals {
+            let Some(line) = portal.line() else {
+                continue;
+            };
+            if portal.freestanding {
+                push_wall(
+                    line,
+                    FOUNDRY_DOOR_NONE,
+                    FOUNDRY_DOOR_CLOSED,
+                    FOUNDRY_SENSE_LIMITED,
+                );
+            } else if portal.closed {
+                push_wall(
+                    line,
+                    FOUNDRY_DOOR,
+                    FOUNDRY_DOOR_CLOSED,
+                    FOUNDRY_SENSE_NORMAL,
+                );
+            } else {
+                push_wall(line, FOUNDRY_DOOR, FOUNDRY_DOOR_OPEN, FOUNDRY_SENSE_NORMAL);
+            }
+        }
+        return serde_json::Value::Array(walls);
+    }
+
+    /// Export the walls, objects and doors of the map as a GeoJSON `FeatureCollection` of
+    /// `LineString` features in grid coordinates, e.g. for analysis with GIS tools. Every feature
+    /// has a `kind` property that is `wall`, `object` or `door`, doors also have a `closed`
+    /// property.
+    pub fn walls_geojson(&self) -> String {
+        let line_string = |points: Vec<Coord>, properties: serde_json::Value| {
+            let coordinates: Vec<[f64; 2]> =
+                points.iter().map(|point| [point.x, point.y]).collect();
+            serde_json::json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": coordinates,
+                },
+                "properties": properties,
+            })
+        };
+        let mut features: Vec<serde_json::Value> = Vec::new();
+        for (walls, kind) in [
+            (&self.line_of_sight, "wall"),
+            (&self.objects_line_of_sight, "object"),
+        ] {
+            features.extend(walls.iter().filter(|wall| wall.len() >= 2).map(|wall| {
+                let points = wall.iter().cloned().map(Coord::from).collect();
+                line_string(points, serde_json::json!({ "kind": kind }))
+            }));
+        }
+        for portal in &self.portals {
+            if let Some(line) = portal.line() {
+                features.push(line_string(
+                    vec![line.start, line.end],
+                    serde_json::json!({ "kind": "door", "closed": portal.closed }),
+                ));
+            }
+        }
+        return serde_json::json!({
+            "type": "FeatureCollection",
+            "features": features,
+        })
+        .to_string();
+    }
+
+    /// Export the walls, objects and closed doors of the map as Roll20 dynamic lighting paths in
+    /// pixel coordinates. Every wall is a path object on the `walls` layer of the form `{layer,
+    /// path, left, top, width, height, stroke, stroke_width, fill}`, where `path` is the Roll20
+    /// list of `["M", x, y]` and `["L", x, y]` commands relative to the top left of the path,
+    /// encoded as a string, and `left` and `top` are the center of the path. Open doors are left
+    /// out, so they do not block vision, unless they
+    /// [always block vision][crate::vtt::VTT::set_portal_blocks_vision()].
+    pub fn export_roll20_paths(&self) -> serde_json::Value {
+        let mut paths: Vec<serde_json::Value> = Vec::new();
+        let mut push_path = |points: Vec<Coord>| {
+            if points.len() < 2 {
+                return;
+            }
+            let points: Vec<Coord> = points.into_iter().map(|p| self.to_pixel(p)).collect();
+            let (mut min, mut max) = (points[0], points[0]);
+            for point in &points {
+                min = Coord {
+                    x: min.x.min(point.x),
+                    y: min.y.min(point.y),
+                };
+                max = Coord {
+                    x: max.x.max(point.x),
+                    y: max.y.max(point.y),
+                };
+            }
+            let commands: Vec<serde_json::Value> = points
+                .iter()
+                .enumerate()
+                .map(|(i, point)| {
+                    let command = if i == 0 { "M" } else { "L" };
+                    serde_json::json!([command, point.x - min.x, point.y - min.y])
+                })
+                .collect();
+            paths.push(serde_json::json!({
+                "layer": ROLL20_LAYER,
+                "path": serde_json::Value::Array(commands).to_string(),
+                "left": (min.x + max.x) / 2.0,
+                "top": (min.y + max.y) / 2.0,
+                "width": max.x - min.x,
+                "height": max.y - min.y,
+                "stroke": ROLL20_STROKE,
+                "stroke_width": ROLL20_STROKE_WIDTH,
+                "fill": "transparent",
+            }));
+        };
+        for wall in self
+            .line_of_sight
+            .iter()
+            .chain(self.objects_line_of_sight.iter())
+        {
+            push_path(wall.iter().cloned().map(Coord::from).collect());
+        }
+        for portal in self.portals.iter().filter(|portal| portal.blocks_vision()) {
+            if let Some(line) = portal.line() {
+                push_path(vec![line.start, line.end]);
+            }
+        }
+        return serde_json::Value::Array(paths);
+    }
+
+    /// Convert a grid coordinate on the map to the pixel of the image that contains it, taking the
+    /// origin of the map into account. The pixel may lie outside of the image.
+    pub fn grid_to_pixel(&self, coordinate: Coordinate) -> PixelCoordinate {
+        let pixel = self.to_pixel(coordinate.into());
+        return PixelCoordinate {
+            x: pixel.x.floor() as i32,
+            y: pixel.y.floor() as i32,
+        };
+    }
+
+    /// Convert a pixel of the image to the grid coordinate on the map of the center of that pixel,
+    /// taking the origin of the map into account. This is the inverse of
+    /// [`grid_to_pixel`][crate::vtt::VTT::grid_to_pixel()], e.g. to convert a click on the image to
+    /// a pov for [`fow_change`][crate::vtt::VTT::fow_change()].
+    pub fn pixel_to_grid(&self, pixel: PixelCoordinate) -> Coordinate {
+        let scale = self.resolution.scale();
+        return Coordinate {
+            x: self.origin().x + (pixel.x as f64 + 0.5) / scale.x,
+            y: self.origin().y + (pixel.y as f64 + 0.5) / scale.y,
+        };
+    }
+
+    /// Helper function: converts a grid coordinate to a pixel coordinate on the image
+    fn to_pixel(&self, coordinate: Coord) -> Coord {
+        let origin: Coord = self.origin().clone().into();
+        let scale = self.resolution.scale();
+        let offset = coordinate - origin;
+        return Coord {
+            x: offset.x * scale.x,
+            y: offset.y * scale.y,
+        };
+    }
+
+    /// Returns all light sources of the map
+    pub fn lights(&self) -> &[Light] {
+        return &self.lights;
+    }
+
+    /// Returns the outline of every object that blocks line of sight in grid coordinates
+    pub fn objects_los(&self) -> &[Vec<Coordinate>] {
+        return &self.objects_line_of_sight;
+    }
+
+    /// Returns every wall of the map as the points it runs through in grid coordinates
+    pub fn line_of_sight(&self) -> &[Vec<Coordinate>] {
+        return &self.line_of_sight;
+    }
+
+    /// Add a wall that runs through the given points in grid coordinates. Returns the index of the
+    /// new wall.
+    pub fn add_wall(&mut self, mut wall: Vec<Coordinate>) -> usize {
+        remove_zero_length_segments(&mut wall);
+        self.line_of_sight.push(wall);
+        self.invalidate_walls();
+        return self.line_of_sight.len() - 1;
+    }
+
+    /// Remove the wall with the given index. Returns false if there is no wall with this index.
+    /// Note that the indices of all following walls shift down by one.
+    pub fn remove_wall(&mut self, index: usize) -> bool {
+        if index >= self.line_of_sight.len() {
+            return false;
+        }
+        self.line_of_sight.remove(index);
+        self.invalidate_walls();
+        return true;
+    }
+
+    /// Replace the points of the wall with the given index, e.g. to move the wall. Returns false
+    /// if there is no wall with this index.
+    pub fn update_wall(&mut self, index: usize, mut wall: Vec<Coordinate>) -> bool {
+        let Some(existing) = self.line_of_sight.get_mut(index) else {
+            return false;
+        };
+        remove_zero_length_segments(&mut wall);
+        *existing = wall;
+        self.invalidate_walls();
+        return true;
+    }
+
+    /// Returns the problems with the walls of this map that can give unexpected line of sight,
+    /// such as walls that cross themselves or walls outside of the map. Walls with two identical
+    /// points in a row are already fixed when the map is opened.
+    pub fn validate_walls(&self) -> Vec<WallIssue> {
+        let map = self.map_rect();
+        let mut issues = Vec::new();
+        for (index, wall) in self.line_of_sight.iter().enumerate() {
+            for (point, coordinate) in wall.iter().enumerate() {
+                let closes_ring = point == wall.len() - 1;
+                let repeated = wall[..point].iter().enumerate().any(|(i, other)| {
+                    other.x == coordinate.x && other.y == coordinate.y && !(closes_ring && i == 0)
+                });
+                if repeated {
+                    issues.push(WallIssue::DuplicatePoint { wall: index, point });
+                }
+            }
+            let on_map = match wall.as_slice() {
+                [point] => map.intersects(&Coord::from(point.clone())),
+                _ => get_line_segments(vec![wall.clone()])
+                    .iter()
+                    .any(|segment| segment.intersects(&map)),
+            };
+            if !on_map {
+                issues.push(WallIssue::OutsideMap { wall: index });
+            }
+        }
+        return issues;
+    }
+
+    /// Add an object that blocks line of sight, such as furniture, with an outline through the
+    /// given points in grid coordinates. The object blocks vision when revealing fog of war
+    /// without looking through objects.
+    pub fn add_object_los(&mut self, mut object: Vec<Coordinate>) -> &mut Self {
+        remove_zero_length_segments(&mut object);
+        self.objects_line_of_sight.push(object);
+        self.line_segments[1] = OnceLock::new();
+        self.invalidate_wall_segments();
+        return self;
+    }
+
+    /// Remove every object that blocks line of sight
+    pub fn clear_objects_los(&mut self) -> &mut Self {
+        self.objects_line_of_sight.clear();
+        self.line_segments[1] = OnceLock::new();
+        self.invalidate_wall_segments();
+        return self;
+    }
+
+    /// Returns the light source with the given index to edit it, or None if there is no light with
+    /// this index.
+    pub fn light_mut(&mut self, index: usize) -> Option<&mut Light> {
+        return self.lights.get_mut(index);
+    }
+
+    /// Add a new light source to the map and return its index. The color is a hex color in the
+    /// form `rrggbb` or `aarrggbb`, optionally prefixed with a `#`. Returns an error if the color is
+    /// not a valid hex color.
+    pub fn add_light(
+        &mut self,
+        position: Coordinate,
+        range: f64,
+        intensity: f64,
+        color: String,
+        shadows: bool,
+    ) -> Result<usize, RustVttError> {
+        if parse_hex_color(&color).is_none() {
+            return Err(RustVttError::InvalidColor { color });
+        }
+        self.lights.push(Light {
+            position,
+            range,
+            intensity,
+            color,
+            shadows,
+            falloff: Falloff::default(),
+        });
+        return Ok(self.lights.len() - 1);
+    }
+
+    /// Remove the light source with the given index. Returns false if there is no light with this
+    /// index. Note that the indices of all following lights shift down by one.
+    pub fn remove_light(&mut self, index: usize) -> bool {
+        if index >= self.lights.len() {
+            return false;
+        }
+        self.lights.remove(index);
+        return true;
+    }
+
+    /// Returns the number of light sources on the map
+    pub fn lights_len(&self) -> usize {
+        return self.lights.len();
+    }
+
+    /// Remove every light source from the map
+    pub fn clear_lights(&mut self) {
+        self.lights.clear();
+    }
+
+    /// Replace every light source of the map, e.g. with a copy of [`lights`][VTT::lights()] taken
+    /// earlier to switch between lighting setups. Returns an error without changing the lights if
+    /// a light has an invalid color.
+    pub fn set_lights(&mut self, lights: Vec<Light>) -> Result<(), RustVttError> {
+        for (index, light) in lights.iter().enumerate() {
+            if parse_hex_color(&light.color).is_none() {
+                return Err(RustVttError::InvalidLightColor {
+                    index,
+                    color: light.color.clone(),
+                });
+            }
+        }
+        self.lights = lights;
+        Ok(())
+    }
+
+    /// Set the ambient light of the map, which tints every pixel of the image when lighting is
+    /// applied. `None` removes the ambient tint.
+    ///
+    /// # Example
+    /// ```
+    /// use vtt_rust::{open_vtt, AmbientLight};
+    ///
+    /// let mut vtt = open_vtt("tests/resources/example1.dd2vtt").unwrap();
+    /// vtt.set_ambient_light(AmbientLight::NightTime).unwrap();
+    /// vtt.set_ambient_light(None).unwrap();
+    /// ```
+    pub fn set_ambient_light<A: Into<Option<AmbientLight>>>(
+        &mut self,
+        ambient_light: A,
+    ) -> Result<&mut Self, RustVttError> {
+        self.environment.ambient_light = match ambient_light.into() {
+            Some(ambient_light) => Some(ambient_light.to_hex()?),
+            None => None,
+        };
+        return Ok(self);
+    }
+
+    /// Returns the ambient light color of the map as a hex string, if there is any
+    pub fn ambient_light(&self) -> Option<&str> {
+        return self.environment.ambient_light.as_deref();
+    }
+
+    /// Add fog of war to cover the entire image
+    pub fn fow_hide_all(&mut self) -> &mut Self {
+        self.fog_of_war.hide_all();
+        return self;
+    }
+
+    /// Remove fog of war from the entire image
+    pub fn fow_show_all(&mut self) -> &mut Self {
+        self.fog_of_war.show_all();
+        return self;
+    }
+
+    /// Returns whether the given coordinate is not covered by fog of war. Coordinates outside of the
+    /// map are never visible.
+    pub fn is_visible(&self, coordinate: Coordinate) -> bool {
+        let pixel = self.grid_to_pixel(coordinate);
+        let size = self.pixel_size();
+        if pixel.x < 0 || pixel.y < 0 || pixel.x >= size.x || pixel.y >= size.y {
+            return false;
+        }
+        return !self.fog_of_war.is_hidden(pixel);
+    }
+
+    /// Returns the fraction of the square containing the given coordinate that is not covered by fog
+    /// of war, from 0.0 when the square is hidden to 1.0 when it is revealed. See
+    /// [`FogOfWar::visible_fraction`].
+    pub fn visible_fraction(&self, coordinate: Coordinate) -> f64 {
+        let origin = self.origin();
+        let topleft = Coordinate {
+            x: origin.x + (coordinate.x - origin.x).floor(),
+            y: origin.y + (coordinate.y - origin.y).floor(),
+        };
+        let bottomright = Coordinate {
+            x: topleft.x + 1.0,
+            y: topleft.y + 1.0,
+        };
+        return self.fog_of_war.visible_fraction(FoWRectangle {
+            topleft: self.grid_to_pixel(topleft),
+            bottomright: self.grid_to_pixel(bottomright),
+        });
+    }
+
+    /// Returns the fraction of the map that is revealed, between 0 and 1, e.g. to show how much of
+    /// the map the players explored
+    pub fn explored_fraction(&self) -> f64 {
+        return self.fog_of_war.explored_fraction();
+    }
+
+    /// Returns whether the whole map is revealed, see [`FogOfWar::is_fully_revealed`]
+    pub fn is_fully_explored(&self) -> bool {
+        return self.fog_of_war.is_fully_revealed();
+    }
+
+    /// Returns the revealed point of the map closest to the coordinate, e.g. to travel to the
+    /// nearest explored area. This is the center of the closest revealed pixel, or None if nothing
+    /// is revealed.
+    pub fn nearest_visible(&self, from: Coordinate) -> Option<Coordinate> {
+        let pixel = self.grid_to_pixel(from);
+        let rectangle = self.fog_of_war.nearest_visible(pixel)?;
+        return Some(self.pixel_to_grid(rectangle.closest_pixel(pixel)));
+    }
+
+    /// Rebuild the fog of war and every fog of war layer for the current resolution of the map,
+    /// where everything is revealed. Use this after changing the resolution of the map, so the fog
+    /// of war matches the image again.
+    pub fn reset_fow(&mut self) -> &mut Self {
+        self.fog_of_war.resize(&self.resolution);
+        for layer in self.fow_layers.values_mut() {
+            layer.resize(&self.resolution);
+        }
+        return self;
+    }
+
+    /// Set the smallest width or height in pixels of the rectangles the fog of war is split into,
+    /// see [`FogOfWar::set_min_square_size`]. Returns an error if the value is smaller than 1.
+    pub fn set_fow_min_square_size(
+        &mut self,
+        min_square_size: i32,
+    ) -> Result<&mut Self, RustVttError> {
+        self.fog_of_war.set_min_square_size(min_square_size)?;
+        return Ok(self);
+    }
+
+    /// Add a fog of war layer with the given name next to the fog of war of the map, e.g. to keep
+    /// what the players see apart from what the game master revealed. The layer has the resolution
+    /// of the map and starts out hidden everywhere. If a layer with this name exists, it is
+    /// returned unchanged. Layers are not stored in vtt files and are reset like the fog of war
+    /// when the resolution of the map changes, see [`reset_fow`][crate::vtt::VTT::reset_fow()].
+    pub fn add_fow_layer(&mut self, name: &str) -> &mut FogOfWar {
+        return self.fow_layers.entry(name.to_string()).or_insert_with(|| {
+            let mut layer = FogOfWar::new(&self.resolution);
+            layer.hide_all();
+            layer
+        });
+    }
+
+    /// Returns the fog of war layer with the given name, or None if there is no such layer
+    pub fn fow_layer(&self, name: &str) -> Option<&FogOfWar> {
+        return self.fow_layers.get(name);
+    }
+
+    /// Returns a mutable reference to the fog of war layer with the given name, or None if there
+    /// is no such layer. A line of sight from [`compute_los`][crate::vtt::VTT::compute_los()] can
+    /// be applied to it after converting it with [`los_to_pixels`][crate::vtt::VTT::los_to_pixels()].
+    pub fn fow_layer_mut(&mut self, name: &str) -> Option<&mut FogOfWar> {
+        return self.fow_layers.get_mut(name);
+    }
+
+    /// Remove the fog of war layer with the given name. Returns false if there is no such layer.
+    pub fn remove_fow_layer(&mut self, name: &str) -> bool {
+        return self.fow_layers.remove(name).is_some();
+    }
+
+    /// Save the fog of war state of this vtt to a file, so it can be restored with
+    /// [`load_fow`][crate::vtt::VTT::load_fow()] after the vtt is opened again.
+    pub fn save_fow<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = File::options()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&path)?;
+        file.write_all(&self.fog_of_war.serialize())?;
+        Ok(())
+    }
+
+    /// Save the fog of war as an RGBA PNG mask of the size of the map in pixels, where hidden areas
+    /// are opaque white and everything else is fully transparent. This can be used to overlay the
+    /// fog of war on the map image elsewhere.
+    #[cfg(feature = "image")]
+    pub fn save_fow_mask<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let size = self.pixel_size();
+        let (width, height) = (size.x.max(0) as usize, size.y.max(0) as usize);
+        let mut mask = vec![0u8; width * height * 4];
+        for rectangle in self.fog_of_war.get_rectangles() {
+            let left = rectangle.topleft.x.clamp(0, size.x) as usize;
+            let right = rectangle.bottomright.x.clamp(0, size.x) as usize;
+            let top = rectangle.topleft.y.clamp(0, size.y) as usize;
+            let bottom = rectangle.bottomright.y.clamp(0, size.y) as usize;
+            for row in top..bottom {
+                mask[(row * width + left) * 4..(row * width + right) * 4].fill(u8::MAX);
+            }
+        }
+        let mut file = File::options()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&path)?;
+        file.write_all(&encode_png_rgba(width as u32, height as u32, &mask))?;
+        Ok(())
+    }
+
+    /// Restore the fog of war state of this vtt from a file written by
+    /// [`save_fow`][crate::vtt::VTT::save_fow()]. Returns an error if the fog of war was saved for
+    /// a map with a different resolution.
+    pub fn load_fow<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let bytes = std::fs::read(path)?;
+        self.fog_of_war = FogOfWar::deserialize(&bytes, &self.resolution)?
+            .with_min_square_size(self.fog_of_war.min_square_size())?;
+        Ok(())
+    }
+
+    /// Hide or reveal a circle around a point, ignoring any walls. This is useful for effects that
+    /// reveal an area in all directions, e.g. a torch. The circle is approximated by a polygon
+    /// with 64 segments, see [`fow_reveal_circle_with_segments`][crate::vtt::VTT::fow_reveal_circle_with_segments()]
+    /// to change this.
+    /// ## `center`
+    /// The center of the circle in grid coordinates
+    /// ## `radius`
+    /// The radius of the circle in grid squares, a radius of zero or less does nothing
+    pub fn fow_reveal_circle(
+        &mut self,
+        center: Coordinate,
+        radius: f64,
+        operation: Operation,
+    ) -> &mut Self {
+        return self.fow_reveal_circle_with_segments(center, radius, operation, CIRCLE_SEGMENTS);
+    }
+
+    /// Hide or reveal a circle around a point like [`fow_reveal_circle`][crate::vtt::VTT::fow_reveal_circle()],
+    /// where the circle is approximated by a polygon with the given number of segments (at least
+    /// 3).
+    pub fn fow_reveal_circle_with_segments(
+        &mut self,
+        center: Coordinate,
+        radius: f64,
+        operation: Operation,
+        segments: usize,
+    ) -> &mut Self {
+        if radius <= 0.0 || !radius.is_finite() {
+            return self;
+        }
+        let center = self.to_pixel(center.into());
+        // the circle is stretched when the image is scaled differently along the x and y axis
+        let radius = self.resolution.scale() * radius;
+        let circle = circle_polygon(center, radius, segments);
+        let size = self.resolution.pixel_size();
+        let map = Rect::new(
+            Coord { x: 0.0, y: 0.0 },
+            Coord {
+                x: size.x as f64,
+                y: size.y as f64,
+            },
+        )
+        .to_polygon();
+        for clipped in circle.intersection(&map) {
+            self.fog_of_war.update(&clipped, operation);
+        }
+        return self;
+    }
+
+    /// Hide or reveal the rectangle between two corners, without taking walls into account. The
+    /// corners may be given in any order.
+    pub fn fow_change_rect(
+        &mut self,
+        topleft: Coordinate,
+        bottomright: Coordinate,
+        operation: Operation,
+    ) -> &mut Self {
+        let rect = Rect::new(
+            self.to_pixel(topleft.into()),
+            self.to_pixel(bottomright.into()),
+        );
+        self.fog_of_war.update(&rect.to_polygon(), operation);
+        return self;
+    }
+
+    /// Given a coordinate on the image, this function should show everything that a person
+    /// standing at this coordinate could see, any objects blocking line of sight (defined in the
+    /// objects_line_of_sight parameter) are disregarded.
+    /// ## `pov`
+    /// The coordinate at which the person you want to reveal area for is standing
+    /// ## `around_walls`
+    /// Whether the person at the pov point can look around walls perfectly. When false, this will
+    /// function as a 'line of sight' fog of war reveal.
+    pub fn fow_show(&mut self, pov: Coordinate, around_walls: bool) -> Result<(), RustVttError> {
+        return self.fow_change(pov, Operation::Show, around_walls);
+    }
+
+    /// Given a coordinate on the image, this function should hide everything that a person
+    /// standing at this coordinate could see. See [`fow_show`][crate::vtt::VTT::fow_show()] for param specifications.
+    pub fn fow_hide(&mut self, pov: Coordinate, around_walls: bool) -> Result<(), RustVttError> {
+        return self.fow_change(pov, Operation::Hide, around_walls);
+    }
+
+    /// Hide or show everything that a person standing at the pov could see. See
+    /// [`fow_show`][crate::vtt::VTT::fow_show()] for param specifications.
+    pub fn fow_change(
+        &mut self,
+        pov: Coordinate,
+        operation: Operation,
+        around_walls: bool,
+    ) -> Result<(), RustVttError> {
+        let config = VisionConfig {
+            around_corners: around_walls,
+            ..VisionConfig::default()
+        };
+        return self.fow_change_with_config(pov, operation, &config);
+    }
+
+    /// Hide or show everything that a person standing at the pov could see, where the config
+    /// decides which walls, objects and doors block vision and whether the person can look around
+    /// corners, e.g. for tremorsense that is only blocked by the walls of the map. Returns an error
+    /// if the pov lies outside of the map.
+    pub fn fow_change_with_config(
+        &mut self,
+        pov: Coordinate,
+        operation: Operation,
+        config: &VisionConfig,
+    ) -> Result<(), RustVttError> {
+        let polygon = self.los_to_pixels(&self.compute_los_with_config(pov, config)?);
+        self.fog_of_war.update(&polygon, operation);
+        return Ok(());
+    }
+
+    /// Hide or show everything that a person standing at the pov could see like
+    /// [`fow_change`][crate::vtt::VTT::fow_change()], calling `progress` with how far along the
+    /// update is, from 0 to 1, e.g. to show a progress bar for large maps. Casting the rays of the
+    /// line of sight takes up to 0.8 and is reported in steps, the line of sight around walls is
+    /// reported once it is done. 1 is reported once the fog of war is updated. The result is the
+    /// same as [`fow_change`][crate::vtt::VTT::fow_change()].
+    pub fn fow_change_with_progress(
+        &mut self,
+        pov: Coordinate,
+        operation: Operation,
+        around_walls: bool,
+        progress: &mut dyn FnMut(f32),
+    ) -> Result<(), RustVttError> {
+        const LOS_SHARE: f32 = 0.8;
+        self.check_in_bounds(&pov)?;
+        progress(0.0);
+        let pov = pov.into();
+        let los = if around_walls {
+            self.calculate_indirect_los(pov)
+        } else {
+            calculate_direct_los_with_progress(
+                pov,
+                &self.segments_seen_from(pov, false),
+                self.map_rect(),
+                self.los_step_size,
+                &mut |fraction| progress(fraction * LOS_SHARE),
+            )
+        };
+        progress(LOS_SHARE);
+        self.fog_of_war.update(&self.los_to_pixels(&los), operation);
+        progress(1.0);
+        Ok(())
+    }
+
+    /// Hide or show everything that a person standing at the center of the pixel could see, e.g.
+    /// for a click on the image. The line of sight is calculated in pixel coordinates, where the
+    /// walls are converted to pixels once, so it is not rounded on the way to the fog of war. See
+    /// [`fow_show`][crate::vtt::VTT::fow_show()] for the other params. Returns an error if the
+    /// pixel lies outside of the map.
+    pub fn fow_change_px(
+        &mut self,
+        pov: PixelCoordinate,
+        operation: Operation,
+        around_walls: bool,
+    ) -> Result<(), RustVttError> {
+        let grid_pov = self.pixel_to_grid(pov);
+        self.check_in_bounds(&grid_pov)?;
+        let pov = Coord {
+            x: pov.x as f64 + 0.5,
+            y: pov.y as f64 + 0.5,
+        };
+        let segments: Vec<Line> = self
+            .segments_seen_from(grid_pov.into(), false)
+            .iter()
+            .map(|segment| Line::new(self.to_pixel(segment.start), self.to_pixel(segment.end)))
+            .collect();
+        let map = self.map_rect();
+        let map = Rect::new(self.to_pixel(map.min()), self.to_pixel(map.max()));
+        let los = if around_walls {
+            self.indirect_los(pov, &segments, map)
+        } else {
+            // the smallest scale casts at least as many rays as the line of sight in squares
+            let scale = self.resolution.scale();
+            let step_size = self.los_step_size * scale.x.min(scale.y);
+            calculate_direct_los(pov, &segments, map, step_size)
+        };
+        self.fog_of_war.update(&los, operation);
+        return Ok(());
+    }
+
+    /// Hide or show everything that any of the persons standing at the povs could see, in a single
+    /// update of the fog of war. See [`fow_show`][crate::vtt::VTT::fow_show()] for param
+    /// specifications. When `through_objects` is false, objects block line of sight. Returns an
+    /// error without changing the fog of war if any of the povs lies outside of the map.
+    pub fn fow_change_multi(
+        &mut self,
+        povs: &[Coordinate],
+        operation: Operation,
+        around_walls: bool,
+        through_objects: bool,
+    ) -> Result<(), RustVttError> {
+        let visible = self.visible_area(povs, around_walls, through_objects)?;
+        self.fog_of_war.update_multi(&visible, operation);
+        Ok(())
+    }
+
+    /// Helper function: returns the area that any of the persons standing at the povs can see in
+    /// pixel coordinates, see [`fow_change_multi`][crate::vtt::VTT::fow_change_multi()].
+    fn visible_area(
+        &self,
+        povs: &[Coordinate],
+        around_walls: bool,
+        through_objects: bool,
+    ) -> Result<MultiPolygon, RustVttError> {
+        let visible = povs
+            .iter()
+            .map(|pov| self.compute_los(pov.clone(), around_walls, through_objects))
+            .collect::<Result<Vec<Polygon>, RustVttError>>()?
+            .into_iter()
+            .fold(MultiPolygon::new(vec![]), |visible, los| {
+                visible.union(&MultiPolygon::new(vec![los]))
+            });
+        return Ok(visible.map_coords(|coord| self.to_pixel(coord)));
+    }
+
+    /// Set what the persons standing at the povs can see right now, like a line of sight mode.
+    /// What they see is revealed, and areas that were revealed before but are not seen right now
+    /// become remembered: [`apply_fow`][crate::vtt::VTT::apply_fow()] dims them instead of drawing
+    /// them black. See [`fow_change_multi`][crate::vtt::VTT::fow_change_multi()] for the params.
+    /// Returns an error without changing the fog of war if any of the povs lies outside of the map.
+    pub fn fow_view(
+        &mut self,
+        povs: &[Coordinate],
+        around_walls: bool,
+        through_objects: bool,
+    ) -> Result<(), RustVttError> {
+        let visible = self.visible_area(povs, around_walls, through_objects)?;
+        self.fog_of_war.remember_shown();
+        self.fog_of_war.update_multi(&visible, Operation::Show);
+        Ok(())
+    }
+
+    /// Stop remembering areas, so everything that is revealed is drawn clearly again until the
+    /// next call to [`fow_view`][crate::vtt::VTT::fow_view()].
+    pub fn clear_view(&mut self) -> &mut Self {
+        self.fog_of_war.show_remembered();
+        return self;
+    }
+
+    /// Returns whether the coordinate is revealed but not seen right now, see
+    /// [`fow_view`][crate::vtt::VTT::fow_view()].
+    pub fn is_remembered(&self, coordinate: Coordinate) -> bool {
+        return self
+            .fog_of_war
+            .is_remembered(self.grid_to_pixel(coordinate));
+    }
+
+    /// Returns the rectangles of the image that are revealed but not seen right now, see
+    /// [`fow_view`][crate::vtt::VTT::fow_view()].
+    pub fn remembered_rectangles(&self) -> Vec<FoWRectangle> {
+        return self.fog_of_war.get_remembered_rectangles();
+    }
+
+    /// Returns the opacity of the overlay that dims remembered areas
+    pub fn remembered_opacity(&self) -> u8 {
+        return self.remembered_opacity;
+    }
+
+    /// Set the opacity of the overlay that dims remembered areas, where 0 does not dim them and 255
+    /// draws them black. The default is 160.
+    pub fn set_remembered_opacity(&mut self, opacity: u8) -> &mut Self {
+        self.remembered_opacity = opacity;
+        return self;
+    }
+
+    /// Hide or show everything that a person standing at the pov could see when only looking in a
+    /// cone of `fov_deg` degrees around the `facing_deg` direction, e.g. a guard that can not see
+    /// behind them. Angles are in degrees from the positive x axis towards the positive y axis, so
+    /// clockwise on the image. A field of view of 360 degrees or more is the same as
+    /// [`fow_change`][crate::vtt::VTT::fow_change()]. See
+    /// [`fow_change_multi`][crate::vtt::VTT::fow_change_multi()] for the other params. Returns an
+    /// error if the pov lies outside of the map or the angles are invalid.
+    pub fn fow_change_cone(
+        &mut self,
+        pov: Coordinate,
+        facing_deg: f64,
+        fov_deg: f64,
+        operation: Operation,
+        around_walls: bool,
+        through_objects: bool,
+    ) -> Result<(), RustVttError> {
+        if !(fov_deg > 0.0 && facing_deg.is_finite()) {
+            return Err(RustVttError::InvalidFieldOfView {
+                facing_deg,
+                fov_deg,
+            });
+        }
+        let los = self.compute_los(pov.clone(), around_walls, through_objects)?;
+        if fov_deg >= 360.0 {
+            let polygon = los.map_coords(|coord| self.to_pixel(coord));
+            self.fog_of_war.update(&polygon, operation);
+            return Ok(());
+        }
+        // the arc lies outside of the map from any pov, so only the sides of the wedge cut the los.
+        // The angles are not wrapped, so a cone around 0 degrees is one wedge.
+        let map = self.map_rect();
+        let radius = 2.0 * map.width().hypot(map.height());
+        let center = Coord::from(pov);
+        let start = (facing_deg - fov_deg / 2.0).to_radians();
+        let steps = (fov_deg / CONE_ARC_STEP).ceil() as usize;
+        let mut wedge = vec![center];
+        wedge.extend((0..=steps).map(|step| {
+            let angle = start + (fov_deg * step as f64 / steps as f64).to_radians();
+            Coord {
+                x: center.x + radius * angle.cos(),
+                y: center.y + radius * angle.sin(),
+            }
+        }));
+        let wedge = Polygon::new(LineString::from(wedge), vec![]);
+        let visible = los
+            .intersection(&wedge)
+            .map_coords(|coord| self.to_pixel(coord));
+        self.fog_of_war.update_multi(&visible, operation);
+        return Ok(());
+    }
+
+    /// Hide or show everything that a person walking along the waypoints could see, in a single
+    /// update of the fog of war. The path is sampled every
+    /// [`los_resolution`][crate::vtt::VTT::los_resolution()] squares and the line of sight of
+    /// every sample is combined like [`fow_change_multi`][crate::vtt::VTT::fow_change_multi()].
+    /// Samples outside of the map or on a wall are skipped.
+    pub fn fow_change_path(
+        &mut self,
+        waypoints: &[Coordinate],
+        operation: Operation,
+        around_walls: bool,
+        through_objects: bool,
+    ) -> Result<(), RustVttError> {
+        let mut samples: Vec<Coord> = waypoints
+            .first()
+            .cloned()
+            .map(Coord::from)
+            .into_iter()
+            .collect();
+        for segment in waypoints.windows(2) {
+            let path = Line::new(
+                Coord::from(segment[0].clone()),
+                Coord::from(segment[1].clone()),
+            );
+            let steps = (path.dx().hypot(path.dy()) / self.los_step_size)
+                .ceil()
+                .max(1.0) as usize;
+            samples.extend(
+                (1..=steps).map(|step| path.start + path.delta() * (step as f64 / steps as f64)),
+            );
+        }
+        let walls = self.wall_segments(!around_walls && !through_objects);
+        let povs: Vec<Coordinate> = samples
+            .into_iter()
+            .filter(|&sample| {
+                let sample = Point::from(sample);
+                walls
+                    .iter()
+                    .all(|wall| Euclidean::distance(wall, &sample) > WALL_TOLERANCE)
+            })
+            .map(|sample| Coordinate {
+                x: sample.x,
+                y: sample.y,
+            })
+            .filter(|pov| self.check_in_bounds(pov).is_ok())
+            .collect();
+        return self.fow_change_multi(&povs, operation, around_walls, through_objects);
+    }
+
+    /// Helper function: returns an error if the pov does not lie on the map. The map runs from the
+    /// origin up to but not including the origin plus the size.
+    fn check_in_bounds(&self, pov: &Coordinate) -> Result<(), RustVttError> {
+        let map = self.map_rect();
+        let on_map = |value: f64, min: f64, max: f64| min <= value && value < max;
+        if !on_map(pov.x, map.min().x, map.max().x) || !on_map(pov.y, map.min().y, map.max().y) {
+            return Err(RustVttError::OutOfBounds {
+                coordinate: pov.clone(),
+            });
+        }
+        return Ok(());
+    }
+
+    /// Helper function: returns the area a person standing at the pov can see in grid
+    /// coordinates, see [`fow_change_multi`][crate::vtt::VTT::fow_change_multi()].
+    fn calculate_los(&self, pov: Coord, around_walls: bool, through_objects: bool) -> Polygon {
+        if around_walls {
+            return self.calculate_indirect_los(pov);
+        }
+        return self.calculate_direct_los(pov, through_objects);
+    }
+
+    /// Returns the area a person standing at the pov can see in grid coordinates, see
+    /// [`fow_show`][crate::vtt::VTT::fow_show()] for param specifications. When `through_objects`
+    /// is false, objects block line of sight; objects never block vision around walls. Returns an
+    /// error if the pov lies outside of the map.
+    pub fn compute_los(
+        &self,
+        pov: Coordinate,
+        around_walls: bool,
+        through_objects: bool,
+    ) -> Result<Polygon, RustVttError> {
+        self.check_in_bounds(&pov)?;
+        return Ok(self.calculate_los(pov.into(), around_walls, through_objects));
+    }
+
+    /// Returns the area a person standing at the pov can see in grid coordinates, where the config
+    /// decides what blocks vision, see [`fow_change_with_config`][VTT::fow_change_with_config()].
+    /// Unlike [`compute_los`][VTT::compute_los()], objects that block vision also block vision
+    /// around corners. Returns an error if the pov lies outside of the map.
+    pub fn compute_los_with_config(
+        &self,
+        pov: Coordinate,
+        config: &VisionConfig,
+    ) -> Result<Polygon, RustVttError> {
+        self.check_in_bounds(&pov)?;
+        let pov = pov.into();
+        let segments = self.vision_segments(pov, config);
+        if config.around_corners {
+            return Ok(self.indirect_los(pov, &segments, self.map_rect()));
+        }
+        return Ok(calculate_direct_los(
+            pov,
+            &segments,
+            self.map_rect(),
+            self.los_step_size,
+        ));
+    }
+
+    /// Returns the area a person standing at the pov can see in grid coordinates both when objects
+    /// block line of sight and when they do not, e.g. for separate player and GM fog of war layers.
+    /// The wall segments of both cases are cached, so neither is rebuilt. See
+    /// [`compute_los`][crate::vtt::VTT::compute_los()] for the other params and errors.
+    pub fn compute_los_pair(
+        &self,
+        pov: Coordinate,
+        around_walls: bool,
+    ) -> Result<(Polygon, Polygon), RustVttError> {
+        self.check_in_bounds(&pov)?;
+        let pov = pov.into();
+        if around_walls {
+            let los = self.calculate_indirect_los(pov);
+            return Ok((los.clone(), los));
+        }
+        return Ok((
+            self.calculate_direct_los(pov, false),
+            self.calculate_direct_los(pov, true),
+        ));
+    }
+
+    /// Converts a polygon in grid coordinates, such as a line of sight from
+    /// [`compute_los`][crate::vtt::VTT::compute_los()], to pixel coordinates on the image so it
+    /// can be applied to any fog of war of this map with [`FogOfWar::update`].
+    pub fn los_to_pixels(&self, los: &Polygon) -> Polygon {
+        return los.map_coords(|coord| self.to_pixel(coord));
+    }
+
+    /// Returns the area a person standing at the pov can see within `max_range` squares in grid
+    /// coordinates, e.g. for darkvision. The line of sight is intersected with a circle of that
+    /// radius around the pov, approximated by a polygon with 64 segments that encloses the
+    /// circle. None, or a range that reaches every corner of the map, gives the same area as
+    /// [`compute_los`][crate::vtt::VTT::compute_los()]. Returns an error if the pov lies outside of
+    /// the map or the range is not a positive number.
+    pub fn compute_los_in_range(
+        &self,
+        pov: Coordinate,
+        around_walls: bool,
+        through_objects: bool,
+        max_range: Option<f64>,
+    ) -> Result<MultiPolygon, RustVttError> {
+        if let Some(max_range) = max_range {
+            if max_range.is_nan() || max_range <= 0.0 {
+                return Err(RustVttError::InvalidSightRange { max_range });
+            }
+        }
+        let los = self.compute_los(pov.clone(), around_walls, through_objects)?;
+        let center = Coord::from(pov);
+        let map = self.map_rect();
+        // the distance to the farthest corner of the map, the pov lies on the map
+        let farthest = (center.x - map.min().x)
+            .max(map.max().x - center.x)
+            .hypot((center.y - map.min().y).max(map.max().y - center.y));
+        match max_range {
+            Some(max_range) if max_range < farthest => {
+                // the corners are placed further out so the edges of the polygon touch the circle
+                let radius = max_range / (f64::consts::PI / CIRCLE_SEGMENTS as f64).cos();
+                let circle = circle_polygon(
+                    center,
+                    Coord {
+                        x: radius,
+                        y: radius,
+                    },
+                    CIRCLE_SEGMENTS,
+                );
+                return Ok(los.intersection(&circle));
+            }
+            _ => return Ok(MultiPolygon::new(vec![los])),
+        }
+    }
+
+    /// Hide or show everything that a person standing at the pov could see within `max_range`
+    /// squares, see [`compute_los_in_range`][crate::vtt::VTT::compute_los_in_range()]. Objects do
+    /// not block line of sight, like [`fow_change`][crate::vtt::VTT::fow_change()].
+    pub fn fow_change_in_range(
+        &mut self,
+        pov: Coordinate,
+        operation: Operation,
+        around_walls: bool,
+        max_range: Option<f64>,
+    ) -> Result<(), RustVttError> {
+        let visible = self
+            .compute_los_in_range(pov, around_walls, true, max_range)?
+            .map_coords(|coord| self.to_pixel(coord));
+        self.fog_of_war.update_multi(&visible, operation);
+        Ok(())
+    }
+
+    /// Returns the distance in squares between the points on the map border that line of sight
+    /// rays are cast to, see [`set_los_resolution`][crate::vtt::VTT::set_los_resolution()].
+    pub fn los_resolution(&self) -> f64 {
+        return self.los_step_size;
+    }
+
+    /// Set the distance in squares between the points on the map border that line of sight rays
+    /// are cast to when revealing fog of war without looking around walls. Lower values give
+    /// smoother edges, higher values are faster. The default is 0.2. Returns an error if the value
+    /// is not a positive number.
+    pub fn set_los_resolution(&mut self, step_size: f64) -> Result<&mut Self, RustVttError> {
+        if !(step_size > 0.0 && step_size.is_finite()) {
+            return Err(RustVttError::InvalidLosResolution { step_size });
+        }
+        self.los_step_size = step_size;
+        return Ok(self);
+    }
+
+    /// Returns an svg image of the line of sight from the pov in pixel coordinates, e.g. to attach
+    /// to a bug report about the fog of war. It shows the map bounds, the walls in black, the
+    /// objects in brown, the doors in red when they block vision and in green otherwise, the line
+    /// of sight as a semi-transparent yellow area and the pov as a blue dot. See
+    /// [`compute_los`][crate::vtt::VTT::compute_los()] for the params and errors.
+    pub fn debug_svg(
+        &self,
+        pov: Coordinate,
+        around_walls: bool,
+        through_objects: bool,
+    ) -> Result<String, RustVttError> {
+        let los =
+            self.los_to_pixels(&self.compute_los(pov.clone(), around_walls, through_objects)?);
+        let size = self.pixel_size();
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n",
+            size.x, size.y
+        );
+        svg += &format!(
+            "<rect width=\"{}\" height=\"{}\" fill=\"white\" stroke=\"gray\"/>\n",
+            size.x, size.y
+        );
+        let mut path = String::new();
+        for ring in std::iter::once(los.exterior()).chain(los.interiors()) {
+            for (index, coord) in ring.coords().enumerate() {
+                let command = if index == 0 { 'M' } else { 'L' };
+                path += &format!("{}{:.2},{:.2} ", command, coord.x, coord.y);
+            }
+            path += "Z ";
+        }
+        svg += &format!(
+            "<path d=\"{}\" fill=\"yellow\" fill-opacity=\"0.4\" fill-rule=\"evenodd\" stroke=\"orange\"/>\n",
+            path.trim_end()
+        );
+        let mut push_line = |line: Line, color: &str| {
+            let start = self.to_pixel(line.start);
+            let end = self.to_pixel(line.end);
+            svg += &format!(
+                "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" stroke-width=\"2\"/>\n",
+                start.x, start.y, end.x, end.y, color
+            );
+        };
+        for wall in get_line_segments(self.line_of_sight.clone()) {
+            push_line(wall, "black");
+        }
+        for object in get_line_segments(self.objects_line_of_sight.clone()) {
+            push_line(object, "saddlebrown");
+        }
+        for portal in &self.portals {
+            if let Some(line) = portal.line() {
+                push_line(
+                    line,
+                    if portal.blocks_vision() {
+                        "red"
+                    } else {
+                        "green"
+                    },
+                );
+            }
+        }
+        let pov = self.to_pixel(pov.into());
+        let radius = (self.pixels_per_grid() as f64 / 8.0).max(2.0);
+        svg += &format!(
+            "<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" fill=\"blue\"/>\n</svg>\n",
+            pov.x, pov.y, radius
+        );
+        return Ok(svg);
+    }
+
+    /// Returns every line of sight ray that is cast when revealing fog of war from the pov without
+    /// looking around walls, in grid coordinates. Every ray runs from the pov to the first wall,
+    /// closed door or (if `objects` is true) object it hits. This is meant for debugging, e.g. by
+    /// drawing the rays to find a wall that unexpectedly blocks vision.
+    pub fn debug_los_rays(&self, pov: Coordinate, objects: bool) -> Vec<Line> {
+        let mut rays = Vec::new();
+        let pov = pov.into();
+        for_each_interesection(
+            pov,
+            &self.segments_seen_from(pov, objects),
+            self.map_rect(),
+            self.los_step_size,
+            |ray| rays.push(ray),
+        );
+        return rays;
+    }
+
+    /// Helper function: returns the area a person standing at the pov can see in a straight line
+    /// in grid coordinates. Walls and closed doors block vision, as do objects unless
+    /// `through_objects` is true.
+    fn calculate_direct_los(&self, pov: Coord, through_objects: bool) -> Polygon {
+        let segments = self.segments_seen_from(pov, !through_objects);
+        return calculate_direct_los(pov, &segments, self.map_rect(), self.los_step_size);
+    }
+
+    /// Helper function: returns the [`wall_segments`][VTT::wall_segments()] together with the one
+    /// way doors that block vision for a person standing at the pov
+    fn segments_seen_from(&self, pov: Coord, objects: bool) -> Cow<'_, [Line]> {
+        let segments = self.wall_segments(objects);
+        let mut one_way = self
+            .portals
+            .iter()
+            .filter(|portal| portal.one_way.is_some() && portal.blocks_vision_from(pov))
+            .filter_map(Portal::line)
+            .peekable();
+        if one_way.peek().is_none() {
+            return Cow::Borrowed(segments);
+        }
+        return Cow::Owned(segments.iter().cloned().chain(one_way).collect());
+    }
+
+    /// Helper function: returns the segments that block vision for a person standing at the pov
+    /// with the given config. Only the combinations that include walls and doors are cached.
+    fn vision_segments(&self, pov: Coord, config: &VisionConfig) -> Cow<'_, [Line]> {
+        if config.blocked_by_walls && config.blocked_by_closed_doors {
+            return self.segments_seen_from(pov, config.blocked_by_objects);
+        }
+        let mut segments = Vec::new();
+        if config.blocked_by_walls {
+            segments.extend_from_slice(self.line_segments(false));
+        }
+        if config.blocked_by_objects {
+            segments.extend_from_slice(self.line_segments(true));
+        }
+        if config.blocked_by_closed_doors {
+            segments.extend(
+                self.portals
+                    .iter()
+                    .filter(|portal| portal.blocks_vision_from(pov))
+                    .filter_map(Portal::line),
+            );
+        }
+        dedup_segments(&mut segments);
+        return Cow::Owned(segments);
+    }
+
+    /// Helper function: returns the segments of every wall and of every door that blocks vision,
+    /// and of every object when `objects` is true, in grid coordinates. One way doors are left
+    /// out, see [`segments_seen_from`][VTT::segments_seen_from()]. The segments are cached until
+    /// a wall changes or a door is opened or closed.
+    fn wall_segments(&self, objects: bool) -> &[Line] {
+        return self.wall_segments[objects as usize].get_or_init(|| {
+            let mut segments = self.line_segments(false).to_vec();
+            if objects {
+                segments.extend_from_slice(self.line_segments(true));
+            }
+            segments.extend(
+                self.portals
+                    .iter()
+                    .filter(|portal| portal.blocks_vision() && portal.one_way.is_none())
+                    .filter_map(Portal::line),
+            );
+            dedup_segments(&mut segments);
+            segments
+        });
+    }
+
+    /// Helper function: returns the segments of every wall, or of every object when `objects` is
+    /// true, in grid coordinates. The segments are cached until a wall changes.
+    fn line_segments(&self, objects: bool) -> &[Line] {
+        return self.line_segments[objects as usize].get_or_init(|| {
+            if objects {
+                return get_line_segments(self.objects_line_of_sight.clone());
+            }
+            return get_line_segments(self.line_of_sight.clone());
+        });
+    }
+
+    /// Returns the segments of every wall and closed door that lies at least partly within the
+    /// square of `radius` squares around the center, in grid coordinates. Walls outside of it can
+    /// not block anything within the radius, e.g. of a light.
+    pub fn walls_near(&self, center: Coordinate, radius: f64) -> Vec<Line> {
+        let bounds = square_around(center.into(), radius);
+        return self
+            .wall_segments(false)
+            .iter()
+            .filter(|wall| bounds.intersects(*wall))
+            .cloned()
+            .collect();
+    }
+
+    /// Returns whether the point lies on a wall or on a door that blocks vision, or on an object
+    /// when `include_objects` is true. The line of sight from such a point is not well defined, so
+    /// a token placed there can be moved with [`nudge_off_wall`][VTT::nudge_off_wall()].
+    pub fn is_on_wall(&self, point: Coordinate, include_objects: bool) -> bool {
+        let point = Point::from(Coord::from(point));
+        return self
+            .wall_segments(include_objects)
+            .iter()
+            .any(|wall| Euclidean::distance(wall, &point) <= WALL_TOLERANCE);
+    }
+
+    /// Returns the point moved a tiny distance off the wall closest to it, so that it no longer
+    /// lies on any wall, door or object and stays on the map. A point that does not lie on a wall
+    /// is returned unchanged, as is a point that can not be moved off.
+    pub fn nudge_off_wall(&self, point: Coordinate) -> Coordinate {
+        let walls = self.wall_segments(true);
+        let origin = Coord::from(point.clone());
+        let distance = |wall: &&Line| Euclidean::distance(*wall, &Point::from(origin));
+        let Some(wall) = walls
+            .iter()
+            .filter(|wall| distance(wall) <= WALL_TOLERANCE)
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        else {
+            return point;
+        };
+        let length = wall.dx().hypot(wall.dy());
+        let normal = if length > 0.0 {
+            Coord {
+                x: -wall.dy() / length,
+                y: wall.dx() / length,
+            }
+        } else {
+            Coord { x: 1.0, y: 0.0 }
+        };
+        // try both sides of the wall first, then every direction in case of a corner
+        let directions = [normal, -normal].into_iter().chain((0..8).map(|step| {
+            let angle = step as f64 * f64::consts::FRAC_PI_4;
+            Coord {
+                x: angle.cos(),
+                y: angle.sin(),
+            }
+        }));
+        for direction in directions {
+            let candidate = Coordinate::from(origin + direction * WALL_NUDGE);
+            if self.check_in_bounds(&candidate).is_ok() && !self.is_on_wall(candidate.clone(), true)
+            {
+                return candidate;
+            }
+        }
+        return point;
+    }
+
+    /// Helper function: clears the cached wall segments, this must be called whenever an object or
+    /// door changes. See [`invalidate_walls`][VTT::invalidate_walls()] for walls.
+    fn invalidate_wall_segments(&mut self) {
+        self.wall_segments = Default::default();
+    }
+
+    /// Helper function: clears the cached rooms and wall segments, this must be called whenever a
+    /// wall changes.
+    fn invalidate_walls(&mut self) {
+        self.rooms = OnceLock::new();
+        self.line_segments = Default::default();
+        self.invalidate_wall_segments();
+    }
+
+    /// Helper function: returns the area of the map in grid coordinates
+    fn map_rect(&self) -> Rect {
+        return Rect::new(
+            Coord::from(self.origin().clone()),
+            Coord {
+                x: self.origin().x + self.size().x,
+                y: self.origin().y + self.size().y,
+            },
+        );
+    }
+
+    /// Helper function: returns the area a person standing at the pov can see when looking around
+    /// walls perfectly in grid coordinates. Open doors do not block vision. If the pov is not in an
+    /// enclosed room, this is the part of the map outside of all rooms that the pov lies in.
+    fn calculate_indirect_los(&self, pov: Coord) -> Polygon {
+        return self.indirect_los(pov, &self.segments_seen_from(pov, false), self.map_rect());
+    }
+
+    /// Helper function: returns the area a person standing at the pov can see when looking around
+    /// the given segments perfectly within the map, see
+    /// [`calculate_indirect_los`][VTT::calculate_indirect_los()]
+    fn indirect_los(&self, pov: Coord, segments: &[Line], map: Rect) -> Polygon {
+        if let Some(room) = calculate_indirect_los(pov, segments) {
+            return room;
+        }
+        let map = map.to_polygon();
+        let rooms = MultiPolygon::new(get_planar_faces(segments));
+        let outside = MultiPolygon::new(vec![map.clone()]).difference(&rooms);
+        // the pov may lie on a wall, so take the closest part instead of the one containing it
+        let pov = Point::from(pov);
+        return outside
+            .into_iter()
+            .min_by(|a, b| Euclidean::distance(a, &pov).total_cmp(&Euclidean::distance(b, &pov)))
+            .unwrap_or(map)
+            .orient(Direction::Default);
+    }
+
+    /// Draw the fog of war onto an image of this vtt, every hidden area is drawn black and every
+    /// remembered area is dimmed, see [`fow_view`][crate::vtt::VTT::fow_view()].
+    #[cfg(feature = "image")]
+    pub fn apply_fow(&self, image: &mut Pixbuf) {
+        self.apply_fow_with_opacity(image, u8::MAX);
+    }
+
+    /// Draw the fog of war onto an image of this vtt, where every hidden area is blended with black
+    /// using the given opacity. An opacity of 255 is the same as [`apply_fow`][crate::vtt::VTT::apply_fow()],
+    /// lower values dim the hidden areas, e.g. to show the entire map to the game master while
+    /// still showing what the players can see.
+    #[cfg(feature = "image")]
+    pub fn apply_fow_with_opacity(&self, image: &mut Pixbuf, opacity: u8) {
+        darken_rectangles(
+            image,
+            &self.remembered_rectangles(),
+            self.remembered_opacity,
+        );
+        let hidden = self.fog_of_war.get_rectangles();
+        if self.fow_edge_blur == 0 {
+            darken_rectangles(image, &hidden, opacity);
+            return;
+        }
+        let edges = blur_fow_edges(&hidden, image.width(), image.height(), self.fow_edge_blur);
+        // the tiles at the edges are blended with their mask instead of the hard rectangles
+        let originals: Vec<Pixbuf> = edges
+            .iter()
+            .map(|(x, y, mask)| image.crop(*x, *y, mask.width(), mask.height()))
+            .collect();
+        darken_rectangles(image, &hidden, opacity);
+        for ((x, y, mask), mut tile) in edges.iter().zip(originals) {
+            tile.blend_mask(mask.as_raw(), Rgb([0, 0, 0]), opacity);
+            image.paste(*x, *y, &tile);
+        }
+    }
+
+    /// Returns the radius in pixels over which the edges of the fog of war are blurred, see
+    /// [`set_fow_edge_blur`][crate::vtt::VTT::set_fow_edge_blur()]
+    pub fn fow_edge_blur(&self) -> u32 {
+        return self.fow_edge_blur;
+    }
+
+    /// Blur the edges of the fog of war over the given radius in pixels when it is drawn by
+    /// [`apply_fow`][crate::vtt::VTT::apply_fow()], so the edge of a revealed area does not look
+    /// blocky. Areas further than the radius from an edge are drawn as before, and remembered areas
+    /// keep hard edges. The default of 0 draws hard edges.
+    pub fn set_fow_edge_blur(&mut self, radius: u32) -> &mut Self {
+        self.fow_edge_blur = radius;
+        return self;
+    }
+
+    /// Draw the fog of war layer with the given name onto an image of this vtt instead of the fog
+    /// of war of the map, where every hidden area is drawn black. Returns an
+    /// [`UnknownFowLayer`][RustVttError::UnknownFowLayer] error if there is no such layer.
+    #[cfg(feature = "image")]
+    pub fn apply_fow_layer(&self, image: &mut Pixbuf, name: &str) -> Result<(), RustVttError> {
+        let layer = self
+            .fow_layer(name)
+            .ok_or_else(|| RustVttError::UnknownFowLayer {
+                name: name.to_string(),
+            })?;
+        darken_rectangles(image, &layer.get_rectangles(), u8::MAX);
+        Ok(())
+    }
+
+    /// Returns a small preview of the image of this vtt, scaled so the longest side is at most
+    /// `max_dimension` pixels. Lighting is not applied. When `fog_of_war` is true, the fog of war
+    /// is scaled along and drawn over the preview. The full image is not kept in memory, unless it
+    /// was already decoded before.
+    #[cfg(feature = "image")]
+    pub fn thumbnail(&self, max_dimension: u32, fog_of_war: bool) -> Result<Pixbuf> {
+        let decoded;
+        let image = match self.decoded_image.get() {
+            Some(image) => image,
+            None => {
+                decoded = self.decode_image_bytes()?;
+                &decoded
+            }
+        };
+        let longest = image.width().max(image.height()).max(1);
+        let scale = (max_dimension.max(1) as f64 / longest as f64).min(1.0);
+        let width = ((image.width() as f64 * scale).round() as u32).max(1);
+        let height = ((image.height() as f64 * scale).round() as u32).max(1);
+        let mut thumbnail = image.resize(width, height);
+        if fog_of_war {
+            let scale_x = width as f64 / image.width().max(1) as f64;
+            let scale_y = height as f64 / image.height().max(1) as f64;
+            for rectangle in self.fog_of_war.get_rectangles() {
+                let left = (rectangle.topleft.x as f64 * scale_x).round() as i32;
+                let top = (rectangle.topleft.y as f64 * scale_y).round() as i32;
+                let right = (rectangle.bottomright.x as f64 * scale_x).round() as i32;
+                let bottom = (rectangle.bottomright.y as f64 * scale_y).round() as i32;
+                thumbnail.fill_rect(
+                    left,
+                    top,
+                    (right - left).max(0) as u32,
+                    (bottom - top).max(0) as u32,
+                    Rgb([0, 0, 0]),
+                );
+            }
+        }
+        return Ok(thumbnail);
+    }
+
+    /// Returns the image of this vtt with all vtt data (fog of war, lighting, etc.) applied to it.
+    /// This function will **not** overwrite the existing image stored in the vtt.
+    #[cfg(feature = "image")]
+    pub fn get_pixbuf(&self) -> Result<Pixbuf> {
+        let mut image = self.get_pixbuf_without_fow()?;
+        self.apply_fow(&mut image);
+        return Ok(image);
+    }
+
+    /// Returns the image of this vtt with lighting applied but without fog of war, e.g. to show
+    /// the entire map to the game master.
+    #[cfg(feature = "image")]
+    pub fn get_pixbuf_without_fow(&self) -> Result<Pixbuf> {
+        let mut image = self.decode_image()?.clone();
+        self.apply_light(&mut image);
+        return Ok(image);
+    }
+
+    /// Save the base64 encoded image of this vtt as it is stored, without drawing on it. The
+    /// extension of the format of the image, e.g. `.png` or `.jpg`, is appended to the path, see
+    /// [`image_format`][crate::vtt::VTT::image_format()]. Use
+    /// [`save_img`][crate::vtt::VTT::save_img()] to always save a PNG image.
+    /// ## `path`
+    /// The path to the file that the image will be exported to **excluding** the extension.
+    /// # Example
+    /// `save_image("path/to/filename")`
+    pub fn save_img_raw<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        // you can do path.as_ref() to get the path object
+        let decoded = self.image_bytes()?;
+        let mut path = path.as_ref().as_os_str().to_os_string();
+        if let Some(format) = guess_format(&decoded) {
+            path.push(".");
+            path.push(format.extension());
+        }
+        let mut file = File::options()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&path)?;
+        file.write_all(&decoded)?;
+        Ok(())
+    }
+
+    /// Save this vtt to a file in the uvtt format. This will not save fog of war state.
+    pub fn save_vtt<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        return VTT::write_uvtt(path, &self.to_uvtt());
+    }
+
+    /// Helper function: returns the data of this vtt that is written to a uvtt file
+    fn to_uvtt(&self) -> UvttFile<'_> {
+        return UvttFile {
+            format: self.format,
+            // the pixel dimensions are not part of the uvtt format
+            resolution: match self.resolution.pixel_dimensions {
+                Some(_) => Cow::Owned(Resolution {
+                    pixel_dimensions: None,
+                    ..self.resolution.clone()
+                }),
+                None => Cow::Borrowed(&self.resolution),
+            },
+            line_of_sight: &self.line_of_sight,
+            objects_line_of_sight: &self.objects_line_of_sight,
+            portals: &self.portals,
+            environment: Cow::Borrowed(&self.environment),
+            lights: &self.lights,
+            image: &self.image,
+        };
+    }
+
+    /// Helper function: writes the uvtt data straight to the file, without building the json in
+    /// memory first
+    fn write_uvtt<P: AsRef<Path>>(path: P, uvtt: &UvttFile) -> Result<()> {
+        let file = File::options()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&path)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, uvtt)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Returns a new vtt of the part of this map between the given corners in grid coordinates,
+    /// e.g. to split a large map into smaller maps. The corners are rounded outwards to whole
+    /// squares and limited to the map. Walls, objects and doors are clipped to the edge of the new
+    /// map and lights outside of it are left out. The coordinates of the walls and lights are
+    /// kept, the new map starts at the top left corner instead. The fog of war of the new map is
+    /// revealed. Returns an error if the corners do not enclose any square of the map.
+    #[cfg(feature = "image")]
+    pub fn crop(&self, topleft: Coordinate, bottomright: Coordinate) -> Result<VTT> {
+        let map = self.map_rect();
+        let origin = map.min();
+        // round outwards to whole squares of this map
+        let min = Coord {
+            x: (origin.x + (topleft.x.min(bottomright.x) - origin.x).floor()).max(origin.x),
+            y: (origin.y + (topleft.y.min(bottomright.y) - origin.y).floor()).max(origin.y),
+        };
+        let max = Coord {
+            x: (origin.x + (topleft.x.max(bottomright.x) - origin.x).ceil()).min(map.max().x),
+            y: (origin.y + (topleft.y.max(bottomright.y) - origin.y).ceil()).min(map.max().y),
+        };
+        let mut resolution =
+            Resolution::new(min.into(), (max - min).into(), self.pixels_per_grid());
+        resolution.validate()?;
+        let bounds = Rect::new(min, max);
+
+        let (pixel_min, pixel_max) = (self.to_pixel(min), self.to_pixel(max));
+        let image = self.decode_image()?.crop(
+            pixel_min.x.round() as u32,
+            pixel_min.y.round() as u32,
+            (pixel_max.x.round() - pixel_min.x.round()) as u32,
+            (pixel_max.y.round() - pixel_min.y.round()) as u32,
+        );
+        let pixel_size = resolution.pixel_size();
+        if image.width() as i32 != pixel_size.x || image.height() as i32 != pixel_size.y {
+            resolution.pixel_dimensions = Some(PixelCoordinate {
+                x: image.width() as i32,
+                y: image.height() as i32,
+            });
+        }
+
+        let clip_walls = |walls: &[Vec<Coordinate>]| -> Vec<Vec<Coordinate>> {
+            return walls
+                .iter()
+                .flat_map(|wall| clip_polyline(wall, bounds))
+                .collect();
+        };
+        let portals = self
+            .portals
+            .iter()
+            .filter_map(|portal| {
+                let line = clip_line(portal.line()?, bounds)?;
+                if line.start == line.end {
+                    return None;
+                }
+                let mut clipped = Portal::new(
+                    line.start.into(),
+                    line.end.into(),
+                    portal.closed,
+                    portal.freestanding,
+                );
+                clipped.rotation = portal.rotation;
+                clipped.always_blocks_vision = portal.always_blocks_vision;
+                clipped.one_way = portal.one_way;
+                return Some(clipped);
+            })
+            .collect();
+        let lights = self
+            .lights
+            .iter()
+            .filter(|light| {
+                let position = &light.position;
+                position.x >= min.x
+                    && position.x < max.x
+                    && position.y >= min.y
+                    && position.y < max.y
+            })
+            .cloned()
+            .collect();
+        let mut vtt = VTTPartial {
+            format: self.format,
+            resolution,
+            line_of_sight: clip_walls(&self.line_of_sight),
+            objects_line_of_sight: clip_walls(&self.objects_line_of_sight),
+            portals,
+            environment: self.environment.clone(),
+            lights,
+            image: BASE64_STANDARD.encode(encode_png(&image)),
+            fog_of_war: None,
+        }
+        .try_to_vtt()?;
+        vtt.los_step_size = self.los_step_size;
+        return Ok(vtt);
+    }
+
+    /// Append another map to the right of this map, e.g. to stitch together a map that was
+    /// exported as several tiles. The images are joined and the walls, objects, doors and lights of
+    /// the other map are moved along with its image. The fog of war of both maps is kept with its
+    /// remembered areas, and so are the fog of war layers, where the part of a layer that only one
+    /// of the maps has is hidden. Returns an error if the pixels per grid differ or the maps are
+    /// not equally high, in squares or in pixels.
+    #[cfg(feature = "image")]
+    pub fn append_right(&mut self, other: &VTT) -> Result<()> {
+        return self.append(other, false);
+    }
+
+    /// Append another map below this map like [`append_right`][crate::vtt::VTT::append_right()].
+    /// Returns an error if the pixels per grid differ or the maps are not equally wide, in squares
+    /// or in pixels.
+    #[cfg(feature = "image")]
+    pub fn append_below(&mut self, other: &VTT) -> Result<()> {
+        return self.append(other, true);
+    }
+
+    /// Helper function: appends the other map to the right of or below this map
+    #[cfg(feature = "image")]
+    fn append(&mut self, other: &VTT, below: bool) -> Result<()> {
+        if self.pixels_per_grid() != other.pixels_per_grid() {
+            return Err(RustVttError::PixelsPerGridMismatch {
+                expected: self.pixels_per_grid(),
+                found: other.pixels_per_grid(),
+            }
+            .into());
+        }
+        let (edge, other_edge) = if below {
+            (self.size().x, other.size().x)
+        } else {
+            (self.size().y, other.size().y)
+        };
+        if edge != other_edge {
+            return Err(RustVttError::EdgeMismatch {
+                expected: edge,
+                found: other_edge,
+            }
+            .into());
+        }
+        let image = self.decode_image()?;
+        let other_image = other.decode_image()?;
+        let mismatch = if below {
+            image.width() != other_image.width()
+        } else {
+            image.height() != other_image.height()
+        };
+        if mismatch {
+            return Err(RustVttError::ImageSizeMismatch {
+                expected: self.pixel_size(),
+                found: other.pixel_size(),
+            }
+            .into());
+        }
+        // the other map is placed at the far edge of this map
+        let (offset, pixel_offset, mut joined) = if below {
+            (
+                Coord {
+                    x: self.origin().x,
+                    y: self.origin().y + self.size().y,
+                },
+                PixelCoordinate::new(0, image.height() as i32),
+                Pixbuf::new(image.width(), image.height() + other_image.height()),
+            )
+        } else {
+            (
+                Coord {
+                    x: self.origin().x + self.size().x,
+                    y: self.origin().y,
+                },
+                PixelCoordinate::new(image.width() as i32, 0),
+                Pixbuf::new(image.width() + other_image.width(), image.height()),
+            )
+        };
+        let offset = offset - Coord::from(other.origin().clone());
+        joined.paste(0, 0, image);
+        joined.paste(pixel_offset.x as u32, pixel_offset.y as u32, other_image);
+        let own_part = FoWRectangle {
+            topleft: PixelCoordinate::new(0, 0),
+            bottomright: PixelCoordinate::new(image.width() as i32, image.height() as i32),
+        };
+        let other_part = FoWRectangle {
+            topleft: pixel_offset,
+            bottomright: PixelCoordinate::new(
+                pixel_offset.x + other_image.width() as i32,
+                pixel_offset.y + other_image.height() as i32,
+            ),
+        };
+
+        let shift =
+            |coordinate: &Coordinate| Coordinate::from(Coord::from(coordinate.clone()) + offset);
+        let shift_walls = |walls: &[Vec<Coordinate>]| -> Vec<Vec<Coordinate>> {
+            return walls
+                .iter()
+                .map(|wall| wall.iter().map(shift).collect())
+                .collect();
+        };
+        self.line_of_sight.extend(shift_walls(&other.line_of_sight));
+        self.objects_line_of_sight
+            .extend(shift_walls(&other.objects_line_of_sight));
+        self.portals
+            .extend(other.portals.iter().map(|portal| Portal {
+                position: shift(&portal.position),
+                bounds: portal.bounds.iter().map(shift).collect(),
+                rotation: portal.rotation,
+                closed: portal.closed,
+                freestanding: portal.freestanding,
+                always_blocks_vision: portal.always_blocks_vision,
+                one_way: portal.one_way,
+            }));
+        self.lights.extend(other.lights.iter().map(|light| Light {
+            position: shift(&light.position),
+            ..light.clone()
+        }));
+
+        if below {
+            self.resolution.map_size.y += other.size().y;
+        } else {
+            self.resolution.map_size.x += other.size().x;
+        }
+        self.resolution.pixel_dimensions = None;
+        let pixel_size = self.pixel_size();
+        if joined.width() as i32 != pixel_size.x || joined.height() as i32 != pixel_size.y {
+            self.resolution.pixel_dimensions = Some(PixelCoordinate::new(
+                joined.width() as i32,
+                joined.height() as i32,
+            ));
+        }
+        self.set_image_from_pixbuf(&joined)?;
+        self.invalidate_walls();
+
+        // the fog of war and every layer keep their hidden, shown and remembered areas, where the
+        // part of a layer that only one of the maps has is hidden like a new layer
+        let join = |fog_of_war: Option<&FogOfWar>, other_fog_of_war: Option<&FogOfWar>| {
+            let mut joined = FogOfWar::new(&self.resolution);
+            if let Some(source) = fog_of_war.or(other_fog_of_war) {
+                joined
+                    .set_min_square_size(source.min_square_size())
+                    .expect("the minimum square size of an existing fog of war is valid");
+            }
+            match fog_of_war {
+                Some(fog_of_war) => joined.paste(fog_of_war, PixelCoordinate::new(0, 0)),
+                None => joined.update_rect(own_part, Operation::Hide),
+            }
+            match other_fog_of_war {
+                Some(other_fog_of_war) => joined.paste(other_fog_of_war, pixel_offset),
+                None => joined.update_rect(other_part, Operation::Hide),
+            }
+            return joined;
+        };
+        let fog_of_war = join(Some(&self.fog_of_war), Some(&other.fog_of_war));
+        let layers: HashMap<String, FogOfWar> = self
+            .fow_layers
+            .keys()
+            .chain(other.fow_layers.keys())
+            .map(|name| {
+                let layer = join(self.fow_layers.get(name), other.fow_layers.get(name));
+                return (name.clone(), layer);
+            })
+            .collect();
+        self.fog_of_war = fog_of_war;
+        self.fow_layers = layers;
+        Ok(())
+    }
+
+    /// Rotate the map clockwise by the given number of quarter turns, e.g. for a map that was
+    /// exported on its side. The image is rotated and the walls, objects, doors and lights are
+    /// moved along with it, where the map keeps its origin and its width and height swap for an
+    /// odd number of turns. The fog of war and its layers are rotated as well. Returns an error if
+    /// the image can not be decoded.
+    #[cfg(feature = "image")]
+    pub fn rotate(&mut self, quarter_turns: u8) -> Result<()> {
+        let turns = quarter_turns % 4;
+        if turns == 0 {
+            return Ok(());
+        }
+        let mut image = self.take_decoded_image()?;
+        for _ in 0..turns {
+            image = image.rotate_clockwise();
+            self.fog_of_war = self.fog_of_war.rotate_clockwise();
+            for layer in self.fow_layers.values_mut() {
+                *layer = layer.rotate_clockwise();
+            }
+            self.rotate_coordinates();
+        }
+        self.set_image_from_pixbuf(&image)?;
+        self.invalidate_walls();
+        Ok(())
+    }
+
+    /// Helper function: turns every coordinate of the map a quarter turn clockwise around the
+    /// map, keeping the origin, and swaps the width and height of the map
+    #[cfg(feature = "image")]
+    fn rotate_coordinates(&mut self) {
+        let origin = Coord::from(self.origin().clone());
+        let height = self.size().y;
+        let turn = |coordinate: &mut Coordinate| {
+            let offset = Coord::from(coordinate.clone()) - origin;
+            *coordinate = Coordinate::new(origin.x + height - offset.y, origin.y + offset.x);
+        };
+        for point in self
+            .line_of_sight
+            .iter_mut()
+            .chain(self.objects_line_of_sight.iter_mut())
+            .flatten()
+        {
+            turn(point);
+        }
+        for portal in &mut self.portals {
+            turn(&mut portal.position);
+            portal.bounds.iter_mut().for_each(turn);
+            // angles increase clockwise on the image, because the y axis points down
+            portal.rotation += f64::consts::FRAC_PI_2;
+        }
+        for light in &mut self.lights {
+            turn(&mut light.position);
+        }
+        let resolution = &mut self.resolution;
+        resolution.map_size = Coordinate::new(resolution.map_size.y, resolution.map_size.x);
+        resolution.pixel_dimensions = resolution
+            .pixel_dimensions
+            .map(|dimensions| PixelCoordinate::new(dimensions.y, dimensions.x));
+    }
+
+    /// Save this vtt to a file like [`save_vtt`][crate::vtt::VTT::save_vtt()], where the stored
+    /// image has the lighting drawn onto it, so the file is marked as having baked lighting. When
+    /// `fog_of_war` is true the fog of war is drawn onto the image as well. Be careful with sharing
+    /// such a file with players, as it shows what is revealed without the fog of war, unless the
+    /// fog of war is included. The image of this vtt is not changed.
+    #[cfg(feature = "image")]
+    pub fn save_vtt_composited<P: AsRef<Path>>(&self, path: P, fog_of_war: bool) -> Result<()> {
+        let image = if fog_of_war {
+            self.get_pixbuf()?
+        } else {
+            self.get_pixbuf_without_fow()?
+        };
+        let image = BASE64_STANDARD.encode(encode_png(&image));
+        let mut environment = self.environment.clone();
+        environment.baked_lighting = true;
+        return VTT::write_uvtt(
+            path,
+            &UvttFile {
+                environment: Cow::Owned(environment),
+                image: &image,
+                ..self.to_uvtt()
+            },
+        );
+    }
+
+    /// Apply all vtt data (fog of war, lighting, etc.) to the image stored in this vtt and save it to a .png file. This
+    /// function will **not** overwrite the existing image stored in the vtt.
+    #[cfg(feature = "image")]
+    pub fn save_img<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let image = self.get_pixbuf()?;
+        return save_png(path, &image);
+    }
+
+    /// Save the image like [`save_img`][crate::vtt::VTT::save_img()], with the fog of war layer with
+    /// the given name drawn instead of the fog of war of the map, see
+    /// [`apply_fow_layer`][crate::vtt::VTT::apply_fow_layer()].
+    #[cfg(feature = "image")]
+    pub fn save_img_with_fow_layer<P: AsRef<Path>>(&self, path: P, name: &str) -> Result<()> {
+        let mut image = self.get_pixbuf_without_fow()?;
+        self.apply_fow_layer(&mut image, name)?;
+        return save_png(path, &image);
+    }
+
+    /// Save the image like [`save_img`][crate::vtt::VTT::save_img()], where the fog of war is drawn
+    /// with the given opacity. See [`apply_fow_with_opacity`][crate::vtt::VTT::apply_fow_with_opacity()].
+    #[cfg(feature = "image")]
+    pub fn save_img_with_fog_opacity<P: AsRef<Path>>(&self, path: P, opacity: u8) -> Result<()> {
+        let mut image = self.get_pixbuf_without_fow()?;
+        self.apply_fow_with_opacity(&mut image, opacity);
+        return save_png(path, &image);
+    }
+
+    /// Save the image like [`save_img`][crate::vtt::VTT::save_img()], with the square grid drawn
+    /// over it, see [`draw_grid`][crate::vtt::VTT::draw_grid()].
+    #[cfg(feature = "image")]
+    pub fn save_img_with_grid<P: AsRef<Path>>(
+        &self,
+        path: P,
+        line_color: Rgb,
+        thickness: u32,
+    ) -> Result<()> {
+        let mut image = self.get_pixbuf()?;
+        self.draw_grid(&mut image, line_color, thickness, 1);
+        return save_png(path, &image);
+    }
+
+    /// Draw the lines between the squares of the map onto an image of this vtt. Only every
+    /// `every`th line is drawn (counted from the origin of the map), which can be used to draw a
+    /// major grid. A value of 0 or 1 draws every line. Every line is placed from its own grid
+    /// coordinate, so the lines do not drift on large maps.
+    #[cfg(feature = "image")]
+    pub fn draw_grid(&self, image: &mut Pixbuf, line_color: Rgb, thickness: u32, every: u32) {
+        let every = every.max(1) as usize;
+        let start = self.origin().clone();
+        let end = Coordinate {
+            x: start.x + self.size().x,
+            y: start.y + self.size().y,
+        };
+        let offset = thickness as f64 / 2.0;
+        let (width, height) = (image.width(), image.height());
+        for x in (start.x.ceil() as i64..=end.x.floor() as i64).step_by(every) {
+            let line = self.to_pixel(Coord {
+                x: x as f64,
+                y: start.y,
+            });
+            let left = (line.x - offset).round() as i32;
+            image.fill_rect(left, 0, thickness, height, line_color);
+        }
+        for y in (start.y.ceil() as i64..=end.y.floor() as i64).step_by(every) {
+            let line = self.to_pixel(Coord {
+                x: start.x,
+                y: y as f64,
+            });
+            let top = (line.y - offset).round() as i32;
+            image.fill_rect(0, top, width, thickness, line_color);
+        }
+    }
+
+    /// Draw the ambient light and light sources of this vtt onto an image of this vtt. Every pixel
+    /// is first multiplied by the ambient light color. Then every light adds its color to the image
+    /// in a radial gradient that fades out at the range of the light following its [`Falloff`],
+    /// scaled by the intensity and the alpha of the color of the light. Lights that cast shadows do
+    /// not light anything that is hidden from them by walls or closed doors. Nothing is drawn when
+    /// the lighting is already baked into the image of the vtt.
+    #[cfg(feature = "image")]
+    pub fn apply_light(&self, image: &mut Pixbuf) {
+        if self.environment.baked_lighting {
+            return;
+        }
+        if let Some(ambient) = self.ambient_light().and_then(parse_hex_color) {
+            for y in 0..image.height() {
+                for x in 0..image.width() {
+                    let Rgb(pixel) = image.get_pixel(x, y);
+                    let tinted =
+                        std::array::from_fn(|i| (pixel[i] as u16 * ambient[i] as u16 / 255) as u8);
+                    image.put_pixel(x, y, Rgb(tinted));
+                }
+            }
+        }
+        let scale = self.resolution.scale();
+        for light in &self.lights {
+            let Some([r, g, b, alpha]) = parse_hex_rgba(&light.color) else {
+                continue;
+            };
+            let color = [r, g, b];
+            if light.range <= 0.0 {
+                continue;
+            }
+            let center = self.to_pixel(light.position.clone().into());
+            let radius = scale * light.range;
+            // the area lit by a light that casts shadows, in pixel coordinates
+            let shadow = light.shadows.then(|| {
+                let position = Coord::from(light.position.clone());
+                let bounds = square_around(position, light.range);
+                let walls = self.walls_near(light.position.clone(), light.range);
+                calculate_direct_los(position, &walls, bounds, self.los_step_size)
+                    .map_coords(|coord| self.to_pixel(coord))
+            });
+            let min_x = (center.x - radius.x).floor().max(0.0) as u32;
+            let min_y = (center.y - radius.y).floor().max(0.0) as u32;
+            let max_x = (center.x + radius.x)
+                .ceil()
+                .clamp(0.0, image.width() as f64) as u32;
+            let max_y = (center.y + radius.y)
+                .ceil()
+                .clamp(0.0, image.height() as f64) as u32;
+            for y in min_y..max_y {
+                let spans = match &shadow {
+                    Some(polygon) => scanline_spans(polygon, y as f64 + 0.5),
+                    None => vec![(min_x as f64, max_x as f64)],
+                };
+                for (start, end) in spans {
+                    // the pixels whose center lies inside the span
+                    let start = (start - 0.5).ceil().max(min_x as f64) as u32;
+                    let end = (end - 0.5).ceil().min(max_x as f64).max(0.0) as u32;
+                    for x in start..end {
+                        // the distance in squares
+                        let dx = (x as f64 + 0.5 - center.x) / scale.x;
+                        let dy = (y as f64 + 0.5 - center.y) / scale.y;
+                        let distance = (dx * dx + dy * dy).sqrt();
+                        if distance >= light.range {
+                            continue;
+                        }
+                        let strength = light.intensity
+                            * (alpha as f64 / 255.0)
+                            * light.falloff.strength(distance, light.range);
+                        let Rgb(pixel) = image.get_pixel(x, y);
+                        let lit = std::array::from_fn(|i| {
+                            (pixel[i] as f64 + color[i] as f64 * strength).min(255.0) as u8
+                        });
+                        image.put_pixel(x, y, Rgb(lit));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Helper function: returns the image of this vtt. The base64 encoded image is only decoded on
+    /// the first call.
+    #[cfg(feature = "image")]
+    fn decode_image(&self) -> Result<&Pixbuf> {
+        if let Some(image) = self.decoded_image.get() {
+            return Ok(image);
+        }
+        let image = self.decode_image_bytes()?;
+        return Ok(self.decoded_image.get_or_init(|| image));
+    }
+
+    /// Helper function: takes the decoded image of this vtt out of the cache without copying it,
+    /// for changes that replace the image anyway
+    #[cfg(feature = "image")]
+    fn take_decoded_image(&mut self) -> Result<Pixbuf> {
+        if let Some(image) = self.decoded_image.take() {
+            return Ok(image);
+        }
+        return self.decode_image_bytes();
+    }
+
+    /// Helper function: decodes the base64 encoded PNG or JPEG image of this vtt, without caching
+    /// it
+    #[cfg(feature = "image")]
+    fn decode_image_bytes(&self) -> Result<Pixbuf> {
+        let bytes = self.image_bytes()?;
+        if guess_format(&bytes) == Some(ImageFormat::Jpeg) {
+            return Ok(decode_jpeg(&bytes)?);
+        }
+        return Ok(decode_png(&bytes)?);
+    }
+}
+
+/// Helper function: returns a polygon with the given number of segments (at least 3) whose
+/// corners lie on the ellipse with the given radii around the center
+fn circle_polygon(center: Coord, radius: Coord, segments: usize) -> Polygon {
+    let segments = segments.max(3);
+    let ring: Vec<Coord> = (0..segments)
+        .map(|i| {
+            let angle = i as f64 / segments as f64 * f64::consts::TAU;
+            Coord {
+                x: center.x + radius.x * angle.cos(),
+                y: center.y + radius.y * angle.sin(),
+            }
+        })
+        .collect();
+    return Polygon::new(LineString::from(ring), vec![]);
+}
+
+/// Helper function: returns a negative number if the point lies to the left of the line, as seen
+/// on the image where the y axis points down, a positive number if it lies to the right and 0 if
+/// it lies on the line
+fn side_of_line(line: Line, point: Coord) -> f64 {
+    let offset = point - line.start;
+    return line.dx() * offset.y - line.dy() * offset.x;
+}
+
+/// Helper function: returns the square with sides of twice the radius around the center
+fn square_around(center: Coord, radius: f64) -> Rect {
+    let reach = Coord {
+        x: radius,
+        y: radius,
+    };
+    return Rect::new(center - reach, center + reach);
+}
+
+/// Helper function: removes points that are identical to the point before them, so the wall does
+/// not contain segments without length
+fn remove_zero_length_segments(wall: &mut Vec<Coordinate>) {
+    wall.dedup_by(|point, previous| point.x == previous.x && point.y == previous.y);
+}
+
+/// Helper function: blends every rectangle of the image with black using the given opacity
+#[cfg(feature = "image")]
+fn darken_rectangles(image: &mut Pixbuf, rectangles: &[FoWRectangle], opacity: u8) {
+    let rectangles: Vec<_> = rectangles
+        .iter()
+        .map(|rectangle| {
+            (
+                rectangle.topleft.x,
+                rectangle.topleft.y,
+                rectangle.width() as u32,
+                rectangle.height() as u32,
+            )
+        })
+        .collect();
+    image.blend_rects(&rectangles, Rgb([0, 0, 0]), opacity);
+}
+
+/// Helper function: encode an image as png and write it to a file
+#[cfg(feature = "image")]
+fn save_png<P: AsRef<Path>>(path: P, image: &Pixbuf) -> Result<()> {
+    let mut file = File::options()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&path)?;
+    file.write_all(&encode_png(image))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::RustVttError;
+    use crate::fog_of_war::FoWRectangle;
+    use crate::format::ImageFormat;
+    use crate::helper::calculate_direct_los;
+    use crate::tests::temp_path;
+    #[cfg(feature = "image")]
+    use crate::vtt::Falloff;
+    use crate::vtt::{
+        Coordinate, Light, PixelCoordinate, PortalInfo, VisionConfig, WallIssue, VTT,
+    };
+    use crate::VttBuilder;
+    use crate::{open_vtt, Operation};
+    #[cfg(feature = "image")]
+    use crate::{AmbientLight, Pixbuf, Rgb};
+    use base64::{prelude::BASE64_STANDARD, Engine as _};
+    use geo::{polygon, Area, Contains, Coord, InteriorPoint, Line, Rect, Winding};
+    use std::f64;
+
+    fn coordinate(x: f64, y: f64) -> Coordinate {
+        return Coordinate { x, y };
+    }
+
+    #[test]
+    fn vtt_origin() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let origin = vtt.origin();
+        assert_eq!(
+            origin.x, 0.0,
+            "x origin did not match. Expected 0.0, found {}",
+            origin.x
+        );
+        assert_eq!(
+            origin.y, 0.0,
+            "y origin did not match. Expected 0.0, found {}",
+            origin.y
+        );
+    }
+
+    #[test]
+    fn vtt_size() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let size = vtt.size();
+        assert_eq!(
+            size.x, 27.0,
+            "x size did not match. Expected 27.0, found {}",
+            size.x
+        );
+        assert_eq!(
+            size.y, 15.0,
+            "y size did not match. Expected 15.0, found {}",
+            size.y
+        );
+    }
+
+    #[test]
+    fn vtt_pixels_per_grid() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        assert_eq!(
+            vtt.pixels_per_grid(),
+            256,
+            "pixels per grid did not match. Expected 256, found {}",
+            vtt.pixels_per_grid()
+        );
+    }
+
+    #[test]
+    fn vtt_rooms() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let rooms = vtt.rooms();
+        assert_eq!(
+            rooms.len(),
+            3,
+            "Expected 3 rooms in example1, found {}",
+            rooms.len()
+        );
+        let total: f64 = rooms.iter().map(|room| room.unsigned_area()).sum();
+        assert!(
+            (total - 84.0).abs() < 1e-6,
+            "Expected a total room area of 84.0, found {}",
+            total
+        );
+        // example1 has one open door connecting two of the rooms
+        let joined = vtt.rooms_joined_by_open_doors();
+        assert_eq!(
+            joined.len(),
+            2,
+            "Expected 2 rooms when joining through open doors, found {}",
+            joined.len()
+        );
+        let joined_total: f64 = joined.iter().map(|room| room.unsigned_area()).sum();
+        assert!(
+            (joined_total - total).abs() < 1e-6,
+            "Joining rooms should not change the total area, found {}",
+            joined_total
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_draw_portals() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let segments = vtt.portal_segments();
+        assert_eq!(
+            segments
+                .iter()
+                .map(|(_, closed)| *closed)
+                .collect::<Vec<bool>>(),
+            vec![true, false, true],
+            "door states did not match"
+        );
+        let ppg = vtt.pixels_per_grid() as u32;
+        let mut image = Pixbuf::new(vtt.size().x as u32 * ppg, vtt.size().y as u32 * ppg);
+        let (open, closed) = (Rgb([0, 255, 0]), Rgb([255, 0, 0]));
+        vtt.draw_portals(&mut image, open, closed);
+        for (line, is_closed) in segments {
+            let middle = (line.start + line.end) / 2.0 * ppg as f64;
+            let expected = if is_closed { closed } else { open };
+            assert_eq!(
+                image.get_pixel(middle.x as u32, middle.y as u32),
+                expected,
+                "Expected the middle of the door at {:?} to be drawn",
+                middle
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_apply_light() {
+        let vtt = open_vtt("tests/resources/The Pig and Whistle tavern.uvtt")
+            .expect("Could not open file the pig and whistle tavern.uvtt");
+        let original = vtt
+            .decode_image()
+            .expect("Could not decode the tavern image");
+        assert_eq!(
+            (original.width(), original.height()),
+            (32 * 120, 22 * 120),
+            "Decoded image size did not match the map size"
+        );
+        let mut lit = original.clone();
+        vtt.apply_light(&mut lit);
+        let brightness = |image: &Pixbuf, x: u32, y: u32| -> u32 {
+            image.get_pixel(x, y).0.iter().map(|&c| c as u32).sum()
+        };
+        // the first light of the tavern is at (13.26047, 4.266033)
+        let (light_x, light_y) = ((13.26047 * 120.0) as u32, (4.266033 * 120.0) as u32);
+        assert!(
+            brightness(&lit, light_x, light_y) > brightness(original, light_x, light_y),
+            "Expected the pixel at the light position to be brightened"
+        );
+        assert_eq!(
+            lit.get_pixel(0, 0),
+            original.get_pixel(0, 0),
+            "Expected the pixel at the map edge to be untouched"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_light_falloff() {
+        let mut vtt = VttBuilder::new(
+            Coordinate { x: 0.0, y: 0.0 },
+            Coordinate { x: 8.0, y: 8.0 },
+            20,
+        )
+        .add_light(
+            Coordinate { x: 4.0, y: 4.0 },
+            4.0,
+            0.8,
+            "ffffffff".to_string(),
+            false,
+        )
+        .build()
+        .expect("Could not build the vtt");
+        // the center of the light and a pixel two squares to the right
+        let brightness = |vtt: &VTT, x: u32| -> f64 {
+            let image = vtt
+                .get_pixbuf_without_fow()
+                .expect("Could not decode the image");
+            return image.get_pixel(x, 80).0[0] as f64;
+        };
+        assert_eq!(vtt.lights()[0].falloff(), Falloff::Linear);
+        let (center, half) = (brightness(&vtt, 80), brightness(&vtt, 120));
+        assert!(
+            (half / center - 0.5).abs() < 0.05,
+            "Expected half the range to be half as bright, found {} and {}",
+            center,
+            half
+        );
+        vtt.light_mut(0)
+            .expect("Expected a light")
+            .set_falloff(Falloff::Constant);
+        assert_eq!(brightness(&vtt, 120), brightness(&vtt, 80));
+        vtt.light_mut(0)
+            .expect("Expected a light")
+            .set_falloff(Falloff::InverseSquare);
+        assert!(brightness(&vtt, 120) < half);
+        assert_eq!(
+            brightness(&vtt, 0),
+            0.0,
+            "Expected no light outside the range"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_apply_light_baked() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut image = Pixbuf::new(vtt.size().x as u32 * 256, vtt.size().y as u32 * 256);
+        vtt.apply_light(&mut image);
+        assert!(
+            image.as_raw().iter().all(|&c| c == 0),
+            "Expected no lights to be drawn on a map with baked lighting"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_ambient_light() {
+        let mut vtt = open_vtt("tests/resources/example4.dd2vtt")
+            .expect("Could not open file example4.dd2vtt");
+        vtt.set_ambient_light(AmbientLight::NightTime)
+            .expect("Could not set the ambient light");
+        assert!(
+            vtt.set_ambient_light(AmbientLight::Custom("not a color".to_string()))
+                .is_err(),
+            "Expected an invalid custom color to be rejected"
+        );
+
+        let mut image = Pixbuf::from_raw(2, 1, vec![255; 6]).unwrap();
+        vtt.apply_light(&mut image);
+        assert_eq!(
+            image.get_pixel(0, 0),
+            Rgb([0x51, 0x5b, 0x6a]),
+            "Expected a white pixel to be tinted by the night time color"
+        );
+
+        let path = temp_path("ambient_light.dd2vtt");
+        vtt.save_vtt(&path).expect("Could not save the vtt");
+        let reopened = open_vtt(&path).expect("Could not open the saved vtt");
+        assert_eq!(
+            reopened.ambient_light(),
+            Some("ff515b6a"),
+            "Ambient light did not persist"
+        );
+
+        vtt.set_ambient_light(None)
+            .expect("Could not remove the ambient light");
+        assert_eq!(vtt.ambient_light(), None, "Ambient light was not removed");
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_apply_fow() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_hide_all();
+        assert_eq!(
+            vtt.fog_of_war.get_rectangles()[0].bottomright,
+            PixelCoordinate {
+                x: 27 * 256,
+                y: 15 * 256
+            },
+            "Fog of war bounds did not match the image size"
+        );
+        vtt.fow_show_all();
+        let mut image = Pixbuf::from_raw(4, 4, vec![255; 48]).unwrap();
+        vtt.apply_fow(&mut image);
+        assert_eq!(image.get_pixel(3, 3), Rgb([255, 255, 255]));
+        vtt.fow_hide_all();
+        let mut dimmed = image.clone();
+        vtt.apply_fow_with_opacity(&mut dimmed, 128);
+        assert_eq!(
+            dimmed.get_pixel(3, 3),
+            Rgb([127, 127, 127]),
+            "Expected a hidden pixel to be dimmed"
+        );
+        vtt.apply_fow(&mut image);
+        assert!(
+            image.as_raw().iter().all(|&c| c == 0),
+            "Expected a hidden map to be drawn black"
+        );
+    }
+
+    #[test]
+    fn vtt_save_load_fow() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_hide_all();
+        let revealed = polygon![(x: 256.0, y: 256.0), (x: 1500.0, y: 400.0), (x: 700.0, y: 2000.0)];
+        vtt.fog_of_war.update(&revealed, Operation::Show);
+        let path = temp_path("save_load.fow");
+        vtt.save_fow(&path).expect("Could not save the fog of war");
+
+        let mut reopened = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        reopened
+            .load_fow(&path)
+            .expect("Could not load the fog of war");
+        assert_eq!(
+            reopened.fog_of_war().get_rectangles(),
+            vtt.fog_of_war().get_rectangles(),
+            "Loaded fog of war did not match the saved fog of war"
+        );
+    }
+
+    #[test]
+    fn vtt_serde_fow() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_hide_all();
+        let revealed = polygon![(x: 256.0, y: 256.0), (x: 1500.0, y: 400.0), (x: 700.0, y: 2000.0)];
+        vtt.fog_of_war.update(&revealed, Operation::Show);
+        let json = serde_json::to_string(&vtt).expect("Could not serialize the vtt");
+        let restored: VTT = serde_json::from_str(&json).expect("Could not deserialize the vtt");
+        assert_eq!(
+            restored.fog_of_war().get_rectangles(),
+            vtt.fog_of_war().get_rectangles(),
+            "Expected the fog of war to be part of the serialized vtt"
+        );
+
+        let path = temp_path("serde_fow.dd2vtt");
+        vtt.save_vtt(&path).expect("Could not save the vtt");
+        let saved: serde_json::Value =
+            serde_json::from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+        assert!(
+            saved.get("fog_of_war").is_none(),
+            "Expected save_vtt to leave out the fog of war"
+        );
+
+        let mut json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        json["resolution"]["map_size"]["x"] = serde_json::json!(10.0);
+        assert!(
+            serde_json::from_value::<VTT>(json).is_err(),
+            "Expected a fog of war of a different size to be rejected"
+        );
+    }
+
+    #[test]
+    fn vtt_serde_pixel_dimensions() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let dimensions = PixelCoordinate { x: 2700, y: 1500 };
+        vtt.set_pixel_dimensions(Some(dimensions))
+            .expect("Could not set the pixel dimensions");
+        vtt.fow_hide_all();
+        vtt.fow_change_rect(
+            Coordinate { x: 1.0, y: 1.0 },
+            Coordinate { x: 4.0, y: 3.0 },
+            Operation::Show,
+        );
+        let json = serde_json::to_string(&vtt).expect("Could not serialize the vtt");
+        let restored: VTT = serde_json::from_str(&json).expect("Could not deserialize the vtt");
+        assert_eq!(restored.pixel_size(), dimensions);
+        assert_eq!(
+            restored.fog_of_war().get_rectangles(),
+            vtt.fog_of_war().get_rectangles(),
+            "Expected the fog of war to be restored at the pixel dimensions"
+        );
+        let reopened =
+            crate::open_vtt_from_bytes(json.as_bytes()).expect("Could not open the serialized vtt");
+        assert_eq!(reopened.pixel_size(), dimensions);
+
+        let path = temp_path("serde_pixel_dimensions.dd2vtt");
+        vtt.save_vtt(&path).expect("Could not save the vtt");
+        let saved: serde_json::Value =
+            serde_json::from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+        assert!(
+            saved["resolution"].get("pixel_dimensions").is_none(),
+            "Expected save_vtt to leave out the pixel dimensions"
+        );
+        assert!(vtt.resolution().pixel_dimensions.is_some());
+    }
+
+    #[test]
+    fn vtt_fow_reveal_circle() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_hide_all();
+        vtt.fow_reveal_circle(Coordinate { x: 5.0, y: 5.0 }, 0.0, Operation::Show);
+        assert_eq!(
+            vtt.fog_of_war().get_rectangles().len(),
+            1,
+            "Expected a radius of zero to not change anything"
+        );
+
+        let hidden_area = |vtt: &VTT| -> f64 {
+            vtt.fog_of_war()
+                .get_rectangles()
+                .iter()
+                .map(|r| r.width() as f64 * r.height() as f64)
+                .sum()
+        };
+        let total = hidden_area(&vtt);
+        // a circle in the top left corner is clipped to a quarter circle
+        vtt.fow_reveal_circle(Coordinate { x: 0.0, y: 0.0 }, 2.0, Operation::Show);
+        let expected = f64::consts::PI * (2.0 * 256.0f64).powi(2) / 4.0;
+        let revealed = total - hidden_area(&vtt);
+        assert!(
+            (revealed - expected).abs() / expected < 0.01,
+            "Expected about {} pixels to be revealed, found {}",
+            expected,
+            revealed
+        );
+        vtt.fow_reveal_circle(Coordinate { x: 0.0, y: 0.0 }, 2.0, Operation::Hide);
+        assert_eq!(vtt.fog_of_war().get_rectangles().len(), 1);
+    }
+
+    #[test]
+    fn vtt_fow_show_around_walls() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_hide_all();
+        let hidden_area = |vtt: &VTT| -> f64 {
+            vtt.fog_of_war()
+                .get_rectangles()
+                .iter()
+                .map(|r| r.width() as f64 * r.height() as f64)
+                .sum()
+        };
+        let total = hidden_area(&vtt);
+        // the room of 15.5 squares is connected to the room of 18 squares by an open door
+        let room = vtt
+            .rooms()
+            .into_iter()
+            .find(|room| (room.unsigned_area() - 15.5).abs() < 1e-6)
+            .expect("Expected example1 to contain a room of 15.5 squares");
+        let pov = room
+            .interior_point()
+            .expect("Expected the room to have an interior");
+        vtt.fow_show(
+            Coordinate {
+                x: pov.x(),
+                y: pov.y(),
+            },
+            true,
+        )
+        .expect("Could not reveal the room");
+        let revealed = (total - hidden_area(&vtt)) / (256.0 * 256.0);
+        assert!(
+            (revealed - 33.5).abs() < 0.1,
+            "Expected both rooms of 33.5 squares to be revealed, found {}",
+            revealed
+        );
+        assert!(
+            vtt.fow_hide(Coordinate { x: 27.0, y: 1.0 }, true).is_err(),
+            "Expected a pov outside the map to be rejected"
+        );
+    }
+
+    #[test]
+    fn vtt_export_foundry_walls() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let walls = vtt.export_foundry_walls();
+        let walls = walls.as_array().expect("Expected an array of walls");
+        let doors: Vec<_> = walls.iter().filter(|wall| wall["door"] == 1).collect();
+        assert_eq!(
+            doors.len(),
+            3,
+            "Expected every portal to be exported as a door"
+        );
+        let ppg = vtt.pixels_per_grid() as f64;
+        let (line, _) = vtt.portal_segments()[0];
+        let expected = [
+            (line.start.x - vtt.origin().x) * ppg,
+            (line.start.y - vtt.origin().y) * ppg,
+            (line.end.x - vtt.origin().x) * ppg,
+            (line.end.y - vtt.origin().y) * ppg,
+        ];
+        assert_eq!(
+            doors[0]["c"],
+            serde_json::json!(expected),
+            "Door was not exported in pixel coordinates"
+        );
+        assert!(
+            walls.len() > doors.len(),
+            "Expected the walls to be exported"
+        );
+    }
+
+    #[test]
+    fn vtt_walls_geojson() {
+        let vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(8.0, 4.0), 16)
+            .add_wall(vec![
+                coordinate(1.0, 1.0),
+                coordinate(3.0, 1.0),
+                coordinate(3.0, 3.0),
+            ])
+            .add_object(vec![coordinate(5.0, 1.0), coordinate(6.0, 1.0)])
+            .add_portal(coordinate(3.0, 1.0), coordinate(4.0, 1.0), false, false)
+            .build()
+            .expect("Could not build the vtt");
+        let geojson: serde_json::Value =
+            serde_json::from_str(&vtt.walls_geojson()).expect("Expected valid json");
+        assert_eq!(geojson["type"], "FeatureCollection");
+        let features = geojson["features"].as_array().unwrap();
+        let kinds: Vec<&str> = features
+            .iter()
+            .map(|feature| feature["properties"]["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, vec!["wall", "object", "door"]);
+        assert_eq!(features[0]["geometry"]["type"], "LineString");
+        assert_eq!(
+            features[0]["geometry"]["coordinates"],
+            serde_json::json!([[1.0, 1.0], [3.0, 1.0], [3.0, 3.0]]),
+            "Expected the wall in grid coordinates"
+        );
+        assert_eq!(features[2]["properties"]["closed"], false);
+    }
+
+    #[test]
+    fn vtt_export_roll20_paths() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.close_all_doors();
+        let paths = vtt.export_roll20_paths();
+        let paths = paths.as_array().expect("Expected an array of paths");
+        let walls = vtt.line_of_sight().len() + vtt.objects_los().len();
+        assert_eq!(
+            paths.len(),
+            walls + vtt.portal_segments().len(),
+            "Expected a path for every wall, object and closed door"
+        );
+        let door = &paths[walls];
+        let commands: serde_json::Value = serde_json::from_str(
+            door["path"]
+                .as_str()
+                .expect("Expected the path as a string"),
+        )
+        .expect("Expected the path to be json");
+        let ppg = vtt.pixels_per_grid() as f64;
+        let (line, _) = vtt.portal_segments()[0];
+        let left = door["left"].as_f64().unwrap() - door["width"].as_f64().unwrap() / 2.0;
+        let top = door["top"].as_f64().unwrap() - door["height"].as_f64().unwrap() / 2.0;
+        assert_eq!(commands[0][0], "M");
+        assert_eq!(commands[1][0], "L");
+        assert!(
+            (left + commands[0][1].as_f64().unwrap() - line.start.x * ppg).abs() < 1e-6
+                && (top + commands[0][2].as_f64().unwrap() - line.start.y * ppg).abs() < 1e-6,
+            "Expected the door to start at its first bound in pixel coordinates"
+        );
+
+        vtt.open_all_doors();
+        assert_eq!(
+            vtt.export_roll20_paths().as_array().map(Vec::len),
+            Some(walls),
+            "Expected open doors to be left out"
+        );
+    }
+
+    #[test]
+    fn vtt_pixel_size() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let size = vtt.pixel_size();
+        assert_eq!(
+            (size.x, size.y),
+            ((vtt.size().x * 256.0) as i32, (vtt.size().y * 256.0) as i32),
+            "pixel size did not match"
+        );
+        assert_eq!(vtt.pixel_origin(), PixelCoordinate { x: 0, y: 0 });
+        let (width, height) = vtt
+            .image_dimensions()
+            .expect("Could not read image dimensions");
+        assert_eq!(
+            (width as i32, height as i32),
+            (size.x, size.y),
+            "Expected the image to match the map size"
+        );
+    }
+
+    #[test]
+    fn vtt_image_bytes() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let bytes = vtt.image_bytes().expect("Could not decode the image");
+        assert!(crate::format::is_png(&bytes), "Expected the PNG bytes");
+        assert_eq!(vtt.image_format().unwrap(), Some(ImageFormat::Png));
+        vtt.image = BASE64_STANDARD.encode([0xff, 0xd8, 0xff, 0xe0, 0, 0x10]);
+        assert_eq!(
+            vtt.image_format().unwrap(),
+            Some(ImageFormat::Jpeg),
+            "Expected a JPEG image to be recognized"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_jpeg_image() {
+        let mut vtt = open_vtt("tests/resources/example2.dd2vtt")
+            .expect("Could not open file example2.dd2vtt");
+        assert_eq!(vtt.image_format().unwrap(), Some(ImageFormat::Jpeg));
+        let size = vtt.pixel_size();
+        assert_eq!(
+            vtt.image_dimensions()
+                .expect("Could not read image dimensions"),
+            (size.x as u32, size.y as u32),
+            "Expected the JPEG image to match the map size"
+        );
+        let path = temp_path("raw_jpeg.v2");
+        vtt.save_img_raw(&path)
+            .expect("Could not save the raw image");
+        let saved = std::fs::read(temp_path("raw_jpeg.v2.jpg"))
+            .expect("Expected the .jpg extension to be appended to the path");
+        assert_eq!(saved, vtt.image_bytes().unwrap());
+
+        vtt.fow_hide_all();
+        let image = vtt.get_pixbuf().expect("Could not draw on the JPEG image");
+        assert_eq!(
+            (image.width(), image.height()),
+            (size.x as u32, size.y as u32)
+        );
+        assert_eq!(
+            image.get_pixel(10, 10),
+            Rgb([0, 0, 0]),
+            "Expected the fog of war to be drawn on the JPEG image"
+        );
+    }
+
+    #[test]
+    fn vtt_fow_show_direct() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        assert!(
+            vtt.set_los_resolution(0.0).is_err(),
+            "Expected a resolution of 0 to be rejected"
+        );
+        assert!(
+            vtt.set_los_resolution(f64::NAN).is_err(),
+            "Expected a NaN resolution to be rejected"
+        );
+        assert_eq!(vtt.los_resolution(), 0.2);
+        let room = vtt
+            .rooms()
+            .into_iter()
+            .find(|room| (room.unsigned_area() - 15.5).abs() < 1e-6)
+            .expect("Expected example1 to contain a room of 15.5 squares");
+        let pov = room
+            .interior_point()
+            .expect("Expected the room to have an interior");
+        let pov = Coordinate {
+            x: pov.x(),
+            y: pov.y(),
+        };
+        let revealed = |vtt: &mut VTT| -> f64 {
+            vtt.fow_hide_all();
+            vtt.fow_show(pov.clone(), false)
+                .expect("Could not reveal the line of sight");
+            let size = vtt.pixel_size();
+            let hidden: f64 = vtt
+                .fog_of_war()
+                .get_rectangles()
+                .iter()
+                .map(|r| r.width() as f64 * r.height() as f64)
+                .sum();
+            (size.x as f64 * size.y as f64 - hidden) / (256.0 * 256.0)
+        };
+        let coarse = revealed(&mut vtt);
+        vtt.set_los_resolution(0.05)
+            .expect("Could not set the los resolution");
+        let fine = revealed(&mut vtt);
+        assert!(
+            fine > 1.0 && fine < vtt.size().x * vtt.size().y,
+            "Expected only part of the map to be revealed, found {}",
+            fine
+        );
+        assert!(
+            (coarse - fine).abs() < 1.0,
+            "Expected the resolution to only change the edges, found {} and {}",
+            coarse,
+            fine
+        );
+    }
+
+    #[test]
+    fn vtt_wall_segments_cache() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let closed_doors = |vtt: &VTT| {
+            vtt.portal_segments()
+                .iter()
+                .filter(|(_, closed)| *closed)
+                .count()
+        };
+        let walls = vtt.wall_segments(false).len() - closed_doors(&vtt);
+        let cached = vtt.wall_segments(false).as_ptr();
+        assert_eq!(
+            cached,
+            vtt.wall_segments(false).as_ptr(),
+            "Expected the wall segments to be cached"
+        );
+        let lines = vtt.line_segments(false).as_ptr();
+        let closed = vtt
+            .portal_segments()
+            .iter()
+            .position(|(_, closed)| *closed)
+            .expect("Expected example1 to have a closed door");
+        vtt.set_door_state(closed, false);
+        assert_eq!(
+            vtt.wall_segments(false).len(),
+            walls + closed_doors(&vtt),
+            "Expected the opened door to be removed from the wall segments"
+        );
+        assert!(
+            vtt.wall_segments(true).len() >= vtt.wall_segments(false).len(),
+            "Expected the objects to be added to the wall segments"
+        );
+        assert_eq!(
+            lines,
+            vtt.line_segments(false).as_ptr(),
+            "Expected the segments of the walls to stay cached when a door changes"
+        );
+        vtt.add_wall(vec![Coordinate::new(0.5, 0.5), Coordinate::new(1.5, 0.5)]);
+        assert_eq!(vtt.line_segments(false).len(), walls + 1);
+    }
+
+    #[test]
+    fn vtt_is_visible() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let center = Coordinate { x: 5.0, y: 5.0 };
+        assert!(vtt.is_visible(center.clone()));
+        vtt.fow_hide_all();
+        assert!(!vtt.is_visible(center.clone()));
+        vtt.fow_reveal_circle(center.clone(), 1.0, Operation::Show);
+        assert!(vtt.is_visible(center));
+        assert!(!vtt.is_visible(Coordinate { x: 5.0, y: 7.0 }));
+        vtt.fow_show_all();
+        assert!(
+            !vtt.is_visible(Coordinate { x: 27.0, y: 1.0 }),
+            "Expected a coordinate outside the map to not be visible"
+        );
+    }
+
+    #[test]
+    fn vtt_fow_change_rect() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_hide_all();
+        vtt.fow_change_rect(
+            Coordinate { x: 4.0, y: 6.0 },
+            Coordinate { x: 2.0, y: 1.0 },
+            Operation::Show,
+        );
+        assert!(vtt.is_visible(Coordinate { x: 3.0, y: 3.0 }));
+        assert!(!vtt.is_visible(Coordinate { x: 5.0, y: 3.0 }));
+        let hidden: i64 = vtt
+            .fog_of_war()
+            .get_rectangles()
+            .iter()
+            .map(|r| r.width() as i64 * r.height() as i64)
+            .sum();
+        let revealed = (27 * 15 * 256 * 256 - hidden) as f64 / (256.0 * 256.0);
+        assert!(
+            (revealed - 10.0).abs() < 0.1,
+            "Expected 10 squares to be revealed, found {}",
+            revealed
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_save_fow_mask() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_hide_all();
+        vtt.fow_change_rect(
+            Coordinate { x: 0.0, y: 0.0 },
+            Coordinate { x: 2.0, y: 1.0 },
+            Operation::Show,
+        );
+        let path = temp_path("fow_mask.png");
+        vtt.save_fow_mask(&path).expect("Could not save the mask");
+        let bytes = std::fs::read(&path).expect("Could not read the mask");
+        // the alpha channel is dropped when decoding
+        let mask = crate::png::decode_png(&bytes).expect("Could not decode the mask");
+        let size = vtt.pixel_size();
+        assert_eq!(
+            (mask.width() as i32, mask.height() as i32),
+            (size.x, size.y),
+            "Expected the mask to match the pixel size of the map"
+        );
+        assert_eq!(mask.get_pixel(100, 100), Rgb([0, 0, 0]));
+        assert_eq!(mask.get_pixel(600, 100), Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn vtt_portal_without_bounds() {
+        let contents = std::fs::read_to_string("tests/resources/example1.dd2vtt")
+            .expect("Could not read file example1.dd2vtt");
+        let mut json: serde_json::Value =
+            serde_json::from_str(&contents).expect("Could not parse example1.dd2vtt");
+        json["portals"][0]["bounds"] = serde_json::json!([]);
+        json["portals"][1]["bounds"] = serde_json::json!([{ "x": 1.0, "y": 1.0 }]);
+        json["portals"][1]["closed"] = serde_json::json!(true);
+        let mut vtt = crate::open_vtt_from_bytes(json.to_string().as_bytes())
+            .expect("Expected a portal without bounds to be accepted");
+        assert_eq!(
+            vtt.portal_segments().len(),
+            1,
+            "Expected the portals without two bounds to be skipped"
+        );
+        assert!(!vtt.rooms().is_empty());
+        vtt.fow_show(Coordinate { x: 5.0, y: 5.0 }, true)
+            .expect("Could not reveal around walls");
+        vtt.fow_show(Coordinate { x: 5.0, y: 5.0 }, false)
+            .expect("Could not reveal the line of sight");
+        let walls = vtt.export_foundry_walls();
+        let doors = walls
+            .as_array()
+            .expect("Expected an array of walls")
+            .iter()
+            .filter(|wall| wall["door"] == 1)
+            .count();
+        assert_eq!(doors, 1, "Expected only the valid portal to be exported");
+    }
+
+    #[test]
+    fn vtt_set_pixel_dimensions() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        assert_eq!(vtt.pixel_scale(), (256.0, 256.0));
+        assert!(vtt
+            .set_pixel_dimensions(Some(PixelCoordinate { x: 0, y: 100 }))
+            .is_err());
+        vtt.set_pixel_dimensions(Some(PixelCoordinate { x: 2700, y: 1800 }))
+            .expect("Could not set the pixel dimensions");
+        assert_eq!(vtt.pixel_scale(), (100.0, 120.0));
+        assert_eq!(vtt.pixel_size(), PixelCoordinate { x: 2700, y: 1800 });
+        vtt.fow_hide_all();
+        assert_eq!(
+            vtt.fog_of_war().get_rectangles()[0].bottomright,
+            PixelCoordinate { x: 2700, y: 1800 },
+            "Expected the fog of war to cover the pixel dimensions"
+        );
+        vtt.fow_change_rect(
+            Coordinate { x: 26.0, y: 14.0 },
+            Coordinate { x: 27.0, y: 15.0 },
+            Operation::Show,
+        );
+        let hidden: i64 = vtt
+            .fog_of_war()
+            .get_rectangles()
+            .iter()
+            .map(|r| r.width() as i64 * r.height() as i64)
+            .sum();
+        assert!(
+            ((2700 * 1800 - hidden) as f64 - 100.0 * 120.0).abs() < 200.0,
+            "Expected the bottom right square to be revealed"
+        );
+        vtt.set_pixel_dimensions(None)
+            .expect("Could not reset the pixel dimensions");
+        assert_eq!(
+            vtt.pixel_size(),
+            PixelCoordinate {
+                x: 27 * 256,
+                y: 15 * 256
+            }
+        );
+    }
+
+    #[test]
+    fn vtt_fow_change_px() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let pixel = PixelCoordinate { x: 800, y: 900 };
+        vtt.fow_hide_all();
+        vtt.fow_change_px(pixel, Operation::Show, false)
+            .expect("Could not reveal from the pixel");
+        let from_pixel = vtt.fog_of_war().get_rectangles();
+        vtt.fow_hide_all();
+        vtt.fow_show(vtt.pixel_to_grid(pixel), false)
+            .expect("Could not reveal from the grid coordinate");
+        assert_eq!(
+            vtt.fog_of_war().get_rectangles(),
+            from_pixel,
+            "Expected the pixel to reveal the same area as its grid coordinate"
+        );
+        assert!(vtt.is_visible(vtt.pixel_to_grid(pixel)));
+        vtt.fow_hide_all();
+        vtt.fow_change_px(pixel, Operation::Show, true)
+            .expect("Could not reveal around walls from the pixel");
+        let around_walls = vtt.fog_of_war().get_rectangles();
+        vtt.fow_hide_all();
+        vtt.fow_show(vtt.pixel_to_grid(pixel), true)
+            .expect("Could not reveal around walls from the grid coordinate");
+        assert_eq!(vtt.fog_of_war().get_rectangles(), around_walls);
+        assert!(vtt
+            .fow_change_px(PixelCoordinate { x: -1, y: 0 }, Operation::Show, false)
+            .is_err());
+    }
+
+    #[test]
+    fn vtt_fow_change_cone() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let pov = Coordinate { x: 3.0, y: 3.0 };
+        let reveal = |vtt: &mut VTT, facing: f64, fov: f64| -> Vec<FoWRectangle> {
+            vtt.fow_hide_all();
+            vtt.fow_change_cone(pov.clone(), facing, fov, Operation::Show, false, true)
+                .expect("Could not reveal the cone");
+            vtt.fog_of_war().get_rectangles()
+        };
+        let full = reveal(&mut vtt, 0.0, 360.0);
+        vtt.fow_hide_all();
+        vtt.fow_show(pov.clone(), false)
+            .expect("Could not reveal the line of sight");
+        assert_eq!(
+            vtt.fog_of_war().get_rectangles(),
+            full,
+            "Expected a field of view of 360 degrees to equal fow_change"
+        );
+
+        assert_eq!(
+            reveal(&mut vtt, 0.0, 90.0),
+            reveal(&mut vtt, 360.0, 90.0),
+            "Expected a cone across the 0 degree seam to not depend on the winding"
+        );
+        vtt.fow_hide_all();
+        vtt.fow_change_cone(pov.clone(), 0.0, 90.0, Operation::Show, false, true)
+            .unwrap();
+        assert!(vtt.is_visible(Coordinate { x: 3.5, y: 3.0 }));
+        assert!(
+            !vtt.is_visible(Coordinate { x: 2.5, y: 3.0 }),
+            "Expected the area behind the pov to stay hidden"
+        );
+
+        assert!(vtt
+            .fow_change_cone(pov.clone(), 0.0, 0.0, Operation::Show, false, true)
+            .is_err());
+        assert!(vtt
+            .fow_change_cone(pov, f64::NAN, 90.0, Operation::Show, false, true)
+            .is_err());
+    }
+
+    #[test]
+    fn vtt_fow_change_with_progress() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut expected = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_hide_all();
+        expected.fow_hide_all();
+        let pov = Coordinate::new(4.0, 7.0);
+        for around_walls in [false, true] {
+            let mut reported = Vec::new();
+            vtt.fow_change_with_progress(
+                pov.clone(),
+                Operation::Show,
+                around_walls,
+                &mut |fraction| reported.push(fraction),
+            )
+            .expect("Could not reveal the fog of war");
+            expected
+                .fow_change(pov.clone(), Operation::Show, around_walls)
+                .unwrap();
+            assert_eq!(
+                vtt.fog_of_war().get_rectangles(),
+                expected.fog_of_war().get_rectangles(),
+                "Expected reporting progress to not change the result"
+            );
+            assert_eq!(reported.first(), Some(&0.0));
+            assert_eq!(reported.last(), Some(&1.0));
+            assert!(reported.windows(2).all(|pair| pair[0] <= pair[1]));
+            if !around_walls {
+                assert!(
+                    reported.len() > 10,
+                    "Expected the ray sweep to report progress"
+                );
+            }
+        }
+        let outside = Coordinate::new(-1.0, 0.0);
+        assert!(vtt
+            .fow_change_with_progress(outside, Operation::Show, false, &mut |_| {})
+            .is_err());
+    }
+
+    #[test]
+    fn vtt_fow_change_multi() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let hidden_area = |vtt: &VTT| -> i64 {
+            vtt.fog_of_war()
+                .get_rectangles()
+                .iter()
+                .map(|r| r.width() as i64 * r.height() as i64)
+                .sum()
+        };
+        let povs = [
+            Coordinate { x: 3.0, y: 3.0 },
+            Coordinate { x: 20.0, y: 12.0 },
+        ];
+        vtt.fow_hide_all();
+        for pov in &povs {
+            vtt.fow_change_multi(std::slice::from_ref(pov), Operation::Show, false, false)
+                .expect("Could not reveal a single pov");
+        }
+        let sequential = hidden_area(&vtt);
+        vtt.fow_hide_all();
+        vtt.fow_change_multi(&povs, Operation::Show, false, false)
+            .expect("Could not reveal both povs");
+        let combined = hidden_area(&vtt);
+        let error = (sequential - combined).abs() as f64 / (256.0 * 256.0);
+        assert!(
+            error < 0.5,
+            "Expected the union to reveal the same area, difference was {} squares",
+            error
+        );
+        for pov in povs {
+            assert!(vtt.is_visible(pov));
+        }
+
+        vtt.fow_hide_all();
+        let outside = [
+            Coordinate { x: 3.0, y: 3.0 },
+            Coordinate { x: 27.0, y: 1.0 },
+        ];
+        assert!(vtt
+            .fow_change_multi(&outside, Operation::Show, true, false)
+            .is_err());
+        assert_eq!(
+            vtt.fog_of_war().get_rectangles().len(),
+            1,
+            "Expected the fog of war to be unchanged after an error"
+        );
+    }
+
+    #[test]
+    fn vtt_compute_los() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let room = vtt
+            .rooms()
+            .into_iter()
+            .find(|room| (room.unsigned_area() - 15.5).abs() < 1e-6)
+            .expect("Expected example1 to contain a room of 15.5 squares");
+        let pov = room
+            .interior_point()
+            .expect("Expected the room to have an interior");
+        let los = vtt
+            .compute_los(
+                Coordinate {
+                    x: pov.x(),
+                    y: pov.y(),
+                },
+                true,
+                true,
+            )
+            .expect("Could not compute the line of sight");
+        assert!(
+            (los.unsigned_area() - 33.5).abs() < 1e-6,
+            "Expected both connected rooms of 33.5 squares, found {}",
+            los.unsigned_area()
+        );
+        let direct = vtt
+            .compute_los(
+                Coordinate {
+                    x: pov.x(),
+                    y: pov.y(),
+                },
+                false,
+                true,
+            )
+            .expect("Could not compute the line of sight");
+        assert!(direct.contains(&pov), "Expected the pov to be visible");
+        assert!(
+            direct.unsigned_area() > 0.0 && direct.unsigned_area() < 27.0 * 15.0,
+            "Expected the line of sight in grid coordinates, found {} squares",
+            direct.unsigned_area()
+        );
+        assert!(vtt
+            .compute_los(Coordinate { x: 27.0, y: 1.0 }, false, true)
+            .is_err());
+    }
+
+    #[test]
+    fn vtt_compute_los_pair() {
+        let mut vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(8.0, 4.0), 16)
+            .add_object(vec![
+                coordinate(3.0, 0.0),
+                coordinate(3.0, 4.0),
+                coordinate(4.0, 4.0),
+                coordinate(4.0, 0.0),
+                coordinate(3.0, 0.0),
+            ])
+            .build()
+            .expect("Could not build the vtt");
+        let pov = coordinate(1.0, 2.0);
+        let (blocked, through) = vtt
+            .compute_los_pair(pov.clone(), false)
+            .expect("Could not compute the line of sight");
+        assert_eq!(
+            blocked,
+            vtt.compute_los(pov.clone(), false, false).unwrap(),
+            "Expected the first polygon to be blocked by objects"
+        );
+        assert_eq!(
+            through,
+            vtt.compute_los(pov.clone(), false, true).unwrap(),
+            "Expected the second polygon to see through objects"
+        );
+        assert!(blocked.unsigned_area() < through.unsigned_area());
+        assert!(vtt.compute_los_pair(coordinate(9.0, 2.0), false).is_err());
+
+        let mut gm_layer = crate::FogOfWar::new(vtt.resolution());
+        gm_layer.update(&vtt.los_to_pixels(&through), Operation::Show);
+        vtt.fow_change(pov, Operation::Show, false)
+            .expect("Could not reveal the fog of war");
+        assert_eq!(
+            gm_layer.get_rectangles(),
+            vtt.fog_of_war().get_rectangles(),
+            "Expected the converted polygon to reveal the same area as fow_change"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_fow_layers() {
+        let mut vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(8.0, 4.0), 16)
+            .add_wall(vec![coordinate(4.0, 0.0), coordinate(4.0, 4.0)])
+            .image_from_pixbuf(Pixbuf::from_raw(128, 64, vec![200; 128 * 64 * 3]).unwrap())
+            .build()
+            .expect("Could not build the vtt");
+        vtt.fow_show_all();
+        assert!(vtt.fow_layer("players").is_none());
+        assert!(
+            !vtt.add_fow_layer("players").get_rectangles().is_empty(),
+            "Expected a new layer to be hidden"
+        );
+        let los = vtt
+            .compute_los(coordinate(1.0, 1.0), false, true)
+            .expect("Could not compute the line of sight");
+        let los = vtt.los_to_pixels(&los);
+        vtt.fow_layer_mut("players")
+            .expect("Expected the players layer")
+            .update(&los, Operation::Show);
+        assert!(
+            vtt.fog_of_war().get_rectangles().is_empty(),
+            "Expected the layer to not change the fog of war of the map"
+        );
+
+        let mut image = vtt.get_pixbuf_without_fow().unwrap();
+        vtt.apply_fow_layer(&mut image, "players")
+            .expect("Could not apply the layer");
+        assert_eq!(image.get_pixel(16, 16), Rgb([200, 200, 200]));
+        assert_eq!(image.get_pixel(100, 16), Rgb([0, 0, 0]));
+        assert!(matches!(
+            vtt.apply_fow_layer(&mut image, "gm"),
+            Err(RustVttError::UnknownFowLayer { .. })
+        ));
+
+        vtt.scale_pixels_per_grid(8)
+            .expect("Could not scale the vtt");
+        let layer = vtt
+            .fow_layer("players")
+            .expect("Expected the layer to stay");
+        assert!(!layer.is_hidden(PixelCoordinate { x: 8, y: 8 }));
+        assert!(layer.is_hidden(PixelCoordinate { x: 50, y: 8 }));
+        assert!(layer
+            .get_rectangles()
+            .iter()
+            .all(|rectangle| rectangle.bottomright.x <= 64 && rectangle.bottomright.y <= 32));
+        assert!(vtt.remove_fow_layer("players"));
+        assert!(!vtt.remove_fow_layer("players"));
+    }
+
+    #[test]
+    fn vtt_compute_los_in_range() {
+        let mut vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(20.0, 4.0), 16)
+            .add_wall(vec![coordinate(0.0, 3.0), coordinate(12.0, 3.0)])
+            .build()
+            .expect("Could not build the vtt");
+        let pov = coordinate(2.0, 1.5);
+        let unbounded = vtt.compute_los(pov.clone(), false, true).unwrap();
+        assert_eq!(
+            vtt.compute_los_in_range(pov.clone(), false, true, None)
+                .unwrap()
+                .0,
+            vec![unbounded.clone()],
+            "Expected no range to be unbounded"
+        );
+        assert_eq!(
+            vtt.compute_los_in_range(pov.clone(), false, true, Some(100.0))
+                .unwrap()
+                .0,
+            vec![unbounded.clone()],
+            "Expected a range larger than the map to be unbounded"
+        );
+
+        let mut previous = unbounded.unsigned_area();
+        for range in [12.0, 6.0, 3.0, 1.0] {
+            let area = vtt
+                .compute_los_in_range(pov.clone(), false, true, Some(range))
+                .unwrap()
+                .unsigned_area();
+            assert!(
+                area < previous,
+                "Expected a range of {} to reveal less than {}, found {}",
+                range,
+                previous,
+                area
+            );
+            previous = area;
+        }
+        assert!((previous - f64::consts::PI).abs() < 0.01);
+        assert!(matches!(
+            vtt.compute_los_in_range(pov.clone(), false, true, Some(0.0)),
+            Err(RustVttError::InvalidSightRange { .. })
+        ));
+
+        vtt.fow_hide_all();
+        vtt.fow_change_in_range(pov, Operation::Show, false, Some(3.0))
+            .expect("Could not reveal the fog of war");
+        assert!(vtt.is_visible(coordinate(4.0, 1.5)));
+        assert!(!vtt.is_visible(coordinate(6.0, 1.5)));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_apply_light_shadows() {
+        let build = |shadows: bool| {
+            VttBuilder::new(coordinate(0.0, 0.0), coordinate(4.0, 2.0), 16)
+                .add_wall(vec![coordinate(2.0, 0.0), coordinate(2.0, 2.0)])
+                .add_light(
+                    coordinate(1.0, 1.0),
+                    3.0,
+                    1.0,
+                    "ffffff".to_string(),
+                    shadows,
+                )
+                .build()
+                .expect("Could not build the vtt")
+        };
+        let mut image = Pixbuf::new(64, 32);
+        build(true).apply_light(&mut image);
+        assert_ne!(
+            image.get_pixel(24, 16),
+            Rgb([0, 0, 0]),
+            "Expected the side of the light to be lit"
+        );
+        assert_eq!(
+            image.get_pixel(40, 16),
+            Rgb([0, 0, 0]),
+            "Expected the far side of the wall to be unlit"
+        );
+        let mut image = Pixbuf::new(64, 32);
+        build(false).apply_light(&mut image);
+        assert_ne!(
+            image.get_pixel(40, 16),
+            Rgb([0, 0, 0]),
+            "Expected a light without shadows to ignore the wall"
+        );
+    }
+
+    #[test]
+    fn vtt_walls_near() {
+        let vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(20.0, 4.0), 16)
+            .add_wall(vec![coordinate(2.0, 0.0), coordinate(2.0, 2.0)])
+            .add_wall(vec![coordinate(15.0, 0.0), coordinate(15.0, 4.0)])
+            .build()
+            .expect("Could not build the vtt");
+        let near = vtt.walls_near(coordinate(1.0, 1.0), 3.0);
+        assert_eq!(
+            near,
+            vec![Line::new(
+                Coord { x: 2.0, y: 0.0 },
+                Coord { x: 2.0, y: 2.0 }
+            )],
+            "Expected only the wall within the radius"
+        );
+        let bounds = Rect::new(Coord { x: -2.0, y: -2.0 }, Coord { x: 4.0, y: 4.0 });
+        let pov = Coord { x: 1.0, y: 1.0 };
+        assert_eq!(
+            calculate_direct_los(pov, &near, bounds, 0.2),
+            calculate_direct_los(pov, vtt.wall_segments(false), bounds, 0.2),
+            "Expected a wall outside of the radius to not affect the lit area"
+        );
+        assert!(vtt.walls_near(coordinate(8.0, 2.0), 1.0).is_empty());
+    }
+
+    #[test]
+    fn vtt_is_on_wall() {
+        let vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(4.0, 4.0), 16)
+            .add_wall(vec![coordinate(2.0, 0.0), coordinate(2.0, 2.0)])
+            .add_wall(vec![coordinate(2.0, 2.0), coordinate(4.0, 2.0)])
+            .add_object(vec![coordinate(0.0, 3.0), coordinate(1.0, 3.0)])
+            .build()
+            .expect("Could not build the vtt");
+        assert!(vtt.is_on_wall(coordinate(2.0, 1.0), false));
+        assert!(!vtt.is_on_wall(coordinate(1.0, 1.0), true));
+        assert!(!vtt.is_on_wall(coordinate(0.5, 3.0), false));
+        assert!(vtt.is_on_wall(coordinate(0.5, 3.0), true));
+
+        for point in [
+            coordinate(2.0, 1.0),
+            coordinate(2.0, 2.0),
+            coordinate(0.5, 3.0),
+        ] {
+            let nudged = vtt.nudge_off_wall(point.clone());
+            assert!(
+                !vtt.is_on_wall(nudged.clone(), true),
+                "Expected {:?} to be nudged off the wall, got {:?}",
+                point,
+                nudged
+            );
+            assert!((nudged.x - point.x).hypot(nudged.y - point.y) < 1e-3);
+        }
+        let free = vtt.nudge_off_wall(coordinate(1.0, 1.0));
+        assert_eq!(
+            (free.x, free.y),
+            (1.0, 1.0),
+            "Expected a free point to stay put"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_scale_pixels_per_grid() {
+        let mut vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(4.0, 2.0), 16)
+            .add_wall(vec![coordinate(1.0, 0.0), coordinate(1.0, 2.0)])
+            .build()
+            .expect("Could not build the vtt");
+        vtt.fow_hide_all();
+        vtt.fow_change_rect(coordinate(2.0, 0.0), coordinate(4.0, 2.0), Operation::Show);
+        assert!(vtt.scale_pixels_per_grid(0).is_err());
+        vtt.scale_pixels_per_grid(8)
+            .expect("Could not scale the pixels per grid");
+        assert_eq!(vtt.pixels_per_grid(), 8);
+        assert_eq!(
+            vtt.pixel_size(),
+            PixelCoordinate { x: 32, y: 16 },
+            "Expected the pixel size to scale with the pixels per grid"
+        );
+        assert_eq!(
+            vtt.decode_image().unwrap().width(),
+            32,
+            "Expected the image to be resized"
+        );
+        assert_eq!(
+            vtt.to_pixel(Coord::from(vtt.line_of_sight()[0][0].clone())),
+            Coord { x: 8.0, y: 0.0 },
+            "Expected the wall to stay on the same square"
+        );
+        assert!(vtt.is_visible(coordinate(3.0, 1.0)));
+        assert!(
+            !vtt.is_visible(coordinate(1.0, 1.0)),
+            "Expected the fog of war to be kept"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_fow_view() {
+        let mut vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(4.0, 2.0), 16)
+            .add_wall(vec![coordinate(2.0, 0.0), coordinate(2.0, 2.0)])
+            .build()
+            .expect("Could not build the vtt");
+        vtt.fow_hide_all();
+        vtt.fow_view(&[coordinate(1.0, 1.0)], false, true)
+            .expect("Could not view from the left");
+        assert!(vtt.is_visible(coordinate(0.5, 0.5)));
+        assert!(!vtt.is_remembered(coordinate(0.5, 0.5)));
+        assert!(!vtt.is_visible(coordinate(3.5, 0.5)));
+        vtt.fow_view(&[coordinate(3.0, 1.0)], false, true)
+            .expect("Could not view from the right");
+        assert!(
+            vtt.is_remembered(coordinate(0.5, 0.5)),
+            "Expected the left side to be remembered after it left the view"
+        );
+        assert!(!vtt.is_remembered(coordinate(3.5, 0.5)));
+
+        vtt.set_remembered_opacity(128);
+        let mut image = Pixbuf::from_raw(64, 32, vec![255; 64 * 32 * 3]).unwrap();
+        vtt.apply_fow(&mut image);
+        assert_eq!(
+            image.get_pixel(8, 8),
+            Rgb([127, 127, 127]),
+            "Expected a remembered pixel to be dimmed"
+        );
+        assert_eq!(image.get_pixel(56, 8), Rgb([255, 255, 255]));
+
+        vtt.clear_view();
+        assert!(vtt.remembered_rectangles().is_empty());
+        assert!(vtt.is_visible(coordinate(0.5, 0.5)));
+    }
+
+    #[test]
+    fn vtt_nearest_visible() {
+        let mut vtt = VttBuilder::new(Coordinate::new(2.0, 2.0), Coordinate::new(8.0, 4.0), 16)
+            .build()
+            .expect("Could not build the vtt");
+        vtt.fow_hide_all();
+        assert!(vtt.nearest_visible(Coordinate::new(3.0, 3.0)).is_none());
+        vtt.fow_change_rect(
+            Coordinate::new(7.0, 2.0),
+            Coordinate::new(8.0, 3.0),
+            Operation::Show,
+        );
+        let nearest = vtt
+            .nearest_visible(Coordinate::new(3.0, 2.5))
+            .expect("Expected a revealed point");
+        assert!(vtt.is_visible(nearest.clone()));
+        assert!(
+            (nearest.x - 7.0).abs() < 0.1 && (nearest.y - 2.5).abs() < 0.1,
+            "Expected the left edge of the revealed square, found {}",
+            nearest
+        );
+    }
+
+    #[test]
+    fn vtt_reset_fow() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_hide_all();
+        vtt.resolution.pixels_per_grid = 100;
+        vtt.reset_fow();
+        assert!(
+            vtt.fog_of_war().get_rectangles().is_empty(),
+            "Expected the fog of war to be revealed after a reset"
+        );
+        vtt.fow_hide_all();
+        assert_eq!(
+            vtt.fog_of_war().get_rectangles()[0].bottomright,
+            PixelCoordinate { x: 2700, y: 1500 },
+            "Expected the fog of war to match the new resolution"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_draw_grid() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let size = vtt.pixel_size();
+        let red = Rgb([255, 0, 0]);
+        let mut image = Pixbuf::new(size.x as u32, size.y as u32);
+        vtt.draw_grid(&mut image, red, 2, 1);
+        assert_eq!(image.get_pixel(255, 100), red);
+        assert_eq!(image.get_pixel(256, 100), red);
+        assert_eq!(image.get_pixel(257, 100), Rgb([0, 0, 0]));
+        assert_eq!(image.get_pixel(100, 100), Rgb([0, 0, 0]));
+        assert_eq!(image.get_pixel(26 * 256, 100), red, "Expected no drift");
+        assert_eq!(image.get_pixel(100, 14 * 256 - 1), red);
+
+        let mut image = Pixbuf::new(size.x as u32, size.y as u32);
+        vtt.draw_grid(&mut image, red, 2, 5);
+        assert_eq!(image.get_pixel(256, 100), Rgb([0, 0, 0]));
+        assert_eq!(
+            image.get_pixel(5 * 256, 100),
+            red,
+            "Expected a major grid line"
+        );
+    }
+
+    #[test]
+    fn vtt_coordinate_constructors() {
+        let coordinate: Coordinate = (4.0, 7.5).into();
+        assert_eq!((coordinate.x, coordinate.y), (4.0, 7.5));
+        let coordinate = Coordinate::new(-1.0, 2.0);
+        assert_eq!((coordinate.x, coordinate.y), (-1.0, 2.0));
+        assert_eq!(coordinate.to_string(), "(-1, 2)");
+        let default = Coordinate::default();
+        assert_eq!((default.x, default.y), (0.0, 0.0));
+
+        let pixel: PixelCoordinate = (3, -4).into();
+        assert_eq!(pixel, PixelCoordinate::new(3, -4));
+        assert_eq!(pixel.to_string(), "(3, -4)");
+        assert_eq!(PixelCoordinate::default(), PixelCoordinate::new(0, 0));
+
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_change((4.0, 7.0).into(), Operation::Show, false)
+            .expect("Expected a tuple to be usable as a pov");
+    }
+
+    #[test]
+    fn vtt_pixel_grid_conversion() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.resolution.map_origin = Coordinate { x: 2.0, y: 1.0 };
+        assert_eq!(
+            vtt.grid_to_pixel(Coordinate { x: 2.0, y: 1.0 }),
+            PixelCoordinate { x: 0, y: 0 },
+            "Expected the origin to be the top left pixel"
+        );
+        assert_eq!(
+            vtt.grid_to_pixel(Coordinate { x: 3.5, y: 1.25 }),
+            PixelCoordinate { x: 384, y: 64 }
+        );
+        for pixel in [
+            PixelCoordinate { x: 0, y: 0 },
+            PixelCoordinate { x: 383, y: 1000 },
+            PixelCoordinate { x: 6911, y: 3839 },
+        ] {
+            assert_eq!(vtt.grid_to_pixel(vtt.pixel_to_grid(pixel)), pixel);
+        }
+        let coordinate = Coordinate::from_pixel(PixelCoordinate { x: 383, y: 0 }, 256);
+        assert_eq!((coordinate.x, coordinate.y), (383.5 / 256.0, 0.5 / 256.0));
+    }
+
+    #[test]
+    fn vtt_fow_nonzero_origin() {
+        let mut vtt = VttBuilder::new(coordinate(3.0, 2.0), coordinate(4.0, 2.0), 16)
+            .build()
+            .expect("Could not build the vtt");
+        vtt.fow_hide_all();
+        assert_eq!(
+            vtt.fog_of_war().get_rectangles()[0].topleft,
+            PixelCoordinate { x: 0, y: 0 },
+            "Expected the fog of war to start at the top left pixel"
+        );
+        vtt.fow_change_rect(coordinate(3.0, 2.0), coordinate(4.0, 3.0), Operation::Show);
+        let fog = vtt.fog_of_war();
+        assert!(!fog.is_hidden(PixelCoordinate { x: 0, y: 0 }));
+        assert!(!fog.is_hidden(PixelCoordinate { x: 15, y: 15 }));
+        assert!(fog.is_hidden(PixelCoordinate { x: 16, y: 16 }));
+        assert!(vtt.is_visible(coordinate(3.5, 2.5)));
+        assert!(!vtt.is_visible(coordinate(0.5, 0.5)));
+    }
+
+    #[test]
+    fn vtt_edit_objects_los() {
+        let mut vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(6.0, 3.0), 16)
+            .build()
+            .expect("Could not build the vtt");
+        let pov = [coordinate(1.0, 1.5)];
+        let behind = coordinate(5.0, 1.5);
+        vtt.fow_hide_all();
+        vtt.fow_change_multi(&pov, Operation::Show, false, false)
+            .expect("Could not reveal the line of sight");
+        assert!(vtt.is_visible(behind.clone()));
+
+        vtt.add_object_los(vec![coordinate(3.0, 0.5), coordinate(3.0, 2.5)]);
+        vtt.fow_hide_all();
+        vtt.fow_change_multi(&pov, Operation::Show, false, false)
+            .expect("Could not reveal the line of sight");
+        assert!(
+            !vtt.is_visible(behind.clone()),
+            "Expected the new object to block vision"
+        );
+        vtt.fow_change_multi(&pov, Operation::Show, false, true)
+            .expect("Could not reveal the line of sight");
+        assert!(vtt.is_visible(behind.clone()));
+
+        let path = temp_path("objects_los.dd2vtt");
+        vtt.save_vtt(&path).expect("Could not save the vtt");
+        let mut reopened = open_vtt(&path).expect("Could not open the saved vtt");
+        assert_eq!(reopened.objects_los().len(), 1, "Object was not saved");
+        reopened.clear_objects_los();
+        reopened.fow_hide_all();
+        reopened
+            .fow_change_multi(&pov, Operation::Show, false, false)
+            .expect("Could not reveal the line of sight");
+        assert!(reopened.is_visible(behind));
+    }
+
+    #[test]
+    fn vtt_portal_blocks_vision() {
+        let mut vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(4.0, 2.0), 16)
+            .add_wall(vec![coordinate(2.0, 0.0), coordinate(2.0, 0.5)])
+            .add_wall(vec![coordinate(2.0, 1.5), coordinate(2.0, 2.0)])
+            .add_portal(coordinate(2.0, 0.5), coordinate(2.0, 1.5), false, false)
+            .build()
+            .expect("Could not build the vtt");
+        let behind = coordinate(3.0, 1.0);
+        let sees_behind = |vtt: &mut VTT| -> bool {
+            vtt.fow_hide_all();
+            vtt.fow_show(coordinate(1.0, 1.0), false)
+                .expect("Could not reveal the line of sight");
+            vtt.is_visible(behind.clone())
+        };
+        assert!(
+            sees_behind(&mut vtt),
+            "Expected to see through the open door"
+        );
+        assert!(vtt.set_portal_blocks_vision(0, true));
+        assert!(vtt.portals().next().unwrap().always_blocks_vision);
+        assert!(
+            !sees_behind(&mut vtt),
+            "Expected the open portcullis to block vision"
+        );
+        assert!(vtt.set_portal_blocks_vision(0, false));
+        assert!(sees_behind(&mut vtt));
+        assert!(!vtt.set_portal_blocks_vision(1, true));
+    }
+
+    #[test]
+    fn vtt_portal_one_way() {
+        // a room on the left with a secret door in its right wall
+        let mut vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(8.0, 4.0), 16)
+            .add_wall(vec![
+                coordinate(4.0, 1.5),
+                coordinate(4.0, 0.5),
+                coordinate(1.0, 0.5),
+                coordinate(1.0, 3.5),
+                coordinate(4.0, 3.5),
+                coordinate(4.0, 2.5),
+            ])
+            .add_portal(coordinate(4.0, 1.5), coordinate(4.0, 2.5), true, false)
+            .build()
+            .expect("Could not build the vtt");
+        let inside = coordinate(2.0, 2.0);
+        let outside = coordinate(6.0, 2.0);
+        assert!(!vtt.set_portal_one_way(1, Some(outside.clone())));
+        assert!(!vtt.set_portal_one_way(0, Some(coordinate(4.0, 3.0))));
+        assert!(vtt.set_portal_one_way(0, Some(outside.clone())));
+        assert!(vtt.portals().next().unwrap().one_way);
+
+        for around_walls in [false, true] {
+            let los = vtt.compute_los(inside.clone(), around_walls, true).unwrap();
+            assert!(
+                los.contains(&Coord { x: 5.0, y: 2.0 }),
+                "Expected to see through the secret door from inside (around walls: {})",
+                around_walls
+            );
+            let los = vtt
+                .compute_los(outside.clone(), around_walls, true)
+                .unwrap();
+            assert!(
+                !los.contains(&Coord { x: 3.0, y: 2.0 }),
+                "Expected the secret door to block vision from outside (around walls: {})",
+                around_walls
+            );
+        }
+        // an open one way door does not block vision from either side
+        vtt.set_door_state(0, false);
+        let los = vtt.compute_los(outside.clone(), false, true).unwrap();
+        assert!(los.contains(&Coord { x: 3.0, y: 2.0 }));
+        vtt.set_door_state(0, true);
+        assert!(vtt.set_portal_one_way(0, None));
+        let los = vtt.compute_los(inside, false, true).unwrap();
+        assert!(!los.contains(&Coord { x: 5.0, y: 2.0 }));
+    }
+
+    #[test]
+    fn vtt_los_orientation() {
+        let vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(8.0, 8.0), 16)
+            .add_wall(vec![
+                coordinate(2.0, 2.0),
+                coordinate(6.0, 2.0),
+                coordinate(6.0, 6.0),
+                coordinate(2.0, 6.0),
+                coordinate(2.0, 2.0),
+            ])
+            .add_wall(vec![coordinate(4.0, 0.0), coordinate(4.0, 8.0)])
+            .build()
+            .expect("Could not build the vtt");
+        let povs = [
+            coordinate(0.01, 0.01),
+            coordinate(7.99, 0.5),
+            coordinate(7.5, 7.99),
+            coordinate(0.5, 7.5),
+            coordinate(3.0, 3.0),
+            coordinate(5.0, 5.0),
+        ];
+        for pov in povs {
+            for around_walls in [false, true] {
+                let los = vtt.compute_los(pov.clone(), around_walls, true).unwrap();
+                assert!(
+                    los.exterior().is_ccw(),
+                    "Expected the line of sight from {} to be counter-clockwise (around walls: {})",
+                    pov,
+                    around_walls
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn vtt_vision_config() {
+        // a wall to the right, an object below and a closed door above the pov
+        let vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(8.0, 8.0), 16)
+            .add_wall(vec![coordinate(6.0, 0.0), coordinate(6.0, 8.0)])
+            .add_object(vec![coordinate(0.0, 6.0), coordinate(6.0, 6.0)])
+            .add_portal(coordinate(0.0, 2.0), coordinate(6.0, 2.0), true, false)
+            .build()
+            .expect("Could not build the vtt");
+        let pov = coordinate(3.0, 4.0);
+        let behind_wall = Coord { x: 7.0, y: 4.0 };
+        let behind_object = Coord { x: 3.0, y: 7.0 };
+        let behind_door = Coord { x: 3.0, y: 1.0 };
+
+        let default = VisionConfig::default();
+        let los = vtt.compute_los_with_config(pov.clone(), &default).unwrap();
+        assert_eq!(los, vtt.compute_los(pov.clone(), false, true).unwrap());
+        assert!(!los.contains(&behind_wall) && !los.contains(&behind_door));
+        assert!(los.contains(&behind_object));
+
+        let config = VisionConfig {
+            blocked_by_walls: false,
+            ..default
+        };
+        let los = vtt.compute_los_with_config(pov.clone(), &config).unwrap();
+        assert!(
+            los.contains(&behind_wall),
+            "Expected to see through the wall"
+        );
+        assert!(!los.contains(&behind_door));
+
+        let config = VisionConfig {
+            blocked_by_objects: true,
+            blocked_by_closed_doors: false,
+            ..default
+        };
+        let los = vtt.compute_los_with_config(pov.clone(), &config).unwrap();
+        assert!(
+            !los.contains(&behind_object),
+            "Expected the object to block"
+        );
+        assert!(
+            los.contains(&behind_door),
+            "Expected to see through the door"
+        );
+        assert!(!los.contains(&behind_wall));
+
+        let config = VisionConfig {
+            around_corners: true,
+            ..default
+        };
+        assert_eq!(
+            vtt.compute_los_with_config(pov.clone(), &config).unwrap(),
+            vtt.compute_los(pov.clone(), true, true).unwrap()
+        );
+
+        let mut vtt = vtt;
+        vtt.fow_hide_all();
+        let config = VisionConfig {
+            blocked_by_closed_doors: false,
+            ..default
+        };
+        vtt.fow_change_with_config(pov.clone(), Operation::Show, &config)
+            .unwrap();
+        assert!(vtt.is_visible(coordinate(3.0, 1.0)));
+        assert!(!vtt.is_visible(coordinate(7.0, 4.0)));
+        assert!(vtt
+            .fow_change_with_config(coordinate(9.0, 1.0), Operation::Show, &config)
+            .is_err());
+    }
+
+    #[test]
+    fn vtt_debug_svg() {
+        let vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(4.0, 2.0), 16)
+            .add_wall(vec![coordinate(2.0, 0.0), coordinate(2.0, 2.0)])
+            .add_object(vec![coordinate(0.5, 1.5), coordinate(1.5, 1.5)])
+            .add_portal(coordinate(3.0, 0.0), coordinate(3.0, 1.0), false, false)
+            .build()
+            .expect("Could not build the vtt");
+        let svg = vtt
+            .debug_svg(coordinate(1.0, 1.0), false, true)
+            .expect("Could not draw the svg");
+        assert!(svg.starts_with("<svg ") && svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains("viewBox=\"0 0 64 32\""));
+        assert!(svg
+            .contains("<line x1=\"32.00\" y1=\"0.00\" x2=\"32.00\" y2=\"32.00\" stroke=\"black\""));
+        assert!(svg.contains("stroke=\"saddlebrown\"") && svg.contains("stroke=\"green\""));
+        assert!(svg.contains("<circle cx=\"16.00\" cy=\"16.00\""));
+        assert_eq!(
+            svg.matches("<path ").count(),
+            1,
+            "Expected one line of sight"
+        );
+        assert!(vtt.debug_svg(coordinate(5.0, 1.0), false, true).is_err());
+    }
+
+    #[test]
+    fn vtt_wide_door() {
+        let mut vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(8.0, 4.0), 16)
+            .add_portal(coordinate(1.0, 2.0), coordinate(4.0, 2.0), true, false)
+            .build()
+            .expect("Could not build the vtt");
+        assert_eq!(
+            vtt.toggle_door(coordinate(1.0, 2.2)),
+            Some(false),
+            "Expected a click at the end of a wide door to open it"
+        );
+        assert_eq!(vtt.toggle_door(coordinate(4.5, 2.5)), Some(true));
+        assert_eq!(
+            vtt.toggle_door(coordinate(6.0, 2.0)),
+            None,
+            "Expected a click more than a square from the door to miss it"
+        );
+    }
+
+    #[test]
+    fn vtt_visible_portals() {
+        // two rooms side by side, with a door between them and a door at the far end of the
+        // second room
+        let vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(12.0, 4.0), 16)
+            .add_wall(vec![coordinate(4.0, 0.0), coordinate(4.0, 1.5)])
+            .add_wall(vec![coordinate(4.0, 2.5), coordinate(4.0, 4.0)])
+            .add_wall(vec![coordinate(8.0, 0.0), coordinate(8.0, 1.5)])
+            .add_wall(vec![coordinate(8.0, 2.5), coordinate(8.0, 4.0)])
+            .add_portal(coordinate(4.0, 1.5), coordinate(4.0, 2.5), false, false)
+            .add_portal(coordinate(8.0, 1.5), coordinate(8.0, 2.5), true, false)
+            .add_portal(coordinate(10.0, 0.5), coordinate(11.0, 0.5), false, true)
+            .build()
+            .expect("Could not build the vtt");
+        let visible = vtt
+            .visible_portals(coordinate(1.0, 2.0), false, true)
+            .expect("Could not compute the visible doors");
+        assert_eq!(
+            visible,
+            vec![0, 1],
+            "Expected the open door and the closed door behind it"
+        );
+        let visible = vtt
+            .visible_portals(coordinate(1.0, 0.5), false, true)
+            .expect("Could not compute the visible doors");
+        assert_eq!(
+            visible,
+            vec![0],
+            "Expected the closed door to be out of view"
+        );
+        assert!(vtt
+            .visible_portals(coordinate(13.0, 2.0), false, true)
+            .is_err());
+    }
+
+    #[test]
+    fn vtt_doors() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        // the first door of example1 is at (15, 7), the end of the second door at (16, 8) is
+        // within a square as well
+        assert_eq!(
+            vtt.doors_within_square(Coordinate { x: 15.2, y: 7.5 }),
+            vec![0, 1],
+            "Expected to find the first door before the second door"
+        );
+        assert!(
+            vtt.doors_within_square(Coordinate { x: 0.0, y: 0.0 })
+                .is_empty(),
+            "Expected no doors near the corner of the map"
+        );
+        assert!(vtt.open_door(Coordinate { x: 15.0, y: 7.0 }));
+        assert!(!vtt.portal_segments()[0].1, "Expected the door to be open");
+        assert!(vtt.set_door_state(0, true));
+        assert!(vtt.portal_segments()[0].1, "Expected the door to be closed");
+        assert!(!vtt.set_door_state(3, true), "Expected no door at index 3");
+        assert!(!vtt.close_door(Coordinate { x: 0.0, y: 0.0 }));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_decoded_image_cache() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let first: *const Pixbuf = vtt.decode_image().expect("Could not decode the image");
+        for _ in 0..10 {
+            let image = vtt
+                .decode_image()
+                .expect("Expected the decoded image to be cached");
+            assert!(
+                std::ptr::eq(image, first),
+                "Expected the cached image to be returned without decoding or copying it"
+            );
+        }
+        let size = vtt.pixel_size();
+        let mut other = Pixbuf::new(size.x as u32, size.y as u32);
+        other.put_pixel(5, 6, Rgb([1, 2, 3]));
+        vtt.set_image_base64(BASE64_STANDARD.encode(crate::png::encode_png(&other)))
+            .expect("Could not set the base64 image");
+        assert_eq!(
+            vtt.decode_image()
+                .expect("Could not decode the image")
+                .get_pixel(5, 6),
+            Rgb([1, 2, 3]),
+            "Expected setting the image to replace the cached image"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_set_image() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_hide_all();
+        let size = vtt.pixel_size();
+        let mut image = Pixbuf::new(size.x as u32, size.y as u32);
+        image.put_pixel(3, 4, Rgb([10, 20, 30]));
+        vtt.set_image_from_pixbuf(&image)
+            .expect("Could not set the image");
+        assert_eq!(
+            vtt.get_pixbuf_without_fow()
+                .expect("Could not decode the image")
+                .get_pixel(3, 4),
+            Rgb([10, 20, 30]),
+            "Expected the new image to be used"
+        );
+        assert!(
+            !vtt.is_visible(Coordinate { x: 1.0, y: 1.0 }),
+            "Expected the fog of war to be kept"
+        );
+
+        let mut other = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        other
+            .get_pixbuf_without_fow()
+            .expect("Could not decode the image");
+        other
+            .set_image_base64(vtt.image.clone())
+            .expect("Could not set the base64 image");
+        assert_eq!(
+            other
+                .get_pixbuf_without_fow()
+                .expect("Could not decode the image")
+                .get_pixel(3, 4),
+            Rgb([10, 20, 30]),
+            "Expected setting the image to invalidate the decoded image"
+        );
+
+        let small = Pixbuf::new(16, 16);
+        assert!(matches!(
+            vtt.set_image_from_pixbuf(&small),
+            Err(RustVttError::ImageSizeMismatch { .. })
+        ));
+        assert!(matches!(
+            vtt.set_image_base64("not base64".to_string()),
+            Err(RustVttError::ImageDecode { .. })
+        ));
+        assert!(
+            matches!(
+                vtt.set_image_base64(format!("{}!!!!", other.image)),
+                Err(RustVttError::ImageDecode { .. })
+            ),
+            "Expected invalid base64 after the header to be rejected"
+        );
+
+        let mut jpeg = open_vtt("tests/resources/example2.dd2vtt")
+            .expect("Could not open file example2.dd2vtt");
+        let image = jpeg.image.clone();
+        jpeg.set_image_base64(image)
+            .expect("Could not set a base64 JPEG image");
+        assert_eq!(jpeg.image_format().unwrap(), Some(ImageFormat::Jpeg));
+    }
+
+    #[test]
+    fn vtt_debug_los_rays() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let pov = Coordinate { x: 3.0, y: 3.0 };
+        let rays = vtt.debug_los_rays(pov.clone(), true);
+        let los = vtt
+            .compute_los(pov, false, false)
+            .expect("Could not compute the line of sight");
+        assert_eq!(
+            rays.len() + 1,
+            los.exterior().0.len(),
+            "Expected one ray for every point of the line of sight"
+        );
+        for (ray, point) in rays.iter().zip(los.exterior().coords()) {
+            assert_eq!(ray.start, Coord { x: 3.0, y: 3.0 });
+            assert_eq!(
+                ray.end, *point,
+                "Expected the ray to end at the line of sight"
+            );
+        }
+    }
+
+    #[test]
+    fn vtt_fow_change_path() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let hidden_area = |vtt: &VTT| -> i64 {
+            vtt.fog_of_war()
+                .get_rectangles()
+                .iter()
+                .map(|r| r.width() as i64 * r.height() as i64)
+                .sum()
+        };
+        vtt.fow_hide_all();
+        let waypoints = [
+            Coordinate { x: 1.0, y: 3.0 },
+            Coordinate { x: 5.0, y: 3.0 },
+            Coordinate { x: 30.0, y: 3.0 },
+        ];
+        vtt.fow_change_path(&waypoints, Operation::Show, false, true)
+            .expect("Could not reveal the path");
+        for x in [1.0, 2.5, 4.0, 5.0] {
+            assert!(
+                vtt.is_visible(Coordinate { x, y: 3.0 }),
+                "Expected ({}, 3) on the path to be visible",
+                x
+            );
+        }
+        let mut endpoint = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        endpoint.fow_hide_all();
+        endpoint
+            .fow_change(Coordinate { x: 5.0, y: 3.0 }, Operation::Show, false)
+            .expect("Could not reveal the endpoint");
+        assert!(
+            hidden_area(&vtt) < hidden_area(&endpoint),
+            "Expected the path to reveal more than its endpoint"
+        );
+        assert!(vtt
+            .fow_change_path(&[], Operation::Hide, true, true)
+            .is_ok());
+    }
+
+    #[test]
+    fn vtt_portals() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let portals: Vec<PortalInfo> = vtt.portals().collect();
+        assert_eq!(portals.len(), vtt.portal_segments().len());
+        // the first door of example1 is at (15, 7)
+        let door = portals[0];
+        assert!((door.position.x - 15.0).abs() < 0.5 && (door.position.y - 7.0).abs() < 1.0);
+        let bounds = door.bounds.expect("Expected the door to have two bounds");
+        assert!(
+            (bounds.dy().atan2(bounds.dx()) - door.rotation).sin().abs() < 1e-3,
+            "Expected the rotation to follow the bounds"
+        );
+        assert_eq!(
+            portals
+                .iter()
+                .map(|portal| portal.closed)
+                .collect::<Vec<bool>>(),
+            vtt.portal_segments()
+                .iter()
+                .map(|(_, closed)| *closed)
+                .collect::<Vec<bool>>()
+        );
+    }
+
+    #[test]
+    fn vtt_normalize_colors() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.lights[0].color = "#4DD569".to_string();
+        vtt.environment.ambient_light = Some("515B6A".to_string());
+        vtt.normalize_colors()
+            .expect("Could not normalize the colors");
+        assert_eq!(vtt.lights()[0].color(), "ff4dd569");
+        assert_eq!(vtt.ambient_light(), Some("ff515b6a"));
+
+        vtt.lights[1].color = "torch".to_string();
+        vtt.environment.ambient_light = Some("#FFFFFF".to_string());
+        assert!(matches!(
+            vtt.normalize_colors(),
+            Err(RustVttError::InvalidLightColor { index: 1, .. })
+        ));
+        assert_eq!(
+            vtt.ambient_light(),
+            Some("#FFFFFF"),
+            "Expected no color to change after an error"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_crop() {
+        let vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let cropped = vtt
+            .crop(
+                Coordinate { x: 10.5, y: 12.0 },
+                Coordinate { x: 19.2, y: 5.0 },
+            )
+            .expect("Could not crop the vtt");
+        assert_eq!((cropped.origin().x, cropped.origin().y), (10.0, 5.0));
+        assert_eq!((cropped.size().x, cropped.size().y), (10.0, 7.0));
+        let image = cropped
+            .decode_image()
+            .expect("Could not decode the cropped image");
+        assert_eq!((image.width(), image.height()), (2560, 1792));
+        let original = vtt.decode_image().expect("Could not decode the image");
+        assert_eq!(
+            image.get_pixel(100, 200),
+            original.get_pixel(2660, 1480),
+            "Expected the image to be cropped at the new origin"
+        );
+        let inside = |coordinate: &Coordinate| {
+            (10.0..=20.0).contains(&coordinate.x) && (5.0..=12.0).contains(&coordinate.y)
+        };
+        assert!(!cropped.line_of_sight.is_empty());
+        assert!(
+            cropped.line_of_sight.iter().flatten().all(inside),
+            "Expected all walls to be clipped to the new map"
+        );
+        assert!(cropped.portals().all(|portal| portal
+            .bounds
+            .is_some_and(|line| { inside(&line.start.into()) && inside(&line.end.into()) })));
+        assert!(cropped
+            .lights()
+            .iter()
+            .all(|light| inside(light.position())));
+
+        let path = temp_path("crop.dd2vtt");
+        cropped.save_vtt(&path).expect("Could not save the vtt");
+        let reopened = open_vtt(&path).expect("Could not open the cropped vtt");
+        assert_eq!(reopened.pixel_size(), cropped.pixel_size());
+        assert!(vtt
+            .crop(
+                Coordinate { x: 30.0, y: 1.0 },
+                Coordinate { x: 40.0, y: 5.0 }
+            )
+            .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_append() {
+        let tile = |origin: Coordinate, width: f64, value: u8| {
+            let pixels = (width as usize * 16) * 64 * 3;
+            return VttBuilder::new(origin, Coordinate::new(width, 4.0), 16)
+                .image_from_pixbuf(
+                    Pixbuf::from_raw(width as u32 * 16, 64, vec![value; pixels]).unwrap(),
+                )
+                .add_wall(vec![Coordinate::new(1.0, 0.0), Coordinate::new(1.0, 2.0)])
+                .add_light(
+                    Coordinate::new(0.5, 0.5),
+                    2.0,
+                    1.0,
+                    "ffffffff".to_string(),
+                    true,
+                )
+                .add_portal(
+                    Coordinate::new(0.0, 3.0),
+                    Coordinate::new(1.0, 3.0),
+                    true,
+                    false,
+                )
+                .build()
+                .expect("Could not build the vtt");
+        };
+        let mut vtt = tile(Coordinate::new(0.0, 0.0), 4.0, 50);
+        let mut other = tile(Coordinate::new(0.0, 0.0), 2.0, 200);
+        other.fow_hide_all();
+        vtt.fow_view(&[Coordinate::new(3.5, 3.5)], false, true)
+            .expect("Could not view the map");
+        vtt.fow_view(&[Coordinate::new(0.5, 0.5)], false, true)
+            .expect("Could not view the map");
+        assert!(vtt.is_remembered(Coordinate::new(3.5, 3.5)));
+        vtt.add_fow_layer("players").show_all();
+
+        vtt.append_right(&other).expect("Could not append the map");
+        assert_eq!((vtt.size().x, vtt.size().y), (6.0, 4.0));
+        assert_eq!(vtt.pixel_size(), PixelCoordinate::new(96, 64));
+        let image = vtt.decode_image().unwrap();
+        assert_eq!(image.get_pixel(63, 10), Rgb([50, 50, 50]));
+        assert_eq!(image.get_pixel(64, 10), Rgb([200, 200, 200]));
+        assert_eq!(
+            vtt.line_of_sight()[1][0].x,
+            5.0,
+            "Expected the walls of the appended map to be moved"
+        );
+        assert_eq!(vtt.lights()[1].position().x, 4.5);
+        assert_eq!(
+            vtt.portals().nth(1).map(|portal| portal.position),
+            Some(Coord { x: 4.5, y: 3.0 })
+        );
+        assert!(vtt.is_visible(Coordinate::new(3.5, 3.5)));
+        assert!(
+            !vtt.is_visible(Coordinate::new(4.5, 3.5)),
+            "Expected the fog of war of the appended map to be kept"
+        );
+        assert!(
+            vtt.is_remembered(Coordinate::new(3.5, 3.5)),
+            "Expected the remembered area to be kept"
+        );
+        let layer = vtt
+            .fow_layer("players")
+            .expect("Expected the layer to be kept");
+        assert!(!layer.is_hidden(PixelCoordinate::new(8, 8)));
+        assert!(
+            layer.is_hidden(PixelCoordinate::new(72, 8)),
+            "Expected the part of the layer on the appended map to be hidden"
+        );
+
+        let below = tile(Coordinate::new(1.0, 1.0), 6.0, 100);
+        vtt.append_below(&below)
+            .expect("Could not append the map below");
+        assert_eq!((vtt.size().x, vtt.size().y), (6.0, 8.0));
+        assert_eq!(
+            vtt.decode_image().unwrap().get_pixel(70, 70),
+            Rgb([100, 100, 100])
+        );
+        // the tile starts at (1, 1), so its walls move by (-1, 3)
+        assert_eq!(vtt.line_of_sight()[2][0].x, 0.0);
+        assert_eq!(vtt.line_of_sight()[2][0].y, 3.0);
+
+        let narrow = tile(Coordinate::new(0.0, 0.0), 2.0, 0);
+        let error = vtt.append_below(&narrow).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<RustVttError>(),
+            Some(RustVttError::EdgeMismatch { .. })
+        ));
+        let coarse = VttBuilder::new(Coordinate::new(0.0, 0.0), Coordinate::new(2.0, 8.0), 8)
+            .build()
+            .unwrap();
+        let error = vtt.append_right(&coarse).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<RustVttError>(),
+            Some(RustVttError::PixelsPerGridMismatch { .. })
+        ));
+        assert_eq!(
+            vtt.size().x,
+            6.0,
+            "Expected a failed append to not change the map"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_rotate() {
+        let mut image = Pixbuf::new(96, 64);
+        image.put_pixel(0, 0, Rgb([255, 0, 0]));
+        let mut vtt = VttBuilder::new(Coordinate::new(1.0, 1.0), Coordinate::new(6.0, 4.0), 16)
+            .image_from_pixbuf(image)
+            .add_wall(vec![Coordinate::new(1.0, 2.0), Coordinate::new(1.0, 4.0)])
+            .add_portal(
+                Coordinate::new(2.0, 1.0),
+                Coordinate::new(3.0, 1.0),
+                true,
+                false,
+            )
+            .add_light(
+                Coordinate::new(1.5, 1.5),
+                2.0,
+                1.0,
+                "ffffffff".to_string(),
+                true,
+            )
+            .build()
+            .expect("Could not build the vtt");
+        vtt.fow_change_rect(
+            Coordinate::new(1.0, 1.0),
+            Coordinate::new(2.0, 2.0),
+            Operation::Hide,
+        );
+        vtt.add_fow_layer("players").update_rect(
+            FoWRectangle {
+                topleft: PixelCoordinate::new(0, 0),
+                bottomright: PixelCoordinate::new(16, 16),
+            },
+            Operation::Show,
+        );
+
+        vtt.rotate(1).expect("Could not rotate the vtt");
+        assert_eq!((vtt.size().x, vtt.size().y), (4.0, 6.0));
+        assert_eq!((vtt.origin().x, vtt.origin().y), (1.0, 1.0));
+        assert_eq!(vtt.pixel_size(), PixelCoordinate::new(64, 96));
+        // the wall on the left edge of the map ends up on the top edge
+        let wall: Vec<(f64, f64)> = vtt.line_of_sight()[0]
+            .iter()
+            .map(|point| (point.x, point.y))
+            .collect();
+        assert_eq!(wall, vec![(4.0, 1.0), (2.0, 1.0)]);
+        let portal = vtt.portals().next().unwrap();
+        assert_eq!(portal.position, Coord { x: 5.0, y: 2.5 });
+        assert!((portal.rotation - f64::consts::FRAC_PI_2).abs() < 1e-9);
+        let light = vtt.lights()[0].position();
+        assert_eq!((light.x, light.y), (4.5, 1.5));
+        assert_eq!(
+            vtt.decode_image().unwrap().get_pixel(63, 0),
+            Rgb([255, 0, 0]),
+            "Expected the top left pixel to move to the top right"
+        );
+        assert!(!vtt.is_visible(Coordinate::new(4.5, 1.5)));
+        assert!(vtt.is_visible(Coordinate::new(1.5, 1.5)));
+        let layer = vtt.fow_layer("players").unwrap();
+        assert!(
+            !layer.is_hidden(PixelCoordinate::new(56, 8)),
+            "Expected the fog of war layers to be rotated"
+        );
+        assert!(layer.is_hidden(PixelCoordinate::new(8, 8)));
+
+        let path = temp_path("rotate.dd2vtt");
+        vtt.save_vtt(&path).expect("Could not save the vtt");
+        let mut reopened = open_vtt(&path).expect("Could not open the rotated vtt");
+        assert_eq!(reopened.pixel_size(), PixelCoordinate::new(64, 96));
+        reopened.rotate(3).expect("Could not rotate the vtt back");
+        let wall: Vec<(f64, f64)> = reopened.line_of_sight()[0]
+            .iter()
+            .map(|point| (point.x, point.y))
+            .collect();
+        assert_eq!(
+            wall,
+            vec![(1.0, 2.0), (1.0, 4.0)],
+            "Expected a full turn to change nothing"
+        );
+        assert_eq!(
+            reopened.decode_image().unwrap().get_pixel(0, 0),
+            Rgb([255, 0, 0])
+        );
+    }
+
+    #[test]
+    fn vtt_eq() {
+        let original = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        assert!(vtt == original, "Expected the same file to be equal");
+        vtt.fow_view(&[Coordinate::new(4.0, 7.0)], false, true)
+            .expect("Could not set the view");
+        vtt.fow_show_all();
+        vtt.add_fow_layer("players");
+        assert!(vtt == original, "Expected the layers to be ignored");
+
+        vtt.fow_change_rect(
+            Coordinate::new(0.0, 0.0),
+            Coordinate::new(3.0, 3.0),
+            Operation::Hide,
+        );
+        assert!(vtt != original, "Expected the fog of war to be compared");
+        vtt.fow_show_all();
+        assert!(vtt == original);
+
+        vtt.set_door_state(0, false);
+        assert!(vtt != original, "Expected the doors to be compared");
+        vtt.set_door_state(0, original.portals().next().unwrap().closed);
+        assert!(vtt == original);
+        vtt.add_wall(vec![Coordinate::new(1.0, 1.0), Coordinate::new(2.0, 1.0)]);
+        assert!(vtt != original, "Expected the walls to be compared");
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_fow_edge_blur() {
+        let mut vtt = VttBuilder::new(Coordinate::new(0.0, 0.0), Coordinate::new(4.0, 4.0), 16)
+            .image_from_pixbuf(Pixbuf::from_raw(64, 64, vec![200; 64 * 64 * 3]).unwrap())
+            .build()
+            .expect("Could not build the vtt");
+        vtt.fow_change_rect(
+            Coordinate::new(2.0, 0.0),
+            Coordinate::new(4.0, 4.0),
+            Operation::Hide,
+        );
+        let hard = vtt.get_pixbuf().unwrap();
+        assert_eq!(vtt.fow_edge_blur(), 0);
+        vtt.set_fow_edge_blur(4);
+        let soft = vtt.get_pixbuf().unwrap();
+        for x in [0, 20, 27, 37, 63] {
+            assert_eq!(
+                soft.get_pixel(x, 30),
+                hard.get_pixel(x, 30),
+                "Expected pixel {} away from the edge to be untouched",
+                x
+            );
+        }
+        let edge: Vec<u8> = (28..36).map(|x| soft.get_pixel(x, 30).0[0]).collect();
+        assert!(
+            edge.windows(2).all(|pair| pair[0] > pair[1]),
+            "Expected the edge to fade to black, found {:?}",
+            edge
+        );
+        assert!(edge[0] < 200 && edge[7] > 0);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_thumbnail() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let thumbnail = vtt
+            .thumbnail(270, false)
+            .expect("Could not create the thumbnail");
+        assert_eq!((thumbnail.width(), thumbnail.height()), (270, 150));
+        assert!(
+            vtt.decoded_image.get().is_none(),
+            "Expected the full image not to be kept"
+        );
+        // hide the left half of the map
+        vtt.fow_change_rect(
+            Coordinate { x: 0.0, y: 0.0 },
+            Coordinate { x: 13.5, y: 15.0 },
+            Operation::Hide,
+        );
+        let thumbnail = vtt
+            .thumbnail(270, true)
+            .expect("Could not create the thumbnail");
+        for y in 0..150 {
+            assert_eq!(thumbnail.get_pixel(134, y), Rgb([0, 0, 0]));
+        }
+        assert!(
+            (135..270).any(|x| thumbnail.get_pixel(x, 75) != Rgb([0, 0, 0])),
+            "Expected the revealed half not to be covered"
+        );
+    }
+
+    #[test]
+    fn vtt_validate_walls() {
+        let vtt = VttBuilder::new(coordinate(0.0, 0.0), coordinate(8.0, 8.0), 16)
+            // a closed room with a repeated corner
+            .add_wall(vec![
+                coordinate(1.0, 1.0),
+                coordinate(4.0, 1.0),
+                coordinate(4.0, 1.0),
+                coordinate(4.0, 4.0),
+                coordinate(1.0, 4.0),
+                coordinate(1.0, 1.0),
+            ])
+            // a wall that crosses itself
+            .add_wall(vec![
+                coordinate(5.0, 5.0),
+                coordinate(7.0, 5.0),
+                coordinate(6.0, 6.0),
+                coordinate(7.0, 5.0),
+            ])
+            .add_wall(vec![coordinate(9.0, 1.0), coordinate(12.0, 1.0)])
+            .build()
+            .expect("Could not build the vtt");
+        assert_eq!(
+            vtt.line_of_sight[0].len(),
+            5,
+            "Expected the zero length segment to be dropped"
+        );
+        assert_eq!(vtt.rooms().len(), 1);
+        assert_eq!(
+            vtt.validate_walls(),
+            vec![
+                WallIssue::DuplicatePoint { wall: 1, point: 3 },
+                WallIssue::OutsideMap { wall: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn vtt_visible_fraction() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.fow_hide_all();
+        vtt.fow_change_rect(
+            Coordinate { x: 0.0, y: 0.0 },
+            Coordinate { x: 2.25, y: 2.0 },
+            Operation::Show,
+        );
+        assert_eq!(vtt.visible_fraction(Coordinate { x: 1.5, y: 1.5 }), 1.0);
+        let fraction = vtt.visible_fraction(Coordinate { x: 2.9, y: 0.1 });
+        assert!(
+            (fraction - 0.25).abs() < 0.02,
+            "Expected a quarter of the square to be visible, found {}",
+            fraction
+        );
+        assert_eq!(vtt.visible_fraction(Coordinate { x: 5.0, y: 5.0 }), 0.0);
+    }
+
+    #[test]
+    fn vtt_edit_walls() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let (walls, segments, rooms) = (
+            vtt.line_of_sight().len(),
+            vtt.wall_segments(false).len(),
+            vtt.rooms().len(),
+        );
+        let square = vec![
+            Coordinate { x: 0.5, y: 0.5 },
+            Coordinate { x: 1.5, y: 0.5 },
+            Coordinate { x: 1.5, y: 1.5 },
+            Coordinate { x: 0.5, y: 1.5 },
+            Coordinate { x: 0.5, y: 0.5 },
+        ];
+        let index = vtt.add_wall(square.clone());
+        assert_eq!(index, walls);
+        assert_eq!(vtt.wall_segments(false).len(), segments + 4);
+        assert_eq!(
+            vtt.rooms().len(),
+            rooms + 1,
+            "Expected the new room to be found"
+        );
+
+        let moved: Vec<Coordinate> = square
+            .iter()
+            .map(|c| Coordinate {
+                x: c.x + 0.25,
+                y: c.y,
+            })
+            .collect();
+        assert!(vtt.update_wall(index, moved));
+        assert_eq!(vtt.line_of_sight()[index][0].x, 0.75);
+        let path = temp_path("edit_walls.dd2vtt");
+        vtt.save_vtt(&path).expect("Could not save the vtt");
+        let reopened = open_vtt(&path).expect("Could not open the saved vtt");
+        assert_eq!(reopened.line_of_sight().len(), walls + 1);
+        assert_eq!(reopened.line_of_sight()[index][0].x, 0.75);
+
+        assert!(vtt.remove_wall(index));
+        assert!(
+            !vtt.remove_wall(index),
+            "Expected no wall at the removed index"
+        );
+        assert!(!vtt.update_wall(index, square));
+        assert_eq!(vtt.wall_segments(false).len(), segments);
+        assert_eq!(vtt.rooms().len(), rooms);
+    }
+
+    #[test]
+    fn vtt_fow_change_corners() {
+        let mut vtt = open_vtt("tests/resources/example4.dd2vtt")
+            .expect("Could not open file example4.dd2vtt");
+        for (x, y) in [
+            (0.5, 0.5),
+            (26.5, 0.5),
+            (0.5, 14.5),
+            (26.5, 14.5),
+            (26.99, 14.99),
+        ] {
+            assert!(
+                vtt.fow_change(Coordinate { x, y }, Operation::Show, false)
+                    .is_ok(),
+                "Expected a pov in the corner square ({}, {}) to be on the map",
+                x,
+                y
+            );
+        }
+        for (x, y) in [(27.0, 1.0), (1.0, 15.0), (-0.01, 1.0), (f64::NAN, 1.0)] {
+            assert!(
+                vtt.fow_change(Coordinate { x, y }, Operation::Show, true)
+                    .is_err(),
+                "Expected a pov at ({}, {}) to be rejected",
+                x,
+                y
+            );
+        }
+        let shifted = VttBuilder::new(
+            Coordinate { x: 2.0, y: 3.0 },
+            Coordinate { x: 4.0, y: 4.0 },
+            16,
+        )
+        .build()
+        .expect("Could not build the vtt");
+        assert!(shifted
+            .compute_los(Coordinate { x: 5.5, y: 6.5 }, false, true)
+            .is_ok());
+        assert!(shifted
+            .compute_los(Coordinate { x: 1.5, y: 6.5 }, false, true)
+            .is_err());
+    }
+
+    #[test]
+    fn vtt_toggle_doors() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        vtt.close_all_doors();
+        let closed_segments = vtt.wall_segments(false).len();
+        assert!(vtt.portal_segments().iter().all(|(_, closed)| *closed));
+        assert_eq!(vtt.toggle_door(Coordinate { x: 15.0, y: 7.0 }), Some(false));
+        assert_eq!(
+            vtt.wall_segments(false).len(),
+            closed_segments - 1,
+            "Expected toggling to invalidate the cached wall segments"
+        );
+        assert_eq!(vtt.toggle_door(Coordinate { x: 15.0, y: 7.0 }), Some(true));
+        assert_eq!(vtt.toggle_door(Coordinate { x: 0.0, y: 0.0 }), None);
+        vtt.open_all_doors();
+        assert!(vtt.portal_segments().iter().all(|(_, closed)| !*closed));
+        assert_eq!(
+            vtt.wall_segments(false).len(),
+            closed_segments - vtt.portal_segments().len(),
+            "Expected opening all doors to invalidate the cached wall segments"
+        );
+    }
+
+    #[test]
+    fn vtt_edit_lights() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        assert_eq!(vtt.lights().len(), 3, "Expected example1 to have 3 lights");
+        assert_eq!(vtt.lights()[0].color(), "ffeccd8b");
+        assert_eq!(vtt.lights()[0].intensity(), 1.7);
+        let light = vtt.light_mut(0).expect("Expected a light at index 0");
+        light.set_intensity(0.5).set_range(2.0);
+        assert!(
+            light.set_color("blue".to_string()).is_err(),
+            "Expected an invalid color to be rejected"
+        );
+        light
+            .set_color("ff0000ff".to_string())
+            .expect("Could not set the light color");
+        assert!(vtt.light_mut(3).is_none(), "Expected no light at index 3");
+
+        let path = temp_path("edit_lights.dd2vtt");
+        vtt.save_vtt(&path).expect("Could not save the vtt");
+        let reopened = open_vtt(&path).expect("Could not open the saved vtt");
+        let light = &reopened.lights()[0];
+        assert_eq!(
+            (light.intensity(), light.range(), light.color()),
+            (0.5, 2.0, "ff0000ff"),
+            "Light edits did not persist"
+        );
+    }
+
+    #[test]
+    fn vtt_add_remove_light() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let position = Coordinate { x: 1.5, y: 1.5 };
+        assert!(
+            vtt.add_light(position.clone(), 3.0, 1.0, "torch".to_string(), true)
+                .is_err(),
+            "Expected an invalid color to be rejected"
+        );
+        let index = vtt
+            .add_light(position, 3.0, 1.0, "ffff9329".to_string(), true)
+            .expect("Could not add a light");
+        assert_eq!(index, 3, "Expected the new light to be appended");
+
+        let path = temp_path("add_light.dd2vtt");
+        vtt.save_vtt(&path).expect("Could not save the vtt");
+        let mut reopened = open_vtt(&path).expect("Could not open the saved vtt");
+        assert_eq!(reopened.lights().len(), 4, "New light was not saved");
+        assert_eq!(reopened.lights()[3].color(), "ffff9329");
+        assert!(reopened.remove_light(0), "Could not remove light 0");
+        assert!(!reopened.remove_light(3), "Expected no light at index 3");
+        assert_eq!(reopened.lights()[2].color(), "ffff9329");
+    }
+
+    #[test]
+    fn vtt_set_lights() {
+        let mut vtt = open_vtt("tests/resources/example1.dd2vtt")
+            .expect("Could not open file example1.dd2vtt");
+        let day = vtt.lights().to_vec();
+        assert_eq!(vtt.lights_len(), 3);
+        vtt.clear_lights();
+        assert_eq!(vtt.lights_len(), 0, "Expected all lights to be removed");
+        vtt.add_light(
+            Coordinate { x: 1.5, y: 1.5 },
+            3.0,
+            1.0,
+            "ff0000ff".to_string(),
+            true,
+        )
+        .expect("Could not add a light");
+        let night = vtt.lights().to_vec();
+
+        let mut invalid = day.clone();
+        invalid.extend(night.clone());
+        invalid[1] = Light {
+            color: "torch".to_string(),
+            ..invalid[1].clone()
+        };
+        assert!(
+            matches!(
+                vtt.set_lights(invalid),
+                Err(RustVttError::InvalidLightColor { index: 1, .. })
+            ),
+            "Expected an invalid color to be rejected"
+        );
+        assert_eq!(vtt.lights_len(), 1, "Expected the lights to be unchanged");
+
+        vtt.set_lights(day).expect("Could not restore the lights");
+        let path = temp_path("set_lights.dd2vtt");
+        vtt.save_vtt(&path).expect("Could not save the vtt");
+        let mut reopened = open_vtt(&path).expect("Could not open the saved vtt");
+        assert_eq!(reopened.lights_len(), 3, "Restored lights were not saved");
+        reopened
+            .set_lights(night)
+            .expect("Could not swap the lights");
+        assert_eq!(reopened.lights()[0].color(), "ff0000ff");
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn vtt_save_vtt_composited() {
+        let mut vtt = VttBuilder::new(
+            Coordinate { x: 0.0, y: 0.0 },
+            Coordinate { x: 4.0, y: 4.0 },
+            16,
+        )
+        .add_light(
+            Coordinate { x: 3.0, y: 3.0 },
+            1.0,
+            1.0,
+            "ffffffff".to_string(),
+            false,
+        )
+        .build()
+        .expect("Could not build the vtt");
+        vtt.fow_change_rect(
+            Coordinate { x: 0.0, y: 0.0 },
+            Coordinate { x: 2.0, y: 2.0 },
+            Operation::Hide,
+        );
+        let path = temp_path("composited.dd2vtt");
+        vtt.save_vtt_composited(&path, true)
+            .expect("Could not save the vtt");
+        let composited = open_vtt(&path).expect("Could not open the saved vtt");
+        let image = composited
+            .decode_image()
+            .expect("Could not decode the image");
+        assert_eq!(
+            image,
+            &vtt.get_pixbuf().expect("Could not decode the image"),
+            "Expected the lighting and fog of war to be drawn onto the stored image"
+        );
+        assert_eq!(
+            &composited
+                .get_pixbuf_without_fow()
+                .expect("Could not decode the image"),
+            image,
+            "Expected the lighting not to be applied again"
+        );
+        assert_eq!(
+            vtt.decode_image()
+                .expect("Could not decode the image")
+                .get_pixel(48, 48),
+            Rgb([0, 0, 0]),
+            "Expected the image of the vtt to be unchanged"
         );
     }
 
@@ -214,7 +6047,7 @@ mod tests {
     fn vtt_save_img() {
         let vtt = open_vtt("tests/resources/The Pig and Whistle tavern.uvtt")
             .expect("Could not open file the pig and whistle tavern.uvtt");
-        vtt.save_img_raw("tests/resources/tavern.png")
+        vtt.save_img_raw("tests/resources/tavern")
             .expect("Failed to save to png");
     }
 }